@@ -0,0 +1,107 @@
+use rust_edge_gateway_sdk::{prelude::*, handler_loop};
+use a_icon_shared::{
+    admin::AdminService,
+    database::Database,
+    storage::StorageService,
+    utils::sha256_hex,
+    HandlerError,
+};
+use serde::Serialize;
+use std::env;
+
+/// Favicons rehashed per request when no limit is given
+const DEFAULT_BATCH_SIZE: i64 = 100;
+
+/// Upper bound on the batch size, to keep each request short
+const MAX_BATCH_SIZE: i64 = 500;
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct BackfillResponse {
+    processed: usize,
+    updated: usize,
+    missing_source: usize,
+    remaining: i64,
+}
+
+fn handle(req: Request) -> Response {
+    match handle_backfill(&req) {
+        Ok(response) => response,
+        Err(e) => e.to_response(),
+    }
+}
+
+/// Replace legacy MD5 source hashes with SHA-256 hashes of the stored
+/// sources. Call repeatedly until `remaining` is 0.
+fn handle_backfill(req: &Request) -> Result<Response, HandlerError> {
+    // Extract token from Authorization header
+    let token = extract_bearer_token(req)?;
+
+    // Initialize admin service and verify token
+    let admin = AdminService::new()?;
+    if !admin.verify_token(&token) {
+        return Err(HandlerError::Unauthorized("Invalid or expired token".to_string()));
+    }
+
+    let limit: i64 = req.query.get("limit")
+        .and_then(|l| l.parse().ok())
+        .unwrap_or(DEFAULT_BATCH_SIZE)
+        .clamp(1, MAX_BATCH_SIZE);
+
+    // Initialize services
+    let db_path = env::var("DB_PATH")
+        .map_err(|e| HandlerError::InternalError(format!("DB_PATH not set: {}", e)))?;
+    let db = Database::new(&db_path)?;
+
+    // Create tokio runtime for async storage operations
+    let rt = tokio::runtime::Runtime::new()
+        .map_err(|e| HandlerError::InternalError(e.to_string()))?;
+
+    let storage = rt.block_on(async {
+        StorageService::new().await
+            .map_err(|e| HandlerError::StorageError(format!("Failed to initialize storage: {}", e)))
+    })?;
+
+    let favicons = db.list_legacy_hashed_favicons(limit)?;
+    let mut updated = 0;
+    let mut missing_source = 0;
+
+    for favicon in &favicons {
+        let source_key = format!("sources/{}/original", favicon.id);
+
+        match rt.block_on(storage.head_object(&source_key))? {
+            Some(_) => {
+                let data = rt.block_on(storage.get_object(&source_key))?;
+                let hash = sha256_hex(&data);
+                db.update_source_hash(&favicon.id, Some(&hash), Some(data.len() as i64))?;
+                updated += 1;
+            }
+            None => {
+                // The MD5 hash can no longer be verified, so stop trusting it
+                db.update_source_hash(&favicon.id, None, favicon.source_size)?;
+                missing_source += 1;
+            }
+        }
+    }
+
+    let response = BackfillResponse {
+        processed: favicons.len(),
+        updated,
+        missing_source,
+        remaining: db.count_legacy_hashed_favicons()?,
+    };
+
+    Ok(Response::ok(json!(response)))
+}
+
+fn extract_bearer_token(req: &Request) -> Result<String, HandlerError> {
+    let auth_header = req.require_header("Authorization")?;
+
+    if !auth_header.starts_with("Bearer ") {
+        return Err(HandlerError::Unauthorized("Invalid Authorization header format".to_string()));
+    }
+
+    Ok(auth_header[7..].to_string())
+}
+
+handler_loop!(handle);
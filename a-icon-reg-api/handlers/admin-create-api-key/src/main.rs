@@ -0,0 +1,74 @@
+use rust_edge_gateway_sdk::{prelude::*, handler_loop};
+use a_icon_shared::{
+    admin::AdminService,
+    api_keys::{create_api_key, parse_scopes},
+    database::Database,
+    models::ApiKeyResponse,
+    HandlerError,
+};
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use std::env;
+
+#[derive(Deserialize)]
+struct CreateApiKeyRequest {
+    name: String,
+    scopes: Vec<String>,
+    /// RFC 3339 time after which the key is refused; keys without one do not expire
+    #[serde(rename = "expiresAt")]
+    expires_at: Option<String>,
+}
+
+fn handle(req: Request) -> Response {
+    match handle_create(&req) {
+        Ok(response) => response,
+        Err(e) => e.to_response(),
+    }
+}
+
+fn handle_create(req: &Request) -> Result<Response, HandlerError> {
+    // Extract token from Authorization header
+    let token = extract_bearer_token(req)?;
+
+    // Initialize admin service and verify token
+    let admin = AdminService::new()?;
+    if !admin.verify_token(&token) {
+        return Err(HandlerError::Unauthorized("Invalid or expired token".to_string()));
+    }
+
+    // Parse JSON body using SDK helper
+    let create_req: CreateApiKeyRequest = req.json()?;
+    let scopes = parse_scopes(&create_req.scopes)?;
+    let expires_at = create_req.expires_at.as_deref()
+        .map(|value| {
+            DateTime::parse_from_rfc3339(value)
+                .map(|dt| dt.with_timezone(&Utc))
+                .map_err(|_| HandlerError::ValidationError("expiresAt must be an RFC 3339 date-time".to_string()))
+        })
+        .transpose()?;
+
+    // Initialize database
+    let db_path = env::var("DB_PATH")
+        .map_err(|e| HandlerError::InternalError(format!("DB_PATH not set: {}", e)))?;
+    let db = Database::new(&db_path)?;
+
+    let (api_key, key) = create_api_key(&db, &create_req.name, scopes, expires_at, Utc::now())?;
+
+    // The key is only shown now; only its hash is stored
+    let mut response = ApiKeyResponse::from_api_key(api_key);
+    response.key = Some(key);
+
+    Ok(Response::ok(json!(response)))
+}
+
+fn extract_bearer_token(req: &Request) -> Result<String, HandlerError> {
+    let auth_header = req.require_header("Authorization")?;
+
+    if !auth_header.starts_with("Bearer ") {
+        return Err(HandlerError::Unauthorized("Invalid Authorization header format".to_string()));
+    }
+
+    Ok(auth_header[7..].to_string())
+}
+
+handler_loop!(handle);
@@ -0,0 +1,67 @@
+use rust_edge_gateway_sdk::{prelude::*, handler_loop};
+use a_icon_shared::{
+    admin::AdminService,
+    database::Database,
+    models::WebhookResponse,
+    webhooks::{create_webhook, parse_events},
+    HandlerError,
+};
+use chrono::Utc;
+use serde::Deserialize;
+use std::env;
+
+#[derive(Deserialize)]
+struct CreateWebhookRequest {
+    url: String,
+    events: Vec<String>,
+    /// Signing secret; one is generated when omitted
+    secret: Option<String>,
+}
+
+fn handle(req: Request) -> Response {
+    match handle_create(&req) {
+        Ok(response) => response,
+        Err(e) => e.to_response(),
+    }
+}
+
+fn handle_create(req: &Request) -> Result<Response, HandlerError> {
+    // Extract token from Authorization header
+    let token = extract_bearer_token(req)?;
+
+    // Initialize admin service and verify token
+    let admin = AdminService::new()?;
+    if !admin.verify_token(&token) {
+        return Err(HandlerError::Unauthorized("Invalid or expired token".to_string()));
+    }
+
+    // Parse JSON body using SDK helper
+    let create_req: CreateWebhookRequest = req.json()?;
+    let events = parse_events(&create_req.events)?;
+
+    // Initialize database
+    let db_path = env::var("DB_PATH")
+        .map_err(|e| HandlerError::InternalError(format!("DB_PATH not set: {}", e)))?;
+    let db = Database::new(&db_path)?;
+
+    let webhook = create_webhook(&db, &create_req.url, events, create_req.secret, Utc::now())?;
+
+    // The secret is only shown now, for the receiver to check signatures
+    let secret = webhook.secret.clone();
+    let mut response = WebhookResponse::from_webhook(webhook);
+    response.secret = Some(secret);
+
+    Ok(Response::ok(json!(response)))
+}
+
+fn extract_bearer_token(req: &Request) -> Result<String, HandlerError> {
+    let auth_header = req.require_header("Authorization")?;
+
+    if !auth_header.starts_with("Bearer ") {
+        return Err(HandlerError::Unauthorized("Invalid Authorization header format".to_string()));
+    }
+
+    Ok(auth_header[7..].to_string())
+}
+
+handler_loop!(handle);
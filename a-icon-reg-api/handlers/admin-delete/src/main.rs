@@ -1,10 +1,14 @@
 use rust_edge_gateway_sdk::{prelude::*, handler_loop};
 use a_icon_shared::{
     admin::AdminService,
+    api_keys::authorize_api_key,
     database::Database,
+    models::{ApiKeyScope, WebhookEvent},
     storage::StorageService,
+    webhooks::emit_event,
     HandlerError,
 };
+use chrono::Utc;
 use serde::{Deserialize, Serialize};
 use std::env;
 
@@ -34,23 +38,26 @@ fn handle(req: Request) -> Response {
 }
 
 fn handle_delete(req: &Request) -> Result<Response, HandlerError> {
-    // Extract token from Authorization header
-    let token = extract_bearer_token(req)?;
+    // Initialize database
+    let db_path = env::var("DB_PATH")
+        .map_err(|e| HandlerError::InternalError(format!("DB_PATH not set: {}", e)))?;
+    let db = Database::new(&db_path)?;
 
-    // Initialize admin service and verify token
-    let admin = AdminService::new()?;
-    if !admin.verify_token(&token) {
-        return Err(HandlerError::Unauthorized("Invalid or expired token".to_string()));
+    // API clients use a key with the admin:delete scope, admins a session token
+    if authorize_api_key(&db, &req.headers, ApiKeyScope::AdminDelete, Utc::now())?.is_none() {
+        // Extract token from Authorization header
+        let token = extract_bearer_token(req)?;
+
+        // Initialize admin service and verify token
+        let admin = AdminService::new()?;
+        if !admin.verify_token(&token) {
+            return Err(HandlerError::Unauthorized("Invalid or expired token".to_string()));
+        }
     }
 
     // Parse JSON body using SDK helper
     let delete_req: DeleteRequest = req.json()?;
 
-    // Initialize services
-    let db_path = env::var("DB_PATH")
-        .map_err(|e| HandlerError::InternalError(format!("DB_PATH not set: {}", e)))?;
-    let db = Database::new(&db_path)?;
-
     // Create tokio runtime for async storage operations
     let rt = tokio::runtime::Runtime::new()
         .map_err(|e| HandlerError::InternalError(e.to_string()))?;
@@ -105,14 +112,8 @@ async fn delete_favicon(db: &Database, storage: &StorageService, id: &str) -> De
         }
     };
 
-    // Delete source image from storage
-    let source_key = format!("sources/{}/original", id);
-    let _ = storage.delete_object(&source_key).await;
-
-    // Delete all assets from storage
-    for asset in assets {
-        let _ = storage.delete_object(&asset.storage_key).await;
-    }
+    // Delete sources and assets from storage
+    storage.delete_favicon_objects(id, &assets).await;
 
     // Delete assets from database
     if let Err(e) = db.delete_assets_by_favicon_id(id) {
@@ -132,10 +133,15 @@ async fn delete_favicon(db: &Database, storage: &StorageService, id: &str) -> De
         };
     }
 
+    // The favicon is gone either way; report a lost event without failing
+    let error = emit_event(db, WebhookEvent::FaviconDeleted, &favicon, Utc::now())
+        .err()
+        .map(|e| format!("Deleted, but the webhook event was not queued: {}", e));
+
     DeleteResult {
         id: id.to_string(),
         success: true,
-        error: None,
+        error,
     }
 }
 
@@ -0,0 +1,50 @@
+use rust_edge_gateway_sdk::{prelude::*, handler_loop};
+use a_icon_shared::{
+    admin::AdminService,
+    database::Database,
+    webhooks::{deliver_due, DISPATCH_LIMIT},
+    HandlerError,
+};
+use chrono::Utc;
+use std::env;
+
+fn handle(req: Request) -> Response {
+    match handle_dispatch(&req) {
+        Ok(response) => response,
+        Err(e) => e.to_response(),
+    }
+}
+
+fn handle_dispatch(req: &Request) -> Result<Response, HandlerError> {
+    // Extract token from Authorization header
+    let token = extract_bearer_token(req)?;
+
+    // Initialize admin service and verify token
+    let admin = AdminService::new()?;
+    if !admin.verify_token(&token) {
+        return Err(HandlerError::Unauthorized("Invalid or expired token".to_string()));
+    }
+
+    // Initialize database
+    let db_path = env::var("DB_PATH")
+        .map_err(|e| HandlerError::InternalError(format!("DB_PATH not set: {}", e)))?;
+    let db = Database::new(&db_path)?;
+
+    // Attempt deliveries that are due, including retries; call again while
+    // `attempted` equals the limit to drain a backlog
+    let summary = deliver_due(&db, Utc::now(), DISPATCH_LIMIT)?;
+
+    Ok(Response::ok(json!(summary)))
+}
+
+fn extract_bearer_token(req: &Request) -> Result<String, HandlerError> {
+    let auth_header = req.require_header("Authorization")?;
+
+    if !auth_header.starts_with("Bearer ") {
+        return Err(HandlerError::Unauthorized("Invalid Authorization header format".to_string()));
+    }
+
+    Ok(auth_header[7..].to_string())
+}
+
+handler_loop!(handle);
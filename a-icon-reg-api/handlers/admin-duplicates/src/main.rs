@@ -0,0 +1,92 @@
+use rust_edge_gateway_sdk::{prelude::*, handler_loop};
+use a_icon_shared::{
+    admin::AdminService,
+    database::Database,
+    models::SimilarFavicon,
+    similarity::{cluster, hamming_distance, DEFAULT_MAX_DISTANCE, MAX_DISTANCE_LIMIT},
+    HandlerError,
+};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::env;
+
+#[derive(Serialize)]
+struct DuplicateCluster {
+    /// Oldest favicon first; distances are measured from it
+    favicons: Vec<SimilarFavicon>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct DuplicatesResponse {
+    max_distance: u32,
+    clusters: Vec<DuplicateCluster>,
+}
+
+fn handle(req: Request) -> Response {
+    match handle_duplicates(&req) {
+        Ok(response) => response,
+        Err(e) => e.to_response(),
+    }
+}
+
+fn handle_duplicates(req: &Request) -> Result<Response, HandlerError> {
+    // Extract token from Authorization header
+    let token = extract_bearer_token(req)?;
+
+    // Initialize admin service and verify token
+    let admin = AdminService::new()?;
+    if !admin.verify_token(&token) {
+        return Err(HandlerError::Unauthorized("Invalid or expired token".to_string()));
+    }
+
+    let max_distance: u32 = match req.query.get("maxDistance") {
+        Some(value) => value.parse()
+            .map_err(|_| HandlerError::ValidationError("maxDistance must be a number".to_string()))?,
+        None => DEFAULT_MAX_DISTANCE,
+    };
+    if max_distance > MAX_DISTANCE_LIMIT {
+        return Err(HandlerError::ValidationError(format!(
+            "maxDistance must be at most {}",
+            MAX_DISTANCE_LIMIT
+        )));
+    }
+
+    // Initialize services
+    let db_path = env::var("DB_PATH")
+        .map_err(|e| HandlerError::InternalError(format!("DB_PATH not set: {}", e)))?;
+    let db = Database::new(&db_path)?;
+
+    let hashes = db.list_perceptual_hashes()?;
+    let hash_by_id: HashMap<&str, u64> = hashes.iter().map(|(id, hash)| (id.as_str(), *hash)).collect();
+
+    let mut clusters = Vec::new();
+    for ids in cluster(&hashes, max_distance) {
+        let first_hash = hash_by_id[ids[0].as_str()];
+
+        let mut favicons = Vec::with_capacity(ids.len());
+        for id in &ids {
+            if let Some(favicon) = db.get_favicon_by_id(id)? {
+                let distance = hamming_distance(first_hash, hash_by_id[id.as_str()]);
+                favicons.push(SimilarFavicon::from_favicon(favicon, distance));
+            }
+        }
+        clusters.push(DuplicateCluster { favicons });
+    }
+
+    let response = DuplicatesResponse { max_distance, clusters };
+
+    Ok(Response::ok(json!(response)))
+}
+
+fn extract_bearer_token(req: &Request) -> Result<String, HandlerError> {
+    let auth_header = req.require_header("Authorization")?;
+
+    if !auth_header.starts_with("Bearer ") {
+        return Err(HandlerError::Unauthorized("Invalid Authorization header format".to_string()));
+    }
+
+    Ok(auth_header[7..].to_string())
+}
+
+handler_loop!(handle);
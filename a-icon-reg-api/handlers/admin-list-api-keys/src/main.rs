@@ -0,0 +1,60 @@
+use rust_edge_gateway_sdk::{prelude::*, handler_loop};
+use a_icon_shared::{
+    admin::AdminService,
+    database::Database,
+    models::ApiKeyResponse,
+    HandlerError,
+};
+use std::collections::HashMap;
+use std::env;
+
+fn handle(req: Request) -> Response {
+    match handle_list(&req) {
+        Ok(response) => response,
+        Err(e) => e.to_response(),
+    }
+}
+
+fn handle_list(req: &Request) -> Result<Response, HandlerError> {
+    // Extract token from Authorization header
+    let token = extract_bearer_token(req)?;
+
+    // Initialize admin service and verify token
+    let admin = AdminService::new()?;
+    if !admin.verify_token(&token) {
+        return Err(HandlerError::Unauthorized("Invalid or expired token".to_string()));
+    }
+
+    // Initialize database
+    let db_path = env::var("DB_PATH")
+        .map_err(|e| HandlerError::InternalError(format!("DB_PATH not set: {}", e)))?;
+    let db = Database::new(&db_path)?;
+
+    // Newest first, with how many favicons each key created
+    let counts: HashMap<String, i64> = db.count_favicons_by_api_key()?.into_iter().collect();
+    let keys: Vec<ApiKeyResponse> = db.list_api_keys()?
+        .into_iter()
+        .map(|api_key| {
+            let favicon_count = counts.get(&api_key.id).copied().unwrap_or(0);
+            let mut response = ApiKeyResponse::from_api_key(api_key);
+            response.favicon_count = Some(favicon_count);
+            response
+        })
+        .collect();
+
+    Ok(Response::ok(json!({
+        "keys": keys,
+    })))
+}
+
+fn extract_bearer_token(req: &Request) -> Result<String, HandlerError> {
+    let auth_header = req.require_header("Authorization")?;
+
+    if !auth_header.starts_with("Bearer ") {
+        return Err(HandlerError::Unauthorized("Invalid Authorization header format".to_string()));
+    }
+
+    Ok(auth_header[7..].to_string())
+}
+
+handler_loop!(handle);
@@ -0,0 +1,56 @@
+use rust_edge_gateway_sdk::{prelude::*, handler_loop};
+use a_icon_shared::{
+    admin::AdminService,
+    database::Database,
+    models::WebhookResponse,
+    HandlerError,
+};
+use std::env;
+
+fn handle(req: Request) -> Response {
+    match handle_list(&req) {
+        Ok(response) => response,
+        Err(e) => e.to_response(),
+    }
+}
+
+fn handle_list(req: &Request) -> Result<Response, HandlerError> {
+    // Extract token from Authorization header
+    let token = extract_bearer_token(req)?;
+
+    // Initialize admin service and verify token
+    let admin = AdminService::new()?;
+    if !admin.verify_token(&token) {
+        return Err(HandlerError::Unauthorized("Invalid or expired token".to_string()));
+    }
+
+    // Initialize database
+    let db_path = env::var("DB_PATH")
+        .map_err(|e| HandlerError::InternalError(format!("DB_PATH not set: {}", e)))?;
+    let db = Database::new(&db_path)?;
+
+    // Oldest first, with the state of each webhook's outbox
+    let mut webhooks = Vec::new();
+    for webhook in db.list_webhooks()? {
+        let deliveries = db.get_webhook_delivery_counts(&webhook.id)?;
+        let mut response = WebhookResponse::from_webhook(webhook);
+        response.deliveries = Some(deliveries);
+        webhooks.push(response);
+    }
+
+    Ok(Response::ok(json!({
+        "webhooks": webhooks,
+    })))
+}
+
+fn extract_bearer_token(req: &Request) -> Result<String, HandlerError> {
+    let auth_header = req.require_header("Authorization")?;
+
+    if !auth_header.starts_with("Bearer ") {
+        return Err(HandlerError::Unauthorized("Invalid Authorization header format".to_string()));
+    }
+
+    Ok(auth_header[7..].to_string())
+}
+
+handler_loop!(handle);
@@ -0,0 +1,104 @@
+use rust_edge_gateway_sdk::{prelude::*, handler_loop};
+use a_icon_shared::{
+    database::Database,
+    models::{asset_url, AssetType, AssetVariant, FaviconDetailResponse},
+    domain_lookup::find_domain_favicon,
+    resize::RenderSpec,
+    validation::{display_domain, validate_domain},
+    HandlerError,
+};
+use std::env;
+
+/// Lookups are cached briefly, since a newer or newly verified favicon can
+/// take over a domain at any time
+const LOOKUP_CACHE_SECONDS: u32 = 300;
+
+/// What the caller asked for: the favicon's details, or a redirect to one file
+enum Target {
+    Details,
+    Rendition(RenderSpec),
+    Stored(AssetType),
+}
+
+fn handle(req: Request) -> Response {
+    match handle_domain_favicon(&req) {
+        Ok(response) => response,
+        Err(e) => e.to_response(),
+    }
+}
+
+fn handle_domain_favicon(req: &Request) -> Result<Response, HandlerError> {
+    // Extract domain from path parameter, in the ASCII form favicons are stored under
+    let domain = req.path_param("domain")
+        .ok_or_else(|| HandlerError::BadRequest("Missing domain parameter".to_string()))?;
+    let domain = validate_domain(domain)?;
+
+    let target = parse_target(req)?;
+
+    // Initialize database
+    let db_path = env::var("DB_PATH")
+        .map_err(|e| HandlerError::InternalError(format!("DB_PATH not set: {}", e)))?;
+    let db = Database::new(&db_path)?;
+
+    // Find the favicon for the domain or the closest parent domain with one
+    let (favicon, matched_domain) = find_domain_favicon(&db, &domain)?
+        .ok_or_else(|| HandlerError::NotFoundMessage(format!("No favicon found for {}", display_domain(&domain))))?;
+
+    match target {
+        Target::Rendition(spec) => Ok(redirect(format!(
+            "/api/favicons/{}/icon?size={}&format={}",
+            favicon.slug,
+            spec.size_label(),
+            spec.format.extension()
+        ))),
+        Target::Stored(r#type) => {
+            let assets = db.get_assets_by_favicon_id(&favicon.id)?;
+            let asset = assets
+                .iter()
+                .find(|asset| asset.r#type == r#type && asset.variant == AssetVariant::Light)
+                .ok_or_else(|| HandlerError::NotFoundMessage(format!("Favicon has no {} file", r#type.as_str())))?;
+            Ok(redirect(asset_url(&asset.storage_key)))
+        }
+        Target::Details => {
+            let assets = db.get_assets_by_favicon_id(&favicon.id)?;
+            let response = FaviconDetailResponse::from_favicon_and_assets(favicon, assets);
+
+            Ok(Response::ok(json!({
+                "domain": display_domain(&domain),
+                "matchedDomain": display_domain(&matched_domain),
+                "favicon": response,
+            }))
+            .with_cache(LOOKUP_CACHE_SECONDS))
+        }
+    }
+}
+
+/// `size` (with an optional `format` of png or webp) redirects to a
+/// rendition; `format` svg or ico alone redirects to the stored file
+fn parse_target(req: &Request) -> Result<Target, HandlerError> {
+    let format = req.query.get("format").map(|format| format.to_ascii_lowercase());
+
+    if let Some(size) = req.query.get("size") {
+        return Ok(Target::Rendition(RenderSpec::parse(size, format.as_deref())?));
+    }
+
+    match format.as_deref() {
+        None => Ok(Target::Details),
+        Some("svg") => Ok(Target::Stored(AssetType::Svg)),
+        Some("ico") => Ok(Target::Stored(AssetType::Ico)),
+        Some("png") | Some("webp") => Err(HandlerError::ValidationError(
+            "size is required for png and webp".to_string(),
+        )),
+        Some(_) => Err(HandlerError::ValidationError(
+            "format must be 'png', 'webp', 'svg' or 'ico'".to_string(),
+        )),
+    }
+}
+
+fn redirect(location: String) -> Response {
+    Response::new(302)
+        .with_header("Location", location)
+        .with_cache(LOOKUP_CACHE_SECONDS)
+}
+
+handler_loop!(handle);
@@ -0,0 +1,74 @@
+use rust_edge_gateway_sdk::{prelude::*, handler_loop};
+use a_icon_shared::{
+    bundle::{build_bundle, bundle_cache_key, bundled_assets},
+    database::Database,
+    ownership::find_readable_favicon,
+    storage::StorageService,
+    HandlerError,
+};
+use std::env;
+
+/// The bundle URL stays the same when a favicon is regenerated, so clients
+/// only cache it briefly; the stored archive is keyed by asset set instead.
+const BUNDLE_CACHE_SECONDS: u32 = 3600;
+
+fn handle(req: Request) -> Response {
+    match handle_bundle(&req) {
+        Ok(response) => response,
+        Err(e) => e.to_response(),
+    }
+}
+
+fn handle_bundle(req: &Request) -> Result<Response, HandlerError> {
+    // Extract slug from path parameter
+    let slug = req.path_param("slug")
+        .ok_or_else(|| HandlerError::BadRequest("Missing slug parameter".to_string()))?;
+
+    // Initialize database
+    let db_path = env::var("DB_PATH")
+        .map_err(|e| HandlerError::InternalError(format!("DB_PATH not set: {}", e)))?;
+    let db = Database::new(&db_path)?;
+
+    // Get favicon by slug; unpublished favicons look missing to everyone
+    // but their owner and API keys allowed to read them
+    let favicon = find_readable_favicon(&db, &req.headers, slug)?;
+
+    let assets = db.get_assets_by_favicon_id(&favicon.id)?;
+    let files = bundled_assets(&assets);
+    if files.is_empty() {
+        return Err(HandlerError::NotFoundMessage(format!("No assets generated for favicon: {}", slug)));
+    }
+
+    // Create tokio runtime for async storage operations
+    let rt = tokio::runtime::Runtime::new()
+        .map_err(|e| HandlerError::InternalError(e.to_string()))?;
+
+    // Initialize storage service
+    let storage = rt.block_on(async {
+        StorageService::new().await
+            .map_err(|e| HandlerError::StorageError(format!("Failed to initialize storage: {}", e)))
+    })?;
+
+    // Serve the cached archive if this asset set has been bundled before
+    let cache_key = bundle_cache_key(&favicon.id, &assets);
+    let data = if rt.block_on(storage.head_object(&cache_key))?.is_some() {
+        rt.block_on(storage.get_object(&cache_key))?
+    } else {
+        let mut contents = Vec::with_capacity(files.len());
+        for (asset, filename) in files {
+            contents.push((filename, rt.block_on(storage.get_object(&asset.storage_key))?));
+        }
+
+        let data = build_bundle(&favicon, &contents)?;
+
+        // Caching is best effort; the archive is returned either way
+        let _ = rt.block_on(storage.upload_object(&cache_key, data.clone(), "application/zip"));
+        data
+    };
+
+    Ok(Response::binary(200, data, "application/zip")
+        .with_header("Content-Disposition", format!("attachment; filename=\"{}-favicons.zip\"", favicon.slug))
+        .with_cache(BUNDLE_CACHE_SECONDS))
+}
+
+handler_loop!(handle);
@@ -1,66 +1,112 @@
 use rust_edge_gateway_sdk::{prelude::*, handler_loop};
 use a_icon_shared::{
+    api_keys::authorize_api_key,
+    creation::{create_favicon, FaviconFields, NewFavicon, SourceOrigin},
     database::Database,
     storage::StorageService,
-    validation::{validate_domain, validate_metadata, validate_file_size, validate_image_type},
-    models::{Favicon, SourceType, GenerationStatus, FaviconDetailResponse},
+    rate_limit::with_rate_limit,
+    validation::{validate_file_size, validate_image_content, validate_generation_options, validate_canvas_document},
+    models::{SourceType, ApiKeyScope},
     HandlerError,
-    utils::generate_short_id,
 };
 use chrono::Utc;
-use uuid::Uuid;
 use serde::Deserialize;
 use std::env;
 
 #[derive(Deserialize)]
 struct CanvasRequest {
     #[serde(rename = "dataUrl")]
-    data_url: String,
+    data_url: Option<String>,
+    /// Editable canvas document, rendered server-side instead of `dataUrl`
+    document: Option<serde_json::Value>,
     title: Option<String>,
     #[serde(rename = "targetDomain")]
     target_domain: Option<String>,
     metadata: Option<String>,
+    #[serde(rename = "themeColor")]
+    theme_color: Option<String>,
+    #[serde(rename = "backgroundColor")]
+    background_color: Option<String>,
+    #[serde(rename = "generationOptions")]
+    generation_options: Option<serde_json::Value>,
+    #[serde(rename = "darkDataUrl")]
+    dark_data_url: Option<String>,
 }
 
 fn handle(req: Request) -> Response {
-    match handle_canvas(&req) {
+    with_rate_limit(&req, "canvas", || match handle_canvas(&req) {
         Ok(response) => response,
         Err(e) => e.to_response(),
-    }
+    })
 }
 
 fn handle_canvas(req: &Request) -> Result<Response, HandlerError> {
     // Parse JSON body using SDK helper
     let canvas_req: CanvasRequest = req.json()?;
 
-    // Parse data URL
-    let (mime_type, image_data) = parse_data_url(&canvas_req.data_url)?;
+    // Take the bitmap from the data URL, or render the canvas document to an SVG
+    let (image_data, document) = match (canvas_req.data_url.as_deref(), canvas_req.document.as_ref()) {
+        (Some(data_url), None) => {
+            let (_declared_mime, data) = parse_data_url(data_url)?;
+            (data, None)
+        }
+        (None, Some(value)) => {
+            let document = validate_canvas_document(value)?;
+            let document_json = serde_json::to_vec(&document)
+                .map_err(|e| HandlerError::InternalError(format!("Failed to serialize canvas document: {}", e)))?;
+            validate_file_size(document_json.len())?;
+            (document.to_svg(), Some(document_json))
+        }
+        (Some(_), Some(_)) => {
+            return Err(HandlerError::ValidationError("Provide either dataUrl or document, not both".to_string()));
+        }
+        (None, None) => {
+            return Err(HandlerError::ValidationError("Either dataUrl or document is required".to_string()));
+        }
+    };
 
     // Validate file size
     validate_file_size(image_data.len())?;
 
-    // Validate image type
-    validate_image_type(&image_data)?;
-
-    // Validate domain if provided
-    if let Some(ref domain) = canvas_req.target_domain {
-        validate_domain(domain)?;
-    }
-
-    // Validate metadata if provided
-    if let Some(ref meta) = canvas_req.metadata {
-        validate_metadata(meta)?;
-    }
+    // Decode the image to reject corrupt, truncated or oversized files
+    let image_info = validate_image_content(&image_data)?;
 
-    // Calculate hash and size for duplicate detection
-    let source_hash = format!("{:x}", md5::compute(&image_data));
-    let source_size = image_data.len() as i64;
+    // Validate the descriptive fields, keeping the domain's ASCII form
+    let fields = FaviconFields {
+        title: canvas_req.title,
+        target_domain: canvas_req.target_domain,
+        metadata: canvas_req.metadata,
+        theme_color: canvas_req.theme_color,
+        background_color: canvas_req.background_color,
+    }.validate()?;
+
+    // Validate generation options if provided
+    let generation_options = canvas_req.generation_options.as_ref().map(validate_generation_options).transpose()?;
+
+    // Validate the dark-mode source if provided
+    let dark_source = match canvas_req.dark_data_url.as_deref() {
+        Some(dark_data_url) => {
+            if generation_options.as_ref().is_some_and(|options| options.dark_variant.is_some()) {
+                return Err(HandlerError::ValidationError(
+                    "Provide either darkDataUrl or generationOptions.darkVariant, not both".to_string(),
+                ));
+            }
+            let (_declared_mime, data) = parse_data_url(dark_data_url)?;
+            validate_file_size(data.len())?;
+            let info = validate_image_content(&data)?;
+            Some((data, info.mime_type))
+        }
+        None => None,
+    };
 
     // Initialize services
     let db_path = env::var("DB_PATH")
         .map_err(|e| HandlerError::InternalError(format!("DB_PATH not set: {}", e)))?;
     let db = Database::new(&db_path)?;
 
+    // A presented API key must allow creating favicons, which are attributed to it
+    let api_key = authorize_api_key(&db, &req.headers, ApiKeyScope::FaviconsCreate, Utc::now())?;
+
     // Create tokio runtime for async storage operations
     let rt = tokio::runtime::Runtime::new()
         .map_err(|e| HandlerError::InternalError(e.to_string()))?;
@@ -70,54 +116,18 @@ fn handle_canvas(req: &Request) -> Result<Response, HandlerError> {
             .map_err(|e| HandlerError::StorageError(format!("Failed to initialize storage: {}", e)))
     })?;
 
-    // Check for duplicate
-    if let Some(existing) = db.find_duplicate(&source_hash, source_size)? {
-        // Return existing favicon details
-        let assets = db.get_assets_by_favicon_id(&existing.id)?;
-        let response = FaviconDetailResponse::from_favicon_and_assets(existing, assets);
-        return Ok(Response::ok(json!(response)));
-    }
-
-    // Generate IDs
-    let id = Uuid::new_v4().to_string();
-    let slug = generate_short_id(10);
-    let now = Utc::now();
-
-    // Store source image
-    let source_key = format!("sources/{}/original", id);
-    rt.block_on(async {
-        storage.upload_object(&source_key, image_data, &mime_type).await
-    })?;
-
-    // Create favicon record
-    let has_metadata = canvas_req.metadata.as_ref().map(|m| !m.trim().is_empty()).unwrap_or(false);
-    let favicon = Favicon {
-        id: id.clone(),
-        slug: slug.clone(),
-        title: canvas_req.title,
-        target_domain: canvas_req.target_domain,
-        published_url: format!("/f/{}", slug),
-        canonical_svg_key: None,
+    let response = rt.block_on(create_favicon(&db, &storage, NewFavicon {
+        fields,
         source_type: SourceType::Canvas,
-        source_original_mime: Some(mime_type),
-        source_hash: Some(source_hash),
-        source_size: Some(source_size),
-        is_published: true,
-        created_at: now,
-        updated_at: now,
-        generated_at: None,
-        generation_status: GenerationStatus::Pending,
-        generation_error: None,
-        metadata: if has_metadata { canvas_req.metadata } else { None },
-        has_steganography: false,
-    };
-
-    db.insert_favicon(&favicon)?;
-
-    // TODO: Generate favicon assets asynchronously
-
-    let assets = db.get_assets_by_favicon_id(&id)?;
-    let response = FaviconDetailResponse::from_favicon_and_assets(favicon, assets);
+        source: image_data,
+        source_info: image_info,
+        dark_source,
+        document,
+        generation_options,
+        origin: SourceOrigin::Request,
+        api_key_id: api_key.map(|api_key| api_key.id),
+        include_similar: req.query.get("includeSimilar").is_some_and(|v| v == "true"),
+    }))?;
 
     Ok(Response::ok(json!(response)))
 }
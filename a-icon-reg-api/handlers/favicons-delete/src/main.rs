@@ -0,0 +1,77 @@
+use rust_edge_gateway_sdk::{prelude::*, handler_loop};
+use a_icon_shared::{
+    database::Database,
+    storage::StorageService,
+    ownership::verify_edit_token,
+    models::WebhookEvent,
+    webhooks::emit_event,
+    HandlerError,
+};
+use chrono::Utc;
+use std::env;
+
+fn handle(req: Request) -> Response {
+    match handle_delete(&req) {
+        Ok(response) => response,
+        Err(e) => e.to_response(),
+    }
+}
+
+fn handle_delete(req: &Request) -> Result<Response, HandlerError> {
+    // Extract edit token from Authorization header
+    let token = extract_bearer_token(req)?;
+
+    // Extract slug from path parameter
+    let slug = req.path_param("slug")
+        .ok_or_else(|| HandlerError::BadRequest("Missing slug parameter".to_string()))?;
+
+    // Initialize database
+    let db_path = env::var("DB_PATH")
+        .map_err(|e| HandlerError::InternalError(format!("DB_PATH not set: {}", e)))?;
+    let db = Database::new(&db_path)?;
+
+    // Get favicon by slug and check the token against it
+    let favicon = db.get_favicon_by_slug(slug)?
+        .ok_or_else(|| HandlerError::NotFoundMessage(format!("Favicon not found: {}", slug)))?;
+    verify_edit_token(&db, &favicon.id, &token)?;
+
+    let assets = db.get_assets_by_favicon_id(&favicon.id)?;
+
+    // Create tokio runtime for async storage operations
+    let rt = tokio::runtime::Runtime::new()
+        .map_err(|e| HandlerError::InternalError(e.to_string()))?;
+
+    rt.block_on(async {
+        let storage = StorageService::new().await
+            .map_err(|e| HandlerError::StorageError(format!("Failed to initialize storage: {}", e)))?;
+        storage.delete_favicon_objects(&favicon.id, &assets).await;
+        Ok::<(), HandlerError>(())
+    })?;
+
+    // Delete assets, the favicon and its edit token from the database
+    db.delete_assets_by_favicon_id(&favicon.id)?;
+    db.delete_favicon(&favicon.id)?;
+
+    // The favicon is gone either way; report a lost event without failing
+    let mut response = json!({
+        "id": favicon.id,
+        "deleted": true,
+    });
+    if let Err(e) = emit_event(&db, WebhookEvent::FaviconDeleted, &favicon, Utc::now()) {
+        response["error"] = json!(format!("Deleted, but the webhook event was not queued: {}", e));
+    }
+
+    Ok(Response::ok(response))
+}
+
+fn extract_bearer_token(req: &Request) -> Result<String, HandlerError> {
+    let auth_header = req.require_header("Authorization")?;
+
+    if !auth_header.starts_with("Bearer ") {
+        return Err(HandlerError::Unauthorized("Invalid Authorization header format".to_string()));
+    }
+
+    Ok(auth_header[7..].to_string())
+}
+
+handler_loop!(handle);
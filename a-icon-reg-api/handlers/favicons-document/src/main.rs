@@ -0,0 +1,56 @@
+use rust_edge_gateway_sdk::{prelude::*, handler_loop};
+use a_icon_shared::{
+    database::Database,
+    ownership::find_readable_favicon,
+    storage::StorageService,
+    HandlerError,
+};
+use std::env;
+
+fn handle(req: Request) -> Response {
+    match handle_document(&req) {
+        Ok(response) => response,
+        Err(e) => e.to_response(),
+    }
+}
+
+fn handle_document(req: &Request) -> Result<Response, HandlerError> {
+    // Extract slug from path parameter
+    let slug = req.path_param("slug")
+        .ok_or_else(|| HandlerError::BadRequest("Missing slug parameter".to_string()))?;
+
+    // Initialize database
+    let db_path = env::var("DB_PATH")
+        .map_err(|e| HandlerError::InternalError(format!("DB_PATH not set: {}", e)))?;
+    let db = Database::new(&db_path)?;
+
+    // Get favicon by slug; unpublished favicons look missing to everyone
+    // but their owner and API keys allowed to read them
+    let favicon = find_readable_favicon(&db, &req.headers, slug)?;
+
+    // Only favicons drawn from a canvas document can be reopened for editing
+    if !favicon.has_canvas_document {
+        return Err(HandlerError::NotFoundMessage(format!("Favicon {} has no canvas document", slug)));
+    }
+
+    // Create tokio runtime for async storage operations
+    let rt = tokio::runtime::Runtime::new()
+        .map_err(|e| HandlerError::InternalError(e.to_string()))?;
+
+    let storage = rt.block_on(async {
+        StorageService::new().await
+            .map_err(|e| HandlerError::StorageError(format!("Failed to initialize storage: {}", e)))
+    })?;
+
+    let document_key = format!("sources/{}/document", favicon.id);
+    let data = rt.block_on(async {
+        storage.get_object(&document_key).await
+    })?;
+
+    let document: serde_json::Value = serde_json::from_slice(&data)
+        .map_err(|e| HandlerError::InternalError(format!("Stored canvas document is invalid: {}", e)))?;
+
+    Ok(Response::ok(document))
+}
+
+handler_loop!(handle);
@@ -0,0 +1,63 @@
+use rust_edge_gateway_sdk::{prelude::*, handler_loop};
+use a_icon_shared::{
+    database::Database,
+    models::DomainChallengeResponse,
+    ownership::verify_edit_token,
+    domain_verification::{issue_challenge, txt_record_value, well_known_url},
+    HandlerError,
+};
+use std::env;
+
+fn handle(req: Request) -> Response {
+    match handle_domain_challenge(&req) {
+        Ok(response) => response,
+        Err(e) => e.to_response(),
+    }
+}
+
+fn handle_domain_challenge(req: &Request) -> Result<Response, HandlerError> {
+    // Extract edit token from Authorization header
+    let token = extract_bearer_token(req)?;
+
+    // Extract slug from path parameter
+    let slug = req.path_param("slug")
+        .ok_or_else(|| HandlerError::BadRequest("Missing slug parameter".to_string()))?;
+
+    // Initialize database
+    let db_path = env::var("DB_PATH")
+        .map_err(|e| HandlerError::InternalError(format!("DB_PATH not set: {}", e)))?;
+    let db = Database::new(&db_path)?;
+
+    // Get favicon by slug and check the token against it
+    let favicon = db.get_favicon_by_slug(slug)?
+        .ok_or_else(|| HandlerError::NotFoundMessage(format!("Favicon not found: {}", slug)))?;
+    verify_edit_token(&db, &favicon.id, &token)?;
+
+    // Reuses the pending challenge unless the target domain changed
+    let challenge = issue_challenge(&db, &favicon)?;
+
+    let response = DomainChallengeResponse {
+        dns_record_name: challenge.domain.clone(),
+        dns_record_type: "TXT".to_string(),
+        dns_record_value: txt_record_value(&challenge),
+        well_known_url: well_known_url(&challenge),
+        created_at: challenge.created_at.to_rfc3339(),
+        verify_url: format!("/api/favicons/{}/verify-domain", favicon.slug),
+        domain: challenge.domain,
+        token: challenge.token,
+    };
+
+    Ok(Response::ok(json!(response)))
+}
+
+fn extract_bearer_token(req: &Request) -> Result<String, HandlerError> {
+    let auth_header = req.require_header("Authorization")?;
+
+    if !auth_header.starts_with("Bearer ") {
+        return Err(HandlerError::Unauthorized("Invalid Authorization header format".to_string()));
+    }
+
+    Ok(auth_header[7..].to_string())
+}
+
+handler_loop!(handle);
@@ -0,0 +1,130 @@
+use rust_edge_gateway_sdk::{prelude::*, handler_loop};
+use a_icon_shared::{
+    creation::{create_favicon, FaviconFields, NewFavicon, SourceOrigin},
+    database::Database,
+    storage::StorageService,
+    validation::{validate_crop, validate_direct_upload_size, validate_frame_index, validate_image_content, ImageInfo},
+    models::{SourceType, FaviconDetailResponse, UploadIntent},
+    ownership::presents_upload_secret,
+    rate_limit::with_rate_limit,
+    HandlerError,
+};
+use chrono::Utc;
+use std::env;
+
+fn handle(req: Request) -> Response {
+    with_rate_limit(&req, "finalize", || match handle_finalize(&req) {
+        Ok(response) => response,
+        Err(e) => e.to_response(),
+    })
+}
+
+fn handle_finalize(req: &Request) -> Result<Response, HandlerError> {
+    // Extract the pending favicon id from path parameter
+    let id = req.path_param("id")
+        .ok_or_else(|| HandlerError::BadRequest("Missing id parameter".to_string()))?;
+
+    // Initialize services
+    let db_path = env::var("DB_PATH")
+        .map_err(|e| HandlerError::InternalError(format!("DB_PATH not set: {}", e)))?;
+    let db = Database::new(&db_path)?;
+
+    // Intents belong to the client given their upload secret; to others
+    // they do not exist
+    let intent = db.get_upload_intent(id)?
+        .filter(|intent| presents_upload_secret(&req.headers, intent))
+        .ok_or_else(|| HandlerError::NotFoundMessage(format!("Upload intent not found: {}", id)))?;
+
+    // Finalizing twice returns the favicon the intent was finalized into
+    if let Some(favicon_id) = &intent.favicon_id {
+        let existing = db.get_favicon_by_id(favicon_id)?
+            .ok_or_else(|| HandlerError::NotFoundMessage(format!("Upload intent not found: {}", id)))?;
+        let assets = db.get_assets_by_favicon_id(&existing.id)?;
+        let response = FaviconDetailResponse::from_favicon_and_assets(existing, assets);
+        return Ok(Response::ok(json!(response)));
+    }
+
+    if intent.expires_at < Utc::now() {
+        return Err(HandlerError::ValidationError("Upload intent has expired".to_string()));
+    }
+
+    // Create tokio runtime for async storage operations
+    let rt = tokio::runtime::Runtime::new()
+        .map_err(|e| HandlerError::InternalError(e.to_string()))?;
+
+    let storage = rt.block_on(async {
+        StorageService::new().await
+            .map_err(|e| HandlerError::StorageError(format!("Failed to initialize storage: {}", e)))
+    })?;
+
+    // An upload that fails validation cannot be fixed by finalizing again,
+    // so it is discarded along with its intent
+    let (data, image_info) = match load_upload(&rt, &storage, &intent) {
+        Ok(upload) => upload,
+        Err(e) => {
+            if matches!(e, HandlerError::ValidationError(_)) {
+                rt.block_on(async {
+                    storage.delete_object(&intent.staging_key).await
+                })?;
+                db.delete_upload_intent(&intent.id)?;
+            }
+            return Err(e);
+        }
+    };
+
+    // The intent's fields were validated when it was created
+    let fields = FaviconFields {
+        title: intent.title.clone(),
+        target_domain: intent.target_domain.clone(),
+        metadata: intent.metadata.clone(),
+        theme_color: intent.theme_color.clone(),
+        background_color: intent.background_color.clone(),
+    };
+    let api_key_id = intent.api_key_id.clone();
+    let generation_options = intent.generation_options.clone();
+
+    // The staged upload is moved to its permanent source key
+    let response = rt.block_on(create_favicon(&db, &storage, NewFavicon {
+        fields,
+        source_type: SourceType::Upload,
+        source: data,
+        source_info: image_info,
+        dark_source: None,
+        document: None,
+        generation_options,
+        origin: SourceOrigin::UploadIntent(Box::new(intent)),
+        api_key_id,
+        include_similar: req.query.get("includeSimilar").is_some_and(|v| v == "true"),
+    }))?;
+
+    Ok(Response::ok(json!(response)))
+}
+
+/// Download the staged upload of an intent and validate it, along with the
+/// intent's generation options
+fn load_upload(
+    rt: &tokio::runtime::Runtime,
+    storage: &StorageService,
+    intent: &UploadIntent,
+) -> Result<(Vec<u8>, ImageInfo), HandlerError> {
+    // Check the uploaded object before downloading it
+    let head = rt.block_on(async {
+        storage.head_object(&intent.staging_key).await
+    })?
+    .ok_or_else(|| HandlerError::BadRequest("File has not been uploaded yet".to_string()))?;
+
+    validate_direct_upload_size(head.content_length as usize)?;
+
+    let data = rt.block_on(async {
+        storage.get_object(&intent.staging_key).await
+    })?;
+
+    // The object may have changed between HEAD and GET
+    validate_direct_upload_size(data.len())?;
+    let image_info = validate_image_content(&data)?;
+    validate_frame_index(intent.generation_options.as_ref(), image_info.frame_count)?;
+    validate_crop(intent.generation_options.as_ref(), image_info.width, image_info.height)?;
+    Ok((data, image_info))
+}
+
+handler_loop!(handle);
@@ -0,0 +1,131 @@
+use rust_edge_gateway_sdk::{prelude::*, handler_loop};
+use a_icon_shared::{
+    api_keys::authorize_api_key,
+    creation::{create_favicon, FaviconFields, NewFavicon, SourceOrigin},
+    database::Database,
+    storage::StorageService,
+    rate_limit::with_rate_limit,
+    validation::{validate_image_content, validate_generation_options},
+    models::{ApiKeyScope, FaviconLink},
+    HandlerError,
+};
+use chrono::Utc;
+use serde::Deserialize;
+use std::env;
+
+/// Overrides for the fork; anything left out is taken from the parent,
+/// except the target domain and metadata, which start empty
+#[derive(Deserialize, Default)]
+struct ForkRequest {
+    title: Option<String>,
+    #[serde(rename = "targetDomain")]
+    target_domain: Option<String>,
+    metadata: Option<String>,
+    #[serde(rename = "themeColor")]
+    theme_color: Option<String>,
+    #[serde(rename = "backgroundColor")]
+    background_color: Option<String>,
+    #[serde(rename = "generationOptions")]
+    generation_options: Option<serde_json::Value>,
+}
+
+fn handle(req: Request) -> Response {
+    with_rate_limit(&req, "fork", || match handle_fork(&req) {
+        Ok(response) => response,
+        Err(e) => e.to_response(),
+    })
+}
+
+fn handle_fork(req: &Request) -> Result<Response, HandlerError> {
+    // Extract slug from path parameter
+    let slug = req.path_param("slug")
+        .ok_or_else(|| HandlerError::BadRequest("Missing slug parameter".to_string()))?;
+
+    // The body is optional; an empty one forks with the parent's settings
+    let fork_req: ForkRequest = match req.body.as_deref() {
+        Some(body) if !body.trim().is_empty() => req.json()?,
+        _ => ForkRequest::default(),
+    };
+
+    // Validate the descriptive fields, keeping the domain's ASCII form
+    let fields = FaviconFields {
+        title: fork_req.title,
+        target_domain: fork_req.target_domain,
+        metadata: fork_req.metadata,
+        theme_color: fork_req.theme_color,
+        background_color: fork_req.background_color,
+    }.validate()?;
+
+    // Validate generation options if provided
+    let generation_options = fork_req.generation_options.as_ref().map(validate_generation_options).transpose()?;
+
+    // Initialize services
+    let db_path = env::var("DB_PATH")
+        .map_err(|e| HandlerError::InternalError(format!("DB_PATH not set: {}", e)))?;
+    let db = Database::new(&db_path)?;
+
+    // A presented API key must allow creating favicons, which are attributed to it
+    let api_key = authorize_api_key(&db, &req.headers, ApiKeyScope::FaviconsCreate, Utc::now())?;
+
+    // Only published favicons can be forked
+    let parent = db.get_favicon_by_slug(slug)?
+        .filter(|parent| parent.is_published)
+        .ok_or_else(|| HandlerError::NotFoundMessage(format!("Favicon not found: {}", slug)))?;
+
+    // Title and colors left out are the parent's
+    let fields = FaviconFields {
+        title: fields.title.or_else(|| parent.title.clone()),
+        theme_color: fields.theme_color.or_else(|| parent.theme_color.clone()),
+        background_color: fields.background_color.or_else(|| parent.background_color.clone()),
+        ..fields
+    };
+
+    // Options sent with the fork replace the parent's; `{}` resets them
+    let generation_options = generation_options.or_else(|| parent.generation_options.clone());
+
+    // A derived dark variant replaces the parent's dark source
+    let dark_source_mime = parent.dark_source_mime.clone()
+        .filter(|_| generation_options.as_ref().is_none_or(|options| options.dark_variant.is_none()));
+
+    // Create tokio runtime for async storage operations
+    let rt = tokio::runtime::Runtime::new()
+        .map_err(|e| HandlerError::InternalError(e.to_string()))?;
+
+    let storage = rt.block_on(async {
+        StorageService::new().await
+            .map_err(|e| HandlerError::StorageError(format!("Failed to initialize storage: {}", e)))
+    })?;
+
+    let source = rt.block_on(async {
+        storage.get_object(&format!("sources/{}/original", parent.id)).await
+    })?;
+    let image_info = validate_image_content(&source)?;
+
+    let dark_source = match dark_source_mime {
+        Some(mime_type) => Some((rt.block_on(async {
+            storage.get_object(&format!("sources/{}/dark", parent.id)).await
+        })?, mime_type)),
+        None => None,
+    };
+
+    let mut response = rt.block_on(create_favicon(&db, &storage, NewFavicon {
+        fields,
+        source_type: parent.source_type.clone(),
+        source,
+        source_info: image_info,
+        dark_source,
+        document: None,
+        generation_options,
+        origin: SourceOrigin::Fork(Box::new(parent.clone())),
+        api_key_id: api_key.map(|api_key| api_key.id),
+        include_similar: false,
+    }))?;
+
+    // The new fork has no forks of its own yet
+    response.parent = Some(FaviconLink::from_favicon(parent));
+    response.children = Some(Vec::new());
+
+    Ok(Response::ok(json!(response)))
+}
+
+handler_loop!(handle);
@@ -2,6 +2,8 @@ use rust_edge_gateway_sdk::{prelude::*, handler_loop};
 use a_icon_shared::{
     database::Database,
     models::FaviconDetailResponse,
+    lineage::find_lineage,
+    ownership::find_readable_favicon,
     HandlerError,
 };
 use std::env;
@@ -23,15 +25,20 @@ fn handle_get(req: &Request) -> Result<Response, HandlerError> {
         .map_err(|e| HandlerError::InternalError(format!("DB_PATH not set: {}", e)))?;
     let db = Database::new(&db_path)?;
 
-    // Get favicon by slug
-    let favicon = db.get_favicon_by_slug(slug)?
-        .ok_or_else(|| HandlerError::NotFoundMessage(format!("Favicon not found: {}", slug)))?;
+    // Get favicon by slug; unpublished favicons look missing to everyone
+    // but their owner and API keys allowed to read them
+    let favicon = find_readable_favicon(&db, &req.headers, slug)?;
 
     // Get assets
     let assets = db.get_assets_by_favicon_id(&favicon.id)?;
 
+    // Get the favicon it was forked from and its forks
+    let (parent, children) = find_lineage(&db, &favicon)?;
+
     // Build response
-    let response = FaviconDetailResponse::from_favicon_and_assets(favicon, assets);
+    let mut response = FaviconDetailResponse::from_favicon_and_assets(favicon, assets);
+    response.parent = parent;
+    response.children = Some(children);
 
     Ok(Response::ok(json!(response)))
 }
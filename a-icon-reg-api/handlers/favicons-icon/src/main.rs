@@ -0,0 +1,81 @@
+use rust_edge_gateway_sdk::{prelude::*, handler_loop};
+use a_icon_shared::{
+    database::Database,
+    generator::load_prepared_source,
+    ownership::find_readable_favicon,
+    resize::{render_cache_key, render_etag, render_variant, RenderSpec},
+    storage::StorageService,
+    HandlerError,
+};
+use std::env;
+
+/// Renditions are revalidated daily; the ETag changes whenever the source does
+const ICON_CACHE_SECONDS: u32 = 86400;
+
+fn handle(req: Request) -> Response {
+    match handle_icon(&req) {
+        Ok(response) => response,
+        Err(e) => e.to_response(),
+    }
+}
+
+fn handle_icon(req: &Request) -> Result<Response, HandlerError> {
+    // Extract slug from path parameter
+    let slug = req.path_param("slug")
+        .ok_or_else(|| HandlerError::BadRequest("Missing slug parameter".to_string()))?;
+
+    let size = req.query.get("size")
+        .ok_or_else(|| HandlerError::ValidationError("size is required".to_string()))?;
+    let spec = RenderSpec::parse(size, req.query.get("format").map(|f| f.as_str()))?;
+
+    // Initialize database
+    let db_path = env::var("DB_PATH")
+        .map_err(|e| HandlerError::InternalError(format!("DB_PATH not set: {}", e)))?;
+    let db = Database::new(&db_path)?;
+
+    // Get favicon by slug; unpublished favicons look missing to everyone
+    // but their owner and API keys allowed to read them
+    let favicon = find_readable_favicon(&db, &req.headers, slug)?;
+
+    let etag = render_etag(&favicon, &spec);
+    if req.headers.get("if-none-match").map(|tag| tag.as_str()) == Some(etag.as_str()) {
+        return Ok(Response::new(304)
+            .with_header("ETag", etag)
+            .with_cache(ICON_CACHE_SECONDS));
+    }
+
+    // Create tokio runtime for async storage operations
+    let rt = tokio::runtime::Runtime::new()
+        .map_err(|e| HandlerError::InternalError(e.to_string()))?;
+
+    // Initialize storage service
+    let storage = rt.block_on(async {
+        StorageService::new().await
+            .map_err(|e| HandlerError::StorageError(format!("Failed to initialize storage: {}", e)))
+    })?;
+
+    // Serve a cached rendition, rendering from the source on first request
+    let cache_key = render_cache_key(&favicon, &spec);
+    let data = if rt.block_on(storage.head_object(&cache_key))?.is_some() {
+        rt.block_on(storage.get_object(&cache_key))?
+    } else {
+        let source_key = format!("sources/{}/original", favicon.id);
+        let source = rt.block_on(storage.get_object(&source_key))?;
+        let mime_type = favicon.source_original_mime.clone()
+            .unwrap_or_else(|| StorageService::detect_mime_type(&source));
+
+        let options = favicon.generation_options.clone().unwrap_or_default();
+        let image = load_prepared_source(&source, &mime_type, &options)?;
+        let data = render_variant(&image, &spec, &options)?;
+
+        // Caching is best effort; the rendition is returned either way
+        let _ = rt.block_on(storage.upload_object(&cache_key, data.clone(), spec.format.mime_type()));
+        data
+    };
+
+    Ok(Response::binary(200, data, spec.format.mime_type())
+        .with_header("ETag", etag)
+        .with_cache(ICON_CACHE_SECONDS))
+}
+
+handler_loop!(handle);
@@ -0,0 +1,100 @@
+use rust_edge_gateway_sdk::{prelude::*, handler_loop};
+use a_icon_shared::{
+    api_keys::authorize_api_key,
+    creation::{create_favicon, FaviconFields, NewFavicon, SourceOrigin},
+    database::Database,
+    storage::StorageService,
+    rate_limit::with_rate_limit,
+    validation::{validate_domain, validate_generation_options},
+    models::{SourceType, ApiKeyScope},
+    import::{import_icon, parse_import_url, GuardedFetcher},
+    HandlerError,
+};
+use chrono::Utc;
+use serde::Deserialize;
+use std::env;
+
+#[derive(Deserialize)]
+struct ImportRequest {
+    /// Page to take the icon from, or the icon itself
+    url: String,
+    title: Option<String>,
+    #[serde(rename = "targetDomain")]
+    target_domain: Option<String>,
+    metadata: Option<String>,
+    #[serde(rename = "themeColor")]
+    theme_color: Option<String>,
+    #[serde(rename = "backgroundColor")]
+    background_color: Option<String>,
+    #[serde(rename = "generationOptions")]
+    generation_options: Option<serde_json::Value>,
+}
+
+fn handle(req: Request) -> Response {
+    with_rate_limit(&req, "import", || match handle_import(&req) {
+        Ok(response) => response,
+        Err(e) => e.to_response(),
+    })
+}
+
+fn handle_import(req: &Request) -> Result<Response, HandlerError> {
+    // Parse JSON body using SDK helper
+    let import_req: ImportRequest = req.json()?;
+    let page_url = parse_import_url(&import_req.url)?;
+
+    // Validate the other fields before fetching anything
+    let mut fields = FaviconFields {
+        title: import_req.title,
+        target_domain: import_req.target_domain,
+        metadata: import_req.metadata,
+        theme_color: import_req.theme_color,
+        background_color: import_req.background_color,
+    }.validate()?;
+
+    // Default to the site's own domain, if it has one
+    if fields.target_domain.is_none() {
+        fields.target_domain = page_url.host_str()
+            .map(|host| host.strip_prefix("www.").unwrap_or(host))
+            .and_then(|host| validate_domain(host).ok());
+    }
+
+    let generation_options = import_req.generation_options.as_ref().map(validate_generation_options).transpose()?;
+
+    // Initialize services
+    let db_path = env::var("DB_PATH")
+        .map_err(|e| HandlerError::InternalError(format!("DB_PATH not set: {}", e)))?;
+    let db = Database::new(&db_path)?;
+
+    // A presented API key must allow creating favicons, which are attributed to it
+    let api_key = authorize_api_key(&db, &req.headers, ApiKeyScope::FaviconsCreate, Utc::now())?;
+
+    // Download the site's best icon; it is decoded and validated like an upload
+    let icon = import_icon(&GuardedFetcher::new(), &page_url)?;
+
+    // Create tokio runtime for async storage operations
+    let rt = tokio::runtime::Runtime::new()
+        .map_err(|e| HandlerError::InternalError(e.to_string()))?;
+
+    let storage = rt.block_on(async {
+        StorageService::new().await
+            .map_err(|e| HandlerError::StorageError(format!("Failed to initialize storage: {}", e)))
+    })?;
+
+    let mut response = rt.block_on(create_favicon(&db, &storage, NewFavicon {
+        fields,
+        source_type: SourceType::Import,
+        source: icon.data,
+        source_info: icon.info,
+        dark_source: None,
+        document: None,
+        generation_options,
+        origin: SourceOrigin::Request,
+        api_key_id: api_key.map(|api_key| api_key.id),
+        include_similar: req.query.get("includeSimilar").is_some_and(|v| v == "true"),
+    }))?;
+    response.imported_from = Some(icon.url.to_string());
+
+    Ok(Response::ok(json!(response)))
+}
+
+handler_loop!(handle);
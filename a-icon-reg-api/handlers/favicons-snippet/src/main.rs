@@ -0,0 +1,50 @@
+use rust_edge_gateway_sdk::{prelude::*, handler_loop};
+use a_icon_shared::{
+    database::Database,
+    ownership::find_readable_favicon,
+    snippet::{build_head_tags, public_base_url, render_html},
+    HandlerError,
+};
+use std::env;
+
+fn handle(req: Request) -> Response {
+    match handle_snippet(&req) {
+        Ok(response) => response,
+        Err(e) => e.to_response(),
+    }
+}
+
+fn handle_snippet(req: &Request) -> Result<Response, HandlerError> {
+    // Extract slug from path parameter
+    let slug = req.path_param("slug")
+        .ok_or_else(|| HandlerError::BadRequest("Missing slug parameter".to_string()))?;
+
+    let format = req.query.get("format").map(|f| f.as_str()).unwrap_or("html");
+    if format != "html" && format != "json" {
+        return Err(HandlerError::ValidationError("format must be 'html' or 'json'".to_string()));
+    }
+
+    // Initialize database
+    let db_path = env::var("DB_PATH")
+        .map_err(|e| HandlerError::InternalError(format!("DB_PATH not set: {}", e)))?;
+    let db = Database::new(&db_path)?;
+
+    // Get favicon by slug; unpublished favicons look missing to everyone
+    // but their owner and API keys allowed to read them
+    let favicon = find_readable_favicon(&db, &req.headers, slug)?;
+
+    let assets = db.get_assets_by_favicon_id(&favicon.id)?;
+    let tags = build_head_tags(&favicon, &assets, &public_base_url());
+    let html = render_html(&tags);
+
+    if format == "json" {
+        return Ok(Response::ok(json!({
+            "html": html,
+            "tags": tags,
+        })));
+    }
+
+    Ok(Response::binary(200, html.into_bytes(), "text/html; charset=utf-8"))
+}
+
+handler_loop!(handle);
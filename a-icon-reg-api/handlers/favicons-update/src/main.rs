@@ -0,0 +1,127 @@
+use rust_edge_gateway_sdk::{prelude::*, handler_loop};
+use a_icon_shared::{
+    database::Database,
+    storage::StorageService,
+    validation::{validate_domain, validate_metadata},
+    models::FaviconDetailResponse,
+    manifest::rebuild_stored_manifest,
+    ownership::verify_edit_token,
+    HandlerError,
+};
+use chrono::Utc;
+use serde::Deserialize;
+use std::env;
+
+/// Fields the owner may change. Omitted fields are left as they are; an
+/// empty string clears a field.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct UpdateRequest {
+    title: Option<String>,
+    #[serde(rename = "targetDomain")]
+    target_domain: Option<String>,
+    metadata: Option<String>,
+    #[serde(rename = "isPublished")]
+    is_published: Option<bool>,
+}
+
+fn handle(req: Request) -> Response {
+    match handle_update(&req) {
+        Ok(response) => response,
+        Err(e) => e.to_response(),
+    }
+}
+
+fn handle_update(req: &Request) -> Result<Response, HandlerError> {
+    // Extract edit token from Authorization header
+    let token = extract_bearer_token(req)?;
+
+    // Extract slug from path parameter
+    let slug = req.path_param("slug")
+        .ok_or_else(|| HandlerError::BadRequest("Missing slug parameter".to_string()))?;
+
+    // Parse JSON body using SDK helper
+    let update_req: UpdateRequest = req.json()?;
+
+    let clear_empty = |value: String| Some(value).filter(|v| !v.trim().is_empty());
+    let title = update_req.title.map(clear_empty);
+    let target_domain = update_req.target_domain.map(clear_empty);
+    let metadata = update_req.metadata.map(clear_empty);
+
+    // Validate domain if provided, keeping its ASCII form
+    let target_domain = match target_domain {
+        Some(Some(domain)) => Some(Some(validate_domain(&domain)?)),
+        other => other,
+    };
+
+    // Validate metadata if provided
+    if let Some(Some(ref meta)) = metadata {
+        validate_metadata(meta)?;
+    }
+
+    // Initialize database
+    let db_path = env::var("DB_PATH")
+        .map_err(|e| HandlerError::InternalError(format!("DB_PATH not set: {}", e)))?;
+    let db = Database::new(&db_path)?;
+
+    // Get favicon by slug and check the token against it
+    let mut favicon = db.get_favicon_by_slug(slug)?
+        .ok_or_else(|| HandlerError::NotFoundMessage(format!("Favicon not found: {}", slug)))?;
+    verify_edit_token(&db, &favicon.id, &token)?;
+
+    // The manifest name comes from the title, else the domain
+    let renames = title.as_ref().is_some_and(|title| *title != favicon.title)
+        || target_domain.as_ref().is_some_and(|domain| *domain != favicon.target_domain);
+
+    if let Some(title) = title {
+        favicon.title = title;
+    }
+    if let Some(target_domain) = target_domain {
+        // Verification was for the old domain
+        if target_domain != favicon.target_domain {
+            favicon.domain_verified_at = None;
+        }
+        favicon.target_domain = target_domain;
+    }
+    if let Some(metadata) = metadata {
+        favicon.metadata = metadata;
+    }
+    if let Some(is_published) = update_req.is_published {
+        favicon.is_published = is_published;
+    }
+    favicon.updated_at = Utc::now();
+
+    db.update_favicon(&favicon)?;
+
+    let assets = db.get_assets_by_favicon_id(&favicon.id)?;
+
+    // Rewrite the stored manifest so it shows the new name
+    if renames {
+        if let Some((key, data)) = rebuild_stored_manifest(&favicon, &assets)? {
+            let rt = tokio::runtime::Runtime::new()
+                .map_err(|e| HandlerError::InternalError(e.to_string()))?;
+
+            rt.block_on(async {
+                let storage = StorageService::new().await
+                    .map_err(|e| HandlerError::StorageError(format!("Failed to initialize storage: {}", e)))?;
+                storage.upload_object(&key, data, "application/manifest+json").await
+            })?;
+        }
+    }
+
+    let response = FaviconDetailResponse::from_favicon_and_assets(favicon, assets);
+
+    Ok(Response::ok(json!(response)))
+}
+
+fn extract_bearer_token(req: &Request) -> Result<String, HandlerError> {
+    let auth_header = req.require_header("Authorization")?;
+
+    if !auth_header.starts_with("Bearer ") {
+        return Err(HandlerError::Unauthorized("Invalid Authorization header format".to_string()));
+    }
+
+    Ok(auth_header[7..].to_string())
+}
+
+handler_loop!(handle);
@@ -0,0 +1,153 @@
+use rust_edge_gateway_sdk::{prelude::*, handler_loop};
+use a_icon_shared::{
+    api_keys::authorize_api_key,
+    database::Database,
+    storage::StorageService,
+    ownership::new_upload_secret,
+    rate_limit::with_rate_limit_bytes,
+    validation::{
+        validate_domain, validate_metadata, validate_color, validate_direct_upload_size,
+        validate_upload_content_type, validate_generation_options, MAX_DIRECT_UPLOAD_SIZE,
+    },
+    models::{UploadIntent, UploadIntentResponse, ApiKeyScope},
+    HandlerError,
+};
+use chrono::{Duration, Utc};
+use uuid::Uuid;
+use serde::Deserialize;
+use std::env;
+
+/// How long the presigned upload URL (and the intent) stays valid
+const INTENT_TTL_MINUTES: i64 = 15;
+
+#[derive(Deserialize)]
+struct UploadIntentRequest {
+    #[serde(rename = "contentType")]
+    content_type: String,
+    #[serde(rename = "contentLength")]
+    content_length: usize,
+    title: Option<String>,
+    #[serde(rename = "targetDomain")]
+    target_domain: Option<String>,
+    metadata: Option<String>,
+    #[serde(rename = "themeColor")]
+    theme_color: Option<String>,
+    #[serde(rename = "backgroundColor")]
+    background_color: Option<String>,
+    #[serde(rename = "generationOptions")]
+    generation_options: Option<serde_json::Value>,
+}
+
+fn handle(req: Request) -> Response {
+    // The file goes straight to storage, so its declared size is counted
+    // against the daily byte quota rather than this request's body. Sizes
+    // over the limit are refused anyway and only count up to it.
+    let declared = req.json::<UploadIntentRequest>()
+        .map_or(0, |intent_req| intent_req.content_length.min(MAX_DIRECT_UPLOAD_SIZE) as u64);
+    with_rate_limit_bytes(&req, "upload-intent", declared, || match handle_upload_intent(&req) {
+        Ok(response) => response,
+        Err(e) => e.to_response(),
+    })
+}
+
+fn handle_upload_intent(req: &Request) -> Result<Response, HandlerError> {
+    // Parse JSON body using SDK helper
+    let intent_req: UploadIntentRequest = req.json()?;
+
+    // Validate the declared file before handing out an upload URL
+    validate_upload_content_type(&intent_req.content_type)?;
+    validate_direct_upload_size(intent_req.content_length)?;
+
+    // Validate domain if provided, keeping its ASCII form
+    let target_domain = intent_req.target_domain.as_deref().map(validate_domain).transpose()?;
+
+    // Validate metadata if provided
+    if let Some(ref meta) = intent_req.metadata {
+        validate_metadata(meta)?;
+    }
+
+    // Validate manifest colors if provided
+    let theme_color = intent_req.theme_color.as_deref().map(validate_color).transpose()?;
+    let background_color = intent_req.background_color.as_deref().map(validate_color).transpose()?;
+
+    // Validate generation options if provided; the frame and crop are checked
+    // against the upload when it is finalized
+    let generation_options = intent_req.generation_options.as_ref().map(validate_generation_options).transpose()?;
+
+    // Initialize services
+    let db_path = env::var("DB_PATH")
+        .map_err(|e| HandlerError::InternalError(format!("DB_PATH not set: {}", e)))?;
+    let db = Database::new(&db_path)?;
+
+    // A presented API key must allow creating favicons; the favicon is
+    // attributed to it when the upload is finalized
+    let api_key = authorize_api_key(&db, &req.headers, ApiKeyScope::FaviconsCreate, Utc::now())?;
+
+    // Create tokio runtime for async storage operations
+    let rt = tokio::runtime::Runtime::new()
+        .map_err(|e| HandlerError::InternalError(e.to_string()))?;
+
+    let storage = rt.block_on(async {
+        StorageService::new().await
+            .map_err(|e| HandlerError::StorageError(format!("Failed to initialize storage: {}", e)))
+    })?;
+
+    // Opportunistically clean up abandoned uploads
+    for staging_key in db.delete_expired_upload_intents(Utc::now())? {
+        let _ = rt.block_on(storage.delete_object(&staging_key));
+    }
+
+    // Reserve the favicon id and a staging key for the upload
+    let id = Uuid::new_v4().to_string();
+    let staging_key = format!("uploads/{}/original", id);
+    let now = Utc::now();
+    let expires_at = now + Duration::minutes(INTENT_TTL_MINUTES);
+
+    let upload_url = rt.block_on(async {
+        storage.presign_put_object(
+            &staging_key,
+            &intent_req.content_type,
+            intent_req.content_length as i64,
+            std::time::Duration::from_secs((INTENT_TTL_MINUTES * 60) as u64),
+        ).await
+    })?;
+
+    // Only the client given the secret may finalize the intent
+    let (upload_secret, secret_hash) = new_upload_secret();
+
+    let has_metadata = intent_req.metadata.as_ref().map(|m| !m.trim().is_empty()).unwrap_or(false);
+    let intent = UploadIntent {
+        id: id.clone(),
+        staging_key,
+        content_type: intent_req.content_type.clone(),
+        title: intent_req.title,
+        target_domain,
+        metadata: if has_metadata { intent_req.metadata } else { None },
+        theme_color,
+        background_color,
+        generation_options,
+        expires_at,
+        created_at: now,
+        api_key_id: api_key.map(|api_key| api_key.id),
+        secret_hash,
+        favicon_id: None,
+    };
+
+    db.insert_upload_intent(&intent)?;
+
+    let response = UploadIntentResponse {
+        id: id.clone(),
+        upload_url,
+        upload_method: "PUT".to_string(),
+        content_type: intent_req.content_type,
+        content_length: intent_req.content_length as i64,
+        max_size: MAX_DIRECT_UPLOAD_SIZE as i64,
+        expires_at: expires_at.to_rfc3339(),
+        finalize_url: format!("/api/favicons/{}/finalize", id),
+        upload_secret,
+    };
+
+    Ok(Response::ok(json!(response)))
+}
+
+handler_loop!(handle);
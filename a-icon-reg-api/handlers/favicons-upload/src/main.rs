@@ -1,21 +1,23 @@
 use rust_edge_gateway_sdk::{prelude::*, handler_loop};
 use a_icon_shared::{
+    api_keys::authorize_api_key,
+    creation::{create_favicon, FaviconFields, NewFavicon, SourceOrigin},
     database::Database,
     storage::StorageService,
-    validation::{validate_domain, validate_metadata, validate_file_size, validate_image_type},
-    models::{Favicon, SourceType, GenerationStatus, FaviconDetailResponse},
+    rate_limit::with_rate_limit,
+    validation::{validate_file_size, validate_image_content, parse_generation_options},
+    models::{SourceType, ApiKeyScope},
     HandlerError,
-    utils::{parse_multipart, generate_short_id},
+    multipart::{parse_multipart, boundary_from_content_type},
 };
 use chrono::Utc;
-use uuid::Uuid;
 use std::env;
 
 fn handle(req: Request) -> Response {
-    match handle_upload(&req) {
+    with_rate_limit(&req, "upload", || match handle_upload(&req) {
         Ok(response) => response,
         Err(e) => e.to_response(),
-    }
+    })
 }
 
 fn handle_upload(req: &Request) -> Result<Response, HandlerError> {
@@ -27,7 +29,8 @@ fn handle_upload(req: &Request) -> Result<Response, HandlerError> {
         .ok_or_else(|| HandlerError::ValidationError("Missing request body".to_string()))?
         .as_bytes();
 
-    let boundary = extract_boundary(content_type)?;
+    // The gateway hands over the whole body, so it is parsed in one piece
+    let boundary = boundary_from_content_type(content_type)?;
     let multipart = parse_multipart(body_bytes, &boundary)?;
 
     // Extract file
@@ -38,6 +41,10 @@ fn handle_upload(req: &Request) -> Result<Response, HandlerError> {
     let title = multipart.get_field("title");
     let target_domain = multipart.get_field("targetDomain");
     let metadata = multipart.get_field("metadata");
+    let theme_color = multipart.get_field("themeColor");
+    let background_color = multipart.get_field("backgroundColor");
+    let generation_options = multipart.get_field("generationOptions");
+    let dark_file_part = multipart.get_file("darkFile");
 
     // Validate file size
     validate_file_size(file_part.content.len())?;
@@ -50,27 +57,38 @@ fn handle_upload(req: &Request) -> Result<Response, HandlerError> {
         return Err(HandlerError::ValidationError("Only image files are allowed".to_string()));
     }
 
-    validate_image_type(&file_part.content)?;
-
-    // Validate domain if provided
-    if let Some(domain) = &target_domain {
-        validate_domain(domain)?;
-    }
-
-    // Validate metadata if provided
-    if let Some(meta) = &metadata {
-        validate_metadata(meta)?;
-    }
-
-    // Calculate hash and size for duplicate detection
-    let source_hash = format!("{:x}", md5::compute(&file_part.content));
-    let source_size = file_part.content.len() as i64;
+    // Decode the image to reject corrupt, truncated or oversized files
+    let image_info = validate_image_content(&file_part.content)?;
+
+    // Validate the descriptive fields, keeping the domain's ASCII form
+    let fields = FaviconFields { title, target_domain, metadata, theme_color, background_color }.validate()?;
+
+    // Validate generation options if provided
+    let generation_options = generation_options.as_deref().map(parse_generation_options).transpose()?;
+
+    // Validate the dark-mode source if provided
+    let dark_source = match dark_file_part {
+        Some(part) => {
+            if generation_options.as_ref().is_some_and(|options| options.dark_variant.is_some()) {
+                return Err(HandlerError::ValidationError(
+                    "Provide either darkFile or generationOptions.darkVariant, not both".to_string(),
+                ));
+            }
+            validate_file_size(part.content.len())?;
+            let info = validate_image_content(&part.content)?;
+            Some((part.content.clone(), info.mime_type))
+        }
+        None => None,
+    };
 
     // Initialize services
     let db_path = env::var("DB_PATH")
         .map_err(|e| HandlerError::InternalError(format!("DB_PATH not set: {}", e)))?;
     let db = Database::new(&db_path)?;
 
+    // A presented API key must allow creating favicons, which are attributed to it
+    let api_key = authorize_api_key(&db, &req.headers, ApiKeyScope::FaviconsCreate, Utc::now())?;
+
     // Create tokio runtime for async storage operations
     let rt = tokio::runtime::Runtime::new()
         .map_err(|e| HandlerError::InternalError(e.to_string()))?;
@@ -80,69 +98,21 @@ fn handle_upload(req: &Request) -> Result<Response, HandlerError> {
             .map_err(|e| HandlerError::StorageError(format!("Failed to initialize storage: {}", e)))
     })?;
 
-    // Check for duplicate
-    if let Some(existing) = db.find_duplicate(&source_hash, source_size)? {
-        // Return existing favicon details
-        let assets = db.get_assets_by_favicon_id(&existing.id)?;
-        let response = FaviconDetailResponse::from_favicon_and_assets(existing, assets);
-        return Ok(Response::ok(json!(response)));
-    }
-
-    // Generate IDs
-    let id = Uuid::new_v4().to_string();
-    let slug = generate_short_id(10);
-    let now = Utc::now();
-
-    // Store source image
-    let source_key = format!("sources/{}/original", id);
-    rt.block_on(async {
-        storage.upload_object(&source_key, file_part.content.clone(), &mime_type).await
-    })?;
-
-    // Create favicon record
-    let has_metadata = metadata.as_ref().map(|m| !m.trim().is_empty()).unwrap_or(false);
-    let favicon = Favicon {
-        id: id.clone(),
-        slug: slug.clone(),
-        title,
-        target_domain,
-        published_url: format!("/f/{}", slug),
-        canonical_svg_key: None,
+    let response = rt.block_on(create_favicon(&db, &storage, NewFavicon {
+        fields,
         source_type: SourceType::Upload,
-        source_original_mime: Some(mime_type),
-        source_hash: Some(source_hash),
-        source_size: Some(source_size),
-        is_published: true,
-        created_at: now,
-        updated_at: now,
-        generated_at: None,
-        generation_status: GenerationStatus::Pending,
-        generation_error: None,
-        metadata: if has_metadata { metadata } else { None },
-        has_steganography: false,
-    };
-
-    db.insert_favicon(&favicon)?;
-
-    // TODO: Generate favicon assets asynchronously
-    // For now, just return the favicon with pending status
-
-    let assets = db.get_assets_by_favicon_id(&id)?;
-    let response = FaviconDetailResponse::from_favicon_and_assets(favicon, assets);
+        source: file_part.content.clone(),
+        source_info: image_info,
+        dark_source,
+        document: None,
+        generation_options,
+        origin: SourceOrigin::Request,
+        api_key_id: api_key.map(|api_key| api_key.id),
+        include_similar: req.query.get("includeSimilar").is_some_and(|v| v == "true"),
+    }))?;
 
     Ok(Response::ok(json!(response)))
 }
 
-fn extract_boundary(content_type: &str) -> Result<String, HandlerError> {
-    let parts: Vec<&str> = content_type.split(';').collect();
-    for part in parts {
-        let trimmed = part.trim();
-        if trimmed.starts_with("boundary=") {
-            return Ok(trimmed[9..].trim_matches('"').to_string());
-        }
-    }
-    Err(HandlerError::ValidationError("Missing boundary in Content-Type".to_string()))
-}
-
 handler_loop!(handle);
 
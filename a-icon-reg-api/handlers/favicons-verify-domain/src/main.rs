@@ -0,0 +1,59 @@
+use rust_edge_gateway_sdk::{prelude::*, handler_loop};
+use a_icon_shared::{
+    database::Database,
+    ownership::verify_edit_token,
+    domain_verification::{verify_domain, HttpsFetcher, SystemDnsResolver},
+    HandlerError,
+};
+use std::env;
+
+fn handle(req: Request) -> Response {
+    match handle_verify_domain(&req) {
+        Ok(response) => response,
+        Err(e) => e.to_response(),
+    }
+}
+
+fn handle_verify_domain(req: &Request) -> Result<Response, HandlerError> {
+    // Extract edit token from Authorization header
+    let token = extract_bearer_token(req)?;
+
+    // Extract slug from path parameter
+    let slug = req.path_param("slug")
+        .ok_or_else(|| HandlerError::BadRequest("Missing slug parameter".to_string()))?;
+
+    // Initialize database
+    let db_path = env::var("DB_PATH")
+        .map_err(|e| HandlerError::InternalError(format!("DB_PATH not set: {}", e)))?;
+    let db = Database::new(&db_path)?;
+
+    // Get favicon by slug and check the token against it
+    let favicon = db.get_favicon_by_slug(slug)?
+        .ok_or_else(|| HandlerError::NotFoundMessage(format!("Favicon not found: {}", slug)))?;
+    verify_edit_token(&db, &favicon.id, &token)?;
+
+    // Look up the DNS record, then the well-known file. Both block, so no
+    // tokio runtime is started in this handler.
+    let resolver = SystemDnsResolver::new()?;
+    let fetcher = HttpsFetcher::new();
+    let (method, verified_at) = verify_domain(&db, &favicon, &resolver, &fetcher)?;
+
+    Ok(Response::ok(json!({
+        "id": favicon.id,
+        "domain": favicon.target_domain,
+        "method": method.as_str(),
+        "domainVerifiedAt": verified_at.to_rfc3339(),
+    })))
+}
+
+fn extract_bearer_token(req: &Request) -> Result<String, HandlerError> {
+    let auth_header = req.require_header("Authorization")?;
+
+    if !auth_header.starts_with("Bearer ") {
+        return Err(HandlerError::Unauthorized("Invalid Authorization header format".to_string()));
+    }
+
+    Ok(auth_header[7..].to_string())
+}
+
+handler_loop!(handle);
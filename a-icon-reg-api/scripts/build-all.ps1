@@ -13,7 +13,30 @@ $handlers = @(
     "admin-verify",
     "admin-delete",
     "storage-source",
-    "storage-asset"
+    "storage-asset",
+    "favicons-upload-intent",
+    "favicons-finalize",
+    "admin-duplicates",
+    "admin-backfill-hashes",
+    "favicons-snippet",
+    "favicons-bundle",
+    "favicons-icon",
+    "favicons-document",
+    "favicons-fork",
+    "favicons-update",
+    "favicons-delete",
+    "admin-revoke-edit-token",
+    "favicons-domain-challenge",
+    "favicons-verify-domain",
+    "domains-favicon",
+    "favicons-import",
+    "admin-create-api-key",
+    "admin-list-api-keys",
+    "admin-revoke-api-key",
+    "admin-create-webhook",
+    "admin-list-webhooks",
+    "admin-delete-webhook",
+    "admin-dispatch-webhooks"
 )
 
 Write-Host "Building all handlers..." -ForegroundColor Cyan
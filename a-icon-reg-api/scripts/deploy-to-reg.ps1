@@ -51,6 +51,29 @@ $HANDLER_MAP = @{
     "admin-delete" = "deleteFavicons"
     "storage-source" = "getSourceImage"
     "storage-asset" = "getFile"
+    "favicons-upload-intent" = "createUploadIntent"
+    "favicons-finalize" = "finalizeUpload"
+    "admin-duplicates" = "listNearDuplicates"
+    "admin-backfill-hashes" = "backfillSourceHashes"
+    "favicons-snippet" = "getFaviconSnippet"
+    "favicons-bundle" = "getFaviconBundle"
+    "favicons-icon" = "getFaviconIcon"
+    "favicons-document" = "getFaviconDocument"
+    "favicons-fork" = "forkFavicon"
+    "favicons-update" = "updateFavicon"
+    "favicons-delete" = "deleteFavicon"
+    "admin-revoke-edit-token" = "revokeEditToken"
+    "favicons-domain-challenge" = "requestDomainChallenge"
+    "favicons-verify-domain" = "verifyDomain"
+    "domains-favicon" = "getDomainFavicon"
+    "favicons-import" = "importFavicon"
+    "admin-create-api-key" = "createApiKey"
+    "admin-list-api-keys" = "listApiKeys"
+    "admin-revoke-api-key" = "revokeApiKey"
+    "admin-create-webhook" = "createWebhook"
+    "admin-list-webhooks" = "listWebhooks"
+    "admin-delete-webhook" = "deleteWebhook"
+    "admin-dispatch-webhooks" = "dispatchWebhooks"
 }
 
 # Copy handler source files
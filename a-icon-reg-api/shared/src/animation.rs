@@ -0,0 +1,249 @@
+use crate::error::HandlerError;
+use crate::generator::{prepare_source, render_icon};
+use crate::models::GenerationOptions;
+use image::codecs::gif::GifDecoder;
+use image::{AnimationDecoder, Delay, DynamicImage, ImageFormat, RgbaImage};
+use std::io::Cursor;
+
+/// Size of the animated PNG and WebP assets
+pub const ANIMATED_SIZE: u32 = 64;
+
+/// Browsers play GIF frame delays below this as `DEFAULT_FRAME_DELAY_MS`
+const MIN_FRAME_DELAY_MS: u32 = 20;
+const DEFAULT_FRAME_DELAY_MS: u32 = 100;
+
+/// One fully composited frame of an animation
+pub struct AnimationFrame {
+    pub image: RgbaImage,
+    pub delay_ms: u32,
+}
+
+/// Frame delay in milliseconds as browsers play it
+pub fn frame_delay_ms(delay: Delay) -> u32 {
+    let (numerator, denominator) = delay.numer_denom_ms();
+    let ms = numerator.checked_div(denominator).unwrap_or(0);
+    if ms < MIN_FRAME_DELAY_MS {
+        DEFAULT_FRAME_DELAY_MS
+    } else {
+        ms
+    }
+}
+
+/// Decode every frame of a GIF, composited onto the full canvas. The source
+/// must already have passed `validate_image_content`, which bounds the frame
+/// count and total size.
+pub fn decode_gif_frames(data: &[u8]) -> Result<Vec<AnimationFrame>, HandlerError> {
+    let decoder = GifDecoder::new(Cursor::new(data))
+        .map_err(|e| HandlerError::ValidationError(format!("Invalid GIF: {}", e)))?;
+    let frames = decoder.into_frames().collect_frames()
+        .map_err(|e| HandlerError::ValidationError(format!("Failed to decode GIF frames: {}", e)))?;
+
+    Ok(frames
+        .into_iter()
+        .map(|frame| AnimationFrame {
+            delay_ms: frame_delay_ms(frame.delay()),
+            image: frame.into_buffer(),
+        })
+        .collect())
+}
+
+/// Pick the frame used for static assets
+pub fn select_frame(frames: Vec<AnimationFrame>, frame_index: Option<u32>) -> Result<DynamicImage, HandlerError> {
+    let count = frames.len();
+    let index = frame_index.unwrap_or(0) as usize;
+    frames
+        .into_iter()
+        .nth(index)
+        .map(|frame| DynamicImage::ImageRgba8(frame.image))
+        .ok_or_else(|| HandlerError::ValidationError(format!(
+            "frameIndex {} is out of range; the source has {} frame(s)",
+            index, count
+        )))
+}
+
+/// Apply the generation options to every frame and render it at `size`
+pub fn render_frames(
+    frames: &[AnimationFrame],
+    size: u32,
+    options: &GenerationOptions,
+) -> Result<Vec<AnimationFrame>, HandlerError> {
+    frames
+        .iter()
+        .map(|frame| {
+            let prepared = prepare_source(DynamicImage::ImageRgba8(frame.image.clone()), options)?;
+            Ok(AnimationFrame {
+                image: render_icon(&prepared, size, options).to_rgba8(),
+                delay_ms: frame.delay_ms,
+            })
+        })
+        .collect()
+}
+
+/// Encode frames of equal size as a looping animated PNG
+pub fn encode_apng(frames: &[AnimationFrame]) -> Result<Vec<u8>, HandlerError> {
+    let err = |e: png::EncodingError| HandlerError::InternalError(format!("Failed to encode APNG: {}", e));
+    let (width, height) = frames.first()
+        .map(|frame| frame.image.dimensions())
+        .ok_or_else(|| HandlerError::InternalError("Failed to encode APNG: no frames".to_string()))?;
+
+    let mut data = Vec::new();
+    let mut encoder = png::Encoder::new(&mut data, width, height);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder.set_animated(frames.len() as u32, 0).map_err(err)?;
+
+    let mut writer = encoder.write_header().map_err(err)?;
+    for frame in frames {
+        writer.set_frame_delay(frame.delay_ms.min(u16::MAX as u32) as u16, 1000).map_err(err)?;
+        writer.set_blend_op(png::BlendOp::Source).map_err(err)?;
+        writer.write_image_data(frame.image.as_raw()).map_err(err)?;
+    }
+    writer.finish().map_err(err)?;
+    Ok(data)
+}
+
+/// Encode frames of equal size as a looping animated WebP. Each frame is
+/// encoded losslessly on its own and muxed into the animation container.
+pub fn encode_animated_webp(frames: &[AnimationFrame]) -> Result<Vec<u8>, HandlerError> {
+    let (width, height) = frames.first()
+        .map(|frame| frame.image.dimensions())
+        .ok_or_else(|| HandlerError::InternalError("Failed to encode WebP: no frames".to_string()))?;
+
+    let mut vp8x = vec![0x02 | 0x10, 0, 0, 0]; // animation and alpha flags
+    vp8x.extend_from_slice(&u24(width - 1));
+    vp8x.extend_from_slice(&u24(height - 1));
+
+    // Transparent background, loop forever
+    let anim = [0, 0, 0, 0, 0, 0];
+
+    let mut body = b"WEBP".to_vec();
+    push_chunk(&mut body, b"VP8X", &vp8x);
+    push_chunk(&mut body, b"ANIM", &anim);
+
+    for frame in frames {
+        let mut still = Cursor::new(Vec::new());
+        DynamicImage::ImageRgba8(frame.image.clone())
+            .write_to(&mut still, ImageFormat::WebP)
+            .map_err(|e| HandlerError::InternalError(format!("Failed to encode WebP: {}", e)))?;
+
+        let mut anmf = Vec::new();
+        anmf.extend_from_slice(&u24(0)); // x offset / 2
+        anmf.extend_from_slice(&u24(0)); // y offset / 2
+        anmf.extend_from_slice(&u24(frame.image.width() - 1));
+        anmf.extend_from_slice(&u24(frame.image.height() - 1));
+        anmf.extend_from_slice(&u24(frame.delay_ms.min(0xFF_FFFF)));
+        anmf.push(0x02); // replace instead of blending, keep the canvas
+        anmf.extend_from_slice(image_chunk(still.get_ref())?);
+        push_chunk(&mut body, b"ANMF", &anmf);
+    }
+
+    let mut data = b"RIFF".to_vec();
+    data.extend_from_slice(&(body.len() as u32).to_le_bytes());
+    data.extend_from_slice(&body);
+    Ok(data)
+}
+
+fn u24(value: u32) -> [u8; 3] {
+    let bytes = value.to_le_bytes();
+    [bytes[0], bytes[1], bytes[2]]
+}
+
+/// Append a RIFF chunk, padded to an even length
+fn push_chunk(out: &mut Vec<u8>, fourcc: &[u8; 4], payload: &[u8]) {
+    out.extend_from_slice(fourcc);
+    out.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+    out.extend_from_slice(payload);
+    if payload.len() % 2 == 1 {
+        out.push(0);
+    }
+}
+
+/// The bitstream chunk (`VP8L` or `VP8 `, with its header and padding) of a
+/// still WebP
+fn image_chunk(webp: &[u8]) -> Result<&[u8], HandlerError> {
+    let mut offset = 12;
+    while offset + 8 <= webp.len() {
+        let fourcc = &webp[offset..offset + 4];
+        let size = u32::from_le_bytes([webp[offset + 4], webp[offset + 5], webp[offset + 6], webp[offset + 7]]) as usize;
+        let end = (offset + 8 + size + size % 2).min(webp.len());
+        if fourcc == b"VP8L" || fourcc == b"VP8 " {
+            return Ok(&webp[offset..end]);
+        }
+        offset = end;
+    }
+    Err(HandlerError::InternalError("Failed to encode WebP: no image data".to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::codecs::gif::GifEncoder;
+    use image::codecs::png::PngDecoder;
+    use image::codecs::webp::WebPDecoder;
+    use image::{Frame, Rgba};
+
+    /// Three 8x8 frames: red, green and blue, 50 ms each
+    fn sample_gif() -> Vec<u8> {
+        let mut data = Vec::new();
+        {
+            let mut encoder = GifEncoder::new(&mut data);
+            for color in [[255, 0, 0, 255], [0, 255, 0, 255], [0, 0, 255, 255]] {
+                let frame = Frame::from_parts(
+                    RgbaImage::from_pixel(8, 8, Rgba(color)),
+                    0,
+                    0,
+                    Delay::from_numer_denom_ms(50, 1),
+                );
+                encoder.encode_frame(frame).unwrap();
+            }
+        }
+        data
+    }
+
+    #[test]
+    fn test_frame_delay_ms() {
+        assert_eq!(frame_delay_ms(Delay::from_numer_denom_ms(50, 1)), 50);
+        assert_eq!(frame_delay_ms(Delay::from_numer_denom_ms(0, 1)), DEFAULT_FRAME_DELAY_MS);
+        assert_eq!(frame_delay_ms(Delay::from_numer_denom_ms(10, 1)), DEFAULT_FRAME_DELAY_MS);
+    }
+
+    #[test]
+    fn test_decode_and_select_frames() {
+        let frames = decode_gif_frames(&sample_gif()).unwrap();
+        assert_eq!(frames.len(), 3);
+        assert_eq!(frames[1].delay_ms, 50);
+        assert_eq!(frames[1].image.get_pixel(0, 0).0, [0, 255, 0, 255]);
+
+        let second = select_frame(decode_gif_frames(&sample_gif()).unwrap(), Some(1)).unwrap().to_rgba8();
+        assert_eq!(second.get_pixel(4, 4).0, [0, 255, 0, 255]);
+        assert!(select_frame(frames, Some(3)).is_err());
+    }
+
+    #[test]
+    fn test_encode_apng() {
+        let frames = render_frames(&decode_gif_frames(&sample_gif()).unwrap(), 16, &GenerationOptions::default()).unwrap();
+        let apng = encode_apng(&frames).unwrap();
+
+        let decoder = PngDecoder::new(Cursor::new(&apng)).unwrap();
+        assert!(decoder.is_apng().unwrap());
+        let decoded = decoder.apng().unwrap().into_frames().collect_frames().unwrap();
+        assert_eq!(decoded.len(), 3);
+        assert_eq!(decoded[2].buffer().dimensions(), (16, 16));
+        assert_eq!(decoded[2].buffer().get_pixel(8, 8).0, [0, 0, 255, 255]);
+        assert_eq!(decoded[0].delay().numer_denom_ms(), (50, 1));
+    }
+
+    #[test]
+    fn test_encode_animated_webp() {
+        let frames = render_frames(&decode_gif_frames(&sample_gif()).unwrap(), 16, &GenerationOptions::default()).unwrap();
+        let webp = encode_animated_webp(&frames).unwrap();
+        assert_eq!(u32::from_le_bytes(webp[4..8].try_into().unwrap()) as usize, webp.len() - 8);
+
+        let decoder = WebPDecoder::new(Cursor::new(&webp)).unwrap();
+        assert!(decoder.has_animation());
+        let decoded = decoder.into_frames().collect_frames().unwrap();
+        assert_eq!(decoded.len(), 3);
+        assert_eq!(decoded[1].buffer().get_pixel(8, 8).0, [0, 255, 0, 255]);
+        assert_eq!(decoded[1].delay().numer_denom_ms(), (50, 1));
+    }
+}
@@ -0,0 +1,218 @@
+use crate::database::Database;
+use crate::error::HandlerError;
+use crate::models::{ApiKey, ApiKeyScope};
+use crate::utils::sha256_hex;
+use chrono::{DateTime, Utc};
+use std::collections::HashMap;
+use uuid::Uuid;
+
+/// Start of every API key, which tells keys apart from edit tokens and
+/// admin sessions in the `Authorization` header
+pub const API_KEY_PREFIX: &str = "aik_";
+
+/// Characters of a key kept to identify it in listings
+const DISPLAY_PREFIX_LEN: usize = 12;
+
+const MAX_NAME_LENGTH: usize = 100;
+
+/// Create a key with the given scopes and store its hash. The key itself is
+/// only returned here, for the creation response; it cannot be recovered.
+pub fn create_api_key(
+    db: &Database,
+    name: &str,
+    scopes: Vec<ApiKeyScope>,
+    expires_at: Option<DateTime<Utc>>,
+    now: DateTime<Utc>,
+) -> Result<(ApiKey, String), HandlerError> {
+    let name = name.trim();
+    if name.is_empty() || name.chars().count() > MAX_NAME_LENGTH {
+        return Err(HandlerError::ValidationError(format!(
+            "name must be between 1 and {} characters",
+            MAX_NAME_LENGTH
+        )));
+    }
+    if scopes.is_empty() {
+        return Err(HandlerError::ValidationError("At least one scope is required".to_string()));
+    }
+    if expires_at.is_some_and(|expires_at| expires_at <= now) {
+        return Err(HandlerError::ValidationError("expiresAt must be in the future".to_string()));
+    }
+
+    let mut unique_scopes = Vec::new();
+    for scope in scopes {
+        if !unique_scopes.contains(&scope) {
+            unique_scopes.push(scope);
+        }
+    }
+
+    let key = generate_api_key();
+    let api_key = ApiKey {
+        id: Uuid::new_v4().to_string(),
+        name: name.to_string(),
+        key_hash: sha256_hex(key.as_bytes()),
+        prefix: key[..DISPLAY_PREFIX_LEN].to_string(),
+        scopes: unique_scopes,
+        created_at: now,
+        expires_at,
+        last_used_at: None,
+        revoked_at: None,
+    };
+    db.insert_api_key(&api_key)?;
+    Ok((api_key, key))
+}
+
+/// Parse scope names as given by an admin
+pub fn parse_scopes(names: &[String]) -> Result<Vec<ApiKeyScope>, HandlerError> {
+    names
+        .iter()
+        .map(|name| {
+            ApiKeyScope::from_str(name.trim()).ok_or_else(|| {
+                let known: Vec<&str> = ApiKeyScope::ALL.iter().map(ApiKeyScope::as_str).collect();
+                HandlerError::ValidationError(format!(
+                    "Unknown scope '{}'; expected one of {}",
+                    name,
+                    known.join(", ")
+                ))
+            })
+        })
+        .collect()
+}
+
+/// The API key a request presents: the `X-API-Key` header, or an
+/// `Authorization: Bearer` value that looks like a key. Other bearer values
+/// are left to the edit token and admin session checks.
+pub fn presented_api_key(headers: &HashMap<String, String>) -> Option<&str> {
+    let header = |wanted: &str| {
+        headers
+            .iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case(wanted))
+            .map(|(_, value)| value.trim())
+    };
+
+    if let Some(key) = header("X-API-Key") {
+        return Some(key);
+    }
+    header("Authorization")
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .map(str::trim)
+        .filter(|value| value.starts_with(API_KEY_PREFIX))
+}
+
+/// The active key a request presents, without recording its use. `None` if
+/// it presents none, or one that is unknown, expired or revoked.
+pub fn find_api_key(
+    db: &Database,
+    headers: &HashMap<String, String>,
+    now: DateTime<Utc>,
+) -> Result<Option<ApiKey>, HandlerError> {
+    let Some(key) = presented_api_key(headers) else {
+        return Ok(None);
+    };
+    Ok(db.get_api_key_by_hash(&sha256_hex(key.as_bytes()))?.filter(|api_key| api_key.is_active(now)))
+}
+
+/// The key a request presents, which must be active and have `scope`, with
+/// its use recorded. `None` if the request presents no key.
+pub fn authorize_api_key(
+    db: &Database,
+    headers: &HashMap<String, String>,
+    scope: ApiKeyScope,
+    now: DateTime<Utc>,
+) -> Result<Option<ApiKey>, HandlerError> {
+    if presented_api_key(headers).is_none() {
+        return Ok(None);
+    }
+
+    let api_key = find_api_key(db, headers, now)?
+        .ok_or_else(|| HandlerError::Unauthorized("Invalid, expired or revoked API key".to_string()))?;
+    if !api_key.has_scope(scope) {
+        return Err(HandlerError::Unauthorized(format!(
+            "API key does not have the {} scope",
+            scope.as_str()
+        )));
+    }
+
+    db.touch_api_key(&api_key.id, now)?;
+    Ok(Some(api_key))
+}
+
+/// "aik_" and two random UUIDs as 64 hex characters (244 random bits)
+fn generate_api_key() -> String {
+    format!("{}{}{}", API_KEY_PREFIX, Uuid::new_v4().simple(), Uuid::new_v4().simple())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+    use tempfile::NamedTempFile;
+
+    fn headers(name: &str, value: &str) -> HashMap<String, String> {
+        HashMap::from([(name.to_string(), value.to_string())])
+    }
+
+    #[test]
+    fn test_api_key_lifecycle() {
+        let file = NamedTempFile::new().unwrap();
+        let db = Database::new(file.path()).unwrap();
+        let now = Utc::now();
+
+        let (api_key, key) = create_api_key(
+            &db,
+            " CI ",
+            vec![ApiKeyScope::FaviconsCreate, ApiKeyScope::FaviconsCreate],
+            None,
+            now,
+        ).unwrap();
+        assert_eq!(api_key.name, "CI");
+        assert_eq!(api_key.scopes, vec![ApiKeyScope::FaviconsCreate]);
+        assert!(key.starts_with(API_KEY_PREFIX));
+        assert!(key.starts_with(&api_key.prefix));
+        assert_ne!(api_key.key_hash, key);
+
+        // Accepted in either header, and its use is recorded
+        let bearer = headers("authorization", &format!("Bearer {}", key));
+        let found = authorize_api_key(&db, &bearer, ApiKeyScope::FaviconsCreate, now).unwrap().unwrap();
+        assert_eq!(found.id, api_key.id);
+        assert_eq!(db.get_api_key_by_id(&api_key.id).unwrap().unwrap().last_used_at, Some(now));
+        let header = headers("X-API-Key", &key);
+        assert!(authorize_api_key(&db, &header, ApiKeyScope::FaviconsCreate, now).unwrap().is_some());
+
+        // Scopes the key was not given are refused
+        assert!(authorize_api_key(&db, &header, ApiKeyScope::AdminDelete, now).is_err());
+
+        // Requests without a key are left to other checks
+        assert!(authorize_api_key(&db, &HashMap::new(), ApiKeyScope::FaviconsCreate, now).unwrap().is_none());
+        let edit_token = headers("Authorization", "Bearer 0123456789abcdef");
+        assert!(authorize_api_key(&db, &edit_token, ApiKeyScope::FaviconsCreate, now).unwrap().is_none());
+
+        // Unknown and revoked keys are refused
+        assert!(authorize_api_key(&db, &headers("X-API-Key", "aik_wrong"), ApiKeyScope::FaviconsCreate, now).is_err());
+        assert!(db.revoke_api_key(&api_key.id, now).unwrap());
+        assert!(!db.revoke_api_key(&api_key.id, now).unwrap());
+        assert!(authorize_api_key(&db, &header, ApiKeyScope::FaviconsCreate, now).is_err());
+    }
+
+    #[test]
+    fn test_api_key_expiry() {
+        let file = NamedTempFile::new().unwrap();
+        let db = Database::new(file.path()).unwrap();
+        let now = Utc::now();
+
+        assert!(create_api_key(&db, "Old", vec![ApiKeyScope::FaviconsCreate], Some(now), now).is_err());
+
+        let expires_at = now + Duration::days(1);
+        let (_, key) = create_api_key(&db, "Temporary", vec![ApiKeyScope::FaviconsReadUnpublished], Some(expires_at), now).unwrap();
+        let header = headers("X-API-Key", &key);
+        assert!(find_api_key(&db, &header, now).unwrap().is_some());
+        assert!(find_api_key(&db, &header, expires_at).unwrap().is_none());
+        assert!(authorize_api_key(&db, &header, ApiKeyScope::FaviconsReadUnpublished, expires_at).is_err());
+    }
+
+    #[test]
+    fn test_parse_scopes() {
+        let names = vec!["favicons:create".to_string(), " admin:delete".to_string()];
+        assert_eq!(parse_scopes(&names).unwrap(), vec![ApiKeyScope::FaviconsCreate, ApiKeyScope::AdminDelete]);
+        assert!(parse_scopes(&["favicons:delete".to_string()]).is_err());
+    }
+}
@@ -0,0 +1,231 @@
+use crate::error::HandlerError;
+use crate::generator::{APPLE_TOUCH_SIZES, MSTILE_SIZES};
+use crate::manifest::{build_browserconfig, build_manifest, display_name, MANIFEST_ICON_SIZES};
+use crate::models::{AssetType, AssetVariant, Favicon, FaviconAsset};
+use crate::snippet::{build_head_tags_with, render_html};
+use crate::utils::sha256_hex;
+use std::io::{Cursor, Write};
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, ZipWriter};
+
+/// Bumped whenever the archive layout changes so cached bundles are rebuilt
+const BUNDLE_FORMAT_VERSION: u32 = 1;
+
+const MANIFEST_FILENAME: &str = "site.webmanifest";
+const BROWSERCONFIG_FILENAME: &str = "browserconfig.xml";
+const README_FILENAME: &str = "README.md";
+
+/// Size of the icon published as plain `apple-touch-icon.png`
+const APPLE_TOUCH_DEFAULT_SIZE: u32 = 180;
+
+/// Conventional filename of an asset inside the bundle, or `None` if the asset
+/// is not bundled. Dark and adaptive variants get a `-dark`/`-adaptive` suffix.
+pub fn bundle_filename(r#type: AssetType, size: Option<&str>, variant: AssetVariant) -> Option<String> {
+    let (stem, extension) = match (r#type, size) {
+        (AssetType::Ico, _) => ("favicon".to_string(), "ico"),
+        (AssetType::Svg, _) => ("favicon".to_string(), "svg"),
+        (AssetType::MaskIcon, _) => ("safari-pinned-tab".to_string(), "svg"),
+        (AssetType::Manifest, _) => return Some(MANIFEST_FILENAME.to_string()),
+        (AssetType::Browserconfig, _) => return Some(BROWSERCONFIG_FILENAME.to_string()),
+        (AssetType::Png, Some(size)) => (png_stem(size), "png"),
+        (AssetType::Maskable, Some(size)) => (format!("maskable-icon-{}", size), "png"),
+        (AssetType::AnimatedPng, Some(size)) => (format!("favicon-animated-{}", size), "png"),
+        (AssetType::AnimatedWebp, Some(size)) => (format!("favicon-animated-{}", size), "webp"),
+        _ => return None,
+    };
+    Some(format!("{}{}.{}", stem, variant.suffix(), extension))
+}
+
+fn png_stem(size: &str) -> String {
+    let width = size.split('x').next().and_then(|w| w.parse::<u32>().ok()).unwrap_or(0);
+
+    if width == APPLE_TOUCH_DEFAULT_SIZE {
+        "apple-touch-icon".to_string()
+    } else if APPLE_TOUCH_SIZES.contains(&width) {
+        format!("apple-touch-icon-{}", size)
+    } else if MANIFEST_ICON_SIZES.contains(&size) {
+        format!("android-chrome-{}", size)
+    } else if MSTILE_SIZES.contains(&width) {
+        format!("mstile-{}", size)
+    } else {
+        format!("favicon-{}", size)
+    }
+}
+
+/// Stored assets copied into the bundle as-is, paired with their filenames.
+/// The manifest and browserconfig are left out; the bundle carries copies that
+/// point at the bundled filenames instead of storage URLs.
+pub fn bundled_assets(assets: &[FaviconAsset]) -> Vec<(&FaviconAsset, String)> {
+    assets
+        .iter()
+        .filter(|asset| !matches!(asset.r#type, AssetType::Manifest | AssetType::Browserconfig))
+        .filter_map(|asset| {
+            bundle_filename(asset.r#type, asset.size.as_deref(), asset.variant).map(|name| (asset, name))
+        })
+        .collect()
+}
+
+/// Storage key of the cached bundle for a favicon's current asset set. Any
+/// regeneration replaces the asset rows and therefore changes the key.
+pub fn bundle_cache_key(favicon_id: &str, assets: &[FaviconAsset]) -> String {
+    let mut entries: Vec<String> = assets
+        .iter()
+        .map(|asset| format!("{}:{}", asset.id, asset.storage_key))
+        .collect();
+    entries.sort();
+
+    let fingerprint = sha256_hex(format!("v{}\n{}", BUNDLE_FORMAT_VERSION, entries.join("\n")).as_bytes());
+    format!("bundles/{}/{}.zip", favicon_id, &fingerprint[..16])
+}
+
+/// Assemble the ZIP archive from `files` (filename and contents, as returned by
+/// [`bundled_assets`]), adding the manifest, browserconfig and a README with
+/// the HTML snippet. Links are root-relative, for sites serving the files from `/`.
+pub fn build_bundle(favicon: &Favicon, files: &[(String, Vec<u8>)]) -> Result<Vec<u8>, HandlerError> {
+    let resolve = |r#type: AssetType, size: Option<&str>, variant: AssetVariant| {
+        bundle_filename(r#type, size, variant)
+            .filter(|name| {
+                name == MANIFEST_FILENAME
+                    || name == BROWSERCONFIG_FILENAME
+                    || files.iter().any(|(file, _)| file == name)
+            })
+            .map(|name| format!("/{}", name))
+    };
+
+    let light = |r#type: AssetType, size: &str| resolve(r#type, Some(size), AssetVariant::Light);
+    let manifest = build_manifest(favicon, &light)?;
+    let browserconfig = build_browserconfig(favicon, &light);
+    let readme = build_readme(favicon, &render_html(&build_head_tags_with(favicon, &resolve)));
+
+    let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+    let options = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+
+    let generated = [
+        (MANIFEST_FILENAME, manifest),
+        (BROWSERCONFIG_FILENAME, browserconfig),
+        (README_FILENAME, readme.into_bytes()),
+    ];
+    let entries = files
+        .iter()
+        .map(|(name, data)| (name.as_str(), data.as_slice()))
+        .chain(generated.iter().map(|(name, data)| (*name, data.as_slice())));
+
+    for (name, data) in entries {
+        zip.start_file(name, options)
+            .and_then(|_| zip.write_all(data).map_err(Into::into))
+            .map_err(|e| HandlerError::InternalError(format!("Failed to build bundle: {}", e)))?;
+    }
+
+    let cursor = zip.finish()
+        .map_err(|e| HandlerError::InternalError(format!("Failed to build bundle: {}", e)))?;
+    Ok(cursor.into_inner())
+}
+
+fn build_readme(favicon: &Favicon, snippet: &str) -> String {
+    format!(
+        "# {} favicons\n\n\
+         Generated by a-icon.com.\n\n\
+         Copy these files to the root of your site and add the following to the\n\
+         `<head>` of every page:\n\n\
+         ```html\n{}```\n",
+        display_name(favicon),
+        snippet
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+    use std::io::Read;
+
+    fn favicon() -> Favicon {
+        Favicon {
+            title: Some("Example".to_string()),
+            ..Favicon::test_default()
+        }
+    }
+
+    fn asset(id: &str, r#type: AssetType, size: Option<&str>) -> FaviconAsset {
+        FaviconAsset {
+            id: id.to_string(),
+            favicon_id: "abc".to_string(),
+            r#type,
+            size: size.map(str::to_string),
+            variant: AssetVariant::Light,
+            format: ".png".to_string(),
+            storage_key: format!("abc/{}-{}.png", r#type.as_str(), size.unwrap_or("canonical")),
+            mime_type: "image/png".to_string(),
+            created_at: Utc::now(),
+        }
+    }
+
+    #[test]
+    fn test_bundle_filenames() {
+        let name = |r#type, size| bundle_filename(r#type, size, AssetVariant::Light).unwrap();
+        assert_eq!(name(AssetType::Ico, Some("MULTI")), "favicon.ico");
+        assert_eq!(name(AssetType::Svg, None), "favicon.svg");
+        assert_eq!(name(AssetType::Png, Some("16x16")), "favicon-16x16.png");
+        assert_eq!(name(AssetType::Png, Some("180x180")), "apple-touch-icon.png");
+        assert_eq!(name(AssetType::Png, Some("152x152")), "apple-touch-icon-152x152.png");
+        assert_eq!(name(AssetType::Png, Some("192x192")), "android-chrome-192x192.png");
+        assert_eq!(name(AssetType::Png, Some("150x150")), "mstile-150x150.png");
+        assert_eq!(name(AssetType::Maskable, Some("512x512")), "maskable-icon-512x512.png");
+        assert_eq!(name(AssetType::MaskIcon, None), "safari-pinned-tab.svg");
+        assert_eq!(name(AssetType::AnimatedWebp, Some("64x64")), "favicon-animated-64x64.webp");
+        assert_eq!(name(AssetType::Manifest, None), "site.webmanifest");
+        assert_eq!(bundle_filename(AssetType::Png, Some("32x32"), AssetVariant::Dark).unwrap(), "favicon-32x32-dark.png");
+        assert_eq!(bundle_filename(AssetType::Svg, None, AssetVariant::Adaptive).unwrap(), "favicon-adaptive.svg");
+    }
+
+    #[test]
+    fn test_bundle_cache_key_tracks_asset_set() {
+        let a = asset("1", AssetType::Png, Some("16x16"));
+        let b = asset("2", AssetType::Svg, None);
+
+        let key = bundle_cache_key("abc", &[a.clone(), b.clone()]);
+        assert!(key.starts_with("bundles/abc/") && key.ends_with(".zip"));
+        assert_eq!(key, bundle_cache_key("abc", &[b.clone(), a.clone()]));
+
+        let regenerated = asset("3", AssetType::Png, Some("16x16"));
+        assert_ne!(key, bundle_cache_key("abc", &[regenerated, b]));
+    }
+
+    #[test]
+    fn test_build_bundle() {
+        let assets = vec![
+            asset("1", AssetType::Png, Some("192x192")),
+            asset("2", AssetType::Png, Some("180x180")),
+            asset("3", AssetType::Manifest, None),
+        ];
+        let files: Vec<(String, Vec<u8>)> = bundled_assets(&assets)
+            .into_iter()
+            .map(|(asset, name)| (name, asset.id.clone().into_bytes()))
+            .collect();
+        assert_eq!(files.len(), 2);
+
+        let data = build_bundle(&favicon(), &files).unwrap();
+        let mut archive = zip::ZipArchive::new(Cursor::new(data)).unwrap();
+        let mut names: Vec<&str> = archive.file_names().collect();
+        names.sort();
+        assert_eq!(names, vec![
+            "README.md",
+            "android-chrome-192x192.png",
+            "apple-touch-icon.png",
+            "browserconfig.xml",
+            "site.webmanifest",
+        ]);
+
+        let mut manifest = String::new();
+        archive.by_name("site.webmanifest").unwrap().read_to_string(&mut manifest).unwrap();
+        let manifest: serde_json::Value = serde_json::from_str(&manifest).unwrap();
+        assert_eq!(manifest["icons"][0]["src"], "/android-chrome-192x192.png");
+        assert_eq!(manifest["icons"].as_array().unwrap().len(), 1);
+
+        let mut readme = String::new();
+        archive.by_name("README.md").unwrap().read_to_string(&mut readme).unwrap();
+        assert!(readme.starts_with("# Example favicons"));
+        assert!(readme.contains("<link rel=\"apple-touch-icon\" sizes=\"180x180\" href=\"/apple-touch-icon.png\">"));
+        assert!(readme.contains("<link rel=\"manifest\" href=\"/site.webmanifest\">"));
+    }
+}
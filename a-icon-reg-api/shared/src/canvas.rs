@@ -0,0 +1,417 @@
+use crate::silhouette::trace_mask;
+use serde::{Deserialize, Serialize};
+
+/// Version of the canvas document format written by this server
+pub const CANVAS_DOCUMENT_VERSION: u32 = 1;
+
+/// Largest canvas width or height, in canvas units
+pub const MAX_CANVAS_SIZE: u32 = 1024;
+
+/// Limits that keep a document cheap to validate and render
+pub const MAX_CANVAS_LAYERS: usize = 32;
+pub const MAX_CANVAS_ELEMENTS: usize = 2000;
+pub const MAX_CANVAS_POINTS: usize = 20_000;
+pub const MAX_PALETTE_COLORS: usize = 256;
+pub const MAX_TEXT_LENGTH: usize = 32;
+
+/// Glyphs of the built-in text font are 5x7 cells with one cell of spacing
+const GLYPH_WIDTH: u32 = 5;
+const GLYPH_HEIGHT: u32 = 7;
+const GLYPH_ADVANCE: u32 = GLYPH_WIDTH + 1;
+
+/// Editable drawing from the canvas editor. The server renders it to an SVG
+/// source, so the drawing keeps its structure and the canonical SVG stays
+/// vector. Validate with [`crate::validation::validate_canvas_document`]
+/// before rendering.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct CanvasDocument {
+    pub version: u32,
+    pub width: u32,
+    pub height: u32,
+    /// Painted behind every layer; transparent when absent
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub background: Option<CanvasColor>,
+    /// Colors elements can refer to by index, as `#rrggbb`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub palette: Vec<String>,
+    /// Bottom layer first
+    pub layers: Vec<CanvasLayer>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct CanvasLayer {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    /// Hidden layers are kept in the document but not rendered
+    #[serde(default = "default_visible")]
+    pub visible: bool,
+    /// 0 (transparent) to 1 (opaque)
+    #[serde(default = "default_opacity")]
+    pub opacity: f64,
+    /// Bottom element first
+    #[serde(default)]
+    pub elements: Vec<CanvasElement>,
+}
+
+fn default_visible() -> bool {
+    true
+}
+
+fn default_opacity() -> f64 {
+    1.0
+}
+
+/// A hex color or an index into the document palette
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum CanvasColor {
+    Palette(usize),
+    Hex(String),
+}
+
+/// Something drawn on a layer. Coordinates are canvas units with the origin
+/// at the top left. Shapes need a `fill`, a `stroke` or both.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase", rename_all_fields = "camelCase", deny_unknown_fields)]
+pub enum CanvasElement {
+    /// Paint the whole canvas
+    Fill { color: CanvasColor },
+    /// Freehand line through `points` with round caps and joins; a single
+    /// point draws a dot
+    Stroke {
+        points: Vec<[f64; 2]>,
+        color: CanvasColor,
+        width: f64,
+    },
+    Rect {
+        x: f64,
+        y: f64,
+        width: f64,
+        height: f64,
+        /// Corner radius
+        #[serde(default)]
+        radius: f64,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        fill: Option<CanvasColor>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        stroke: Option<CanvasColor>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        stroke_width: Option<f64>,
+    },
+    Ellipse {
+        cx: f64,
+        cy: f64,
+        rx: f64,
+        ry: f64,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        fill: Option<CanvasColor>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        stroke: Option<CanvasColor>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        stroke_width: Option<f64>,
+    },
+    /// Closed shape through at least three points
+    Polygon {
+        points: Vec<[f64; 2]>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        fill: Option<CanvasColor>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        stroke: Option<CanvasColor>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        stroke_width: Option<f64>,
+    },
+    /// Text in the built-in pixel font (A-Z, 0-9 and `.-+!?#&`, case
+    /// insensitive). `x`/`y` is the top left corner and `size` the cap height.
+    Text {
+        x: f64,
+        y: f64,
+        text: String,
+        size: f64,
+        color: CanvasColor,
+    },
+}
+
+impl CanvasDocument {
+    /// Render the document as an SVG. The output depends only on the
+    /// document, so the same drawing always produces the same source.
+    pub fn to_svg(&self) -> Vec<u8> {
+        let mut body = String::new();
+        if let Some(background) = &self.background {
+            body.push_str(&format!(
+                r#"<rect width="{}" height="{}" fill="{}"/>"#,
+                self.width,
+                self.height,
+                self.color(background)
+            ));
+        }
+
+        for layer in self.layers.iter().filter(|layer| layer.visible && layer.opacity > 0.0) {
+            let elements: String = layer.elements.iter().map(|element| self.element_svg(element)).collect();
+            if layer.opacity < 1.0 {
+                body.push_str(&format!(r#"<g opacity="{}">{}</g>"#, num(layer.opacity), elements));
+            } else {
+                body.push_str(&elements);
+            }
+        }
+
+        format!(
+            r#"<svg xmlns="http://www.w3.org/2000/svg" width="{w}" height="{h}" viewBox="0 0 {w} {h}">{body}</svg>"#,
+            w = self.width,
+            h = self.height,
+        )
+        .into_bytes()
+    }
+
+    /// `#rrggbb` of a color of a validated document
+    fn color<'a>(&'a self, color: &'a CanvasColor) -> &'a str {
+        match color {
+            CanvasColor::Palette(index) => &self.palette[*index],
+            CanvasColor::Hex(hex) => hex,
+        }
+    }
+
+    fn paint(&self, fill: &Option<CanvasColor>, stroke: &Option<CanvasColor>, stroke_width: Option<f64>) -> String {
+        let mut attributes = format!(r#" fill="{}""#, fill.as_ref().map_or("none", |color| self.color(color)));
+        if let Some(stroke) = stroke {
+            attributes.push_str(&format!(
+                r#" stroke="{}" stroke-width="{}""#,
+                self.color(stroke),
+                num(stroke_width.unwrap_or(1.0))
+            ));
+        }
+        attributes
+    }
+
+    fn element_svg(&self, element: &CanvasElement) -> String {
+        match element {
+            CanvasElement::Fill { color } => format!(
+                r#"<rect width="{}" height="{}" fill="{}"/>"#,
+                self.width,
+                self.height,
+                self.color(color)
+            ),
+            CanvasElement::Stroke { points, color, width } => match points.as_slice() {
+                [[x, y]] => format!(
+                    r#"<circle cx="{}" cy="{}" r="{}" fill="{}"/>"#,
+                    num(*x),
+                    num(*y),
+                    num(width / 2.0),
+                    self.color(color)
+                ),
+                _ => format!(
+                    r#"<polyline points="{}" fill="none" stroke="{}" stroke-width="{}" stroke-linecap="round" stroke-linejoin="round"/>"#,
+                    point_list(points),
+                    self.color(color),
+                    num(*width)
+                ),
+            },
+            CanvasElement::Rect { x, y, width, height, radius, fill, stroke, stroke_width } => {
+                let corners = if *radius > 0.0 {
+                    format!(r#" rx="{r}" ry="{r}""#, r = num(*radius))
+                } else {
+                    String::new()
+                };
+                format!(
+                    r#"<rect x="{}" y="{}" width="{}" height="{}"{}{}/>"#,
+                    num(*x),
+                    num(*y),
+                    num(*width),
+                    num(*height),
+                    corners,
+                    self.paint(fill, stroke, *stroke_width)
+                )
+            }
+            CanvasElement::Ellipse { cx, cy, rx, ry, fill, stroke, stroke_width } => format!(
+                r#"<ellipse cx="{}" cy="{}" rx="{}" ry="{}"{}/>"#,
+                num(*cx),
+                num(*cy),
+                num(*rx),
+                num(*ry),
+                self.paint(fill, stroke, *stroke_width)
+            ),
+            CanvasElement::Polygon { points, fill, stroke, stroke_width } => format!(
+                r#"<polygon points="{}"{}/>"#,
+                point_list(points),
+                self.paint(fill, stroke, *stroke_width)
+            ),
+            CanvasElement::Text { x, y, text, size, color } => match text_path(text) {
+                Some(path) => format!(
+                    r#"<path transform="translate({} {}) scale({})" fill="{}" fill-rule="evenodd" d="{}"/>"#,
+                    num(*x),
+                    num(*y),
+                    num(size / GLYPH_HEIGHT as f64),
+                    self.color(color),
+                    path
+                ),
+                None => String::new(),
+            },
+        }
+    }
+}
+
+/// Shortest decimal form with at most two decimals, so equal documents
+/// always print the same numbers
+fn num(value: f64) -> String {
+    let fixed = format!("{:.2}", value);
+    let trimmed = fixed.trim_end_matches('0').trim_end_matches('.');
+    if trimmed == "-0" {
+        "0".to_string()
+    } else {
+        trimmed.to_string()
+    }
+}
+
+fn point_list(points: &[[f64; 2]]) -> String {
+    points
+        .iter()
+        .map(|[x, y]| format!("{},{}", num(*x), num(*y)))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Outline of `text` in font cells, or `None` if nothing is drawn
+fn text_path(text: &str) -> Option<String> {
+    let glyphs: Vec<[u8; 7]> = text.chars().filter_map(glyph).collect();
+    let width = (glyphs.len() as u32 * GLYPH_ADVANCE).checked_sub(1)?;
+    let mut mask = vec![false; (width * GLYPH_HEIGHT) as usize];
+    for (index, rows) in glyphs.iter().enumerate() {
+        for (y, row) in rows.iter().enumerate() {
+            for x in 0..GLYPH_WIDTH {
+                if row & (1 << (GLYPH_WIDTH - 1 - x)) != 0 {
+                    let column = index as u32 * GLYPH_ADVANCE + x;
+                    mask[(y as u32 * width + column) as usize] = true;
+                }
+            }
+        }
+    }
+
+    let path = trace_mask(&mask, width, GLYPH_HEIGHT);
+    (!path.is_empty()).then_some(path)
+}
+
+/// Whether the built-in font can draw `c`
+pub fn is_supported_char(c: char) -> bool {
+    glyph(c).is_some()
+}
+
+/// Rows of a glyph, top first, with the leftmost cell in the high bit
+fn glyph(c: char) -> Option<[u8; 7]> {
+    Some(match c.to_ascii_uppercase() {
+        'A' => [0b01110, 0b10001, 0b10001, 0b11111, 0b10001, 0b10001, 0b10001],
+        'B' => [0b11110, 0b10001, 0b10001, 0b11110, 0b10001, 0b10001, 0b11110],
+        'C' => [0b01110, 0b10001, 0b10000, 0b10000, 0b10000, 0b10001, 0b01110],
+        'D' => [0b11110, 0b10001, 0b10001, 0b10001, 0b10001, 0b10001, 0b11110],
+        'E' => [0b11111, 0b10000, 0b10000, 0b11110, 0b10000, 0b10000, 0b11111],
+        'F' => [0b11111, 0b10000, 0b10000, 0b11110, 0b10000, 0b10000, 0b10000],
+        'G' => [0b01110, 0b10001, 0b10000, 0b10111, 0b10001, 0b10001, 0b01111],
+        'H' => [0b10001, 0b10001, 0b10001, 0b11111, 0b10001, 0b10001, 0b10001],
+        'I' => [0b01110, 0b00100, 0b00100, 0b00100, 0b00100, 0b00100, 0b01110],
+        'J' => [0b00111, 0b00010, 0b00010, 0b00010, 0b00010, 0b10010, 0b01100],
+        'K' => [0b10001, 0b10010, 0b10100, 0b11000, 0b10100, 0b10010, 0b10001],
+        'L' => [0b10000, 0b10000, 0b10000, 0b10000, 0b10000, 0b10000, 0b11111],
+        'M' => [0b10001, 0b11011, 0b10101, 0b10101, 0b10001, 0b10001, 0b10001],
+        'N' => [0b10001, 0b10001, 0b11001, 0b10101, 0b10011, 0b10001, 0b10001],
+        'O' => [0b01110, 0b10001, 0b10001, 0b10001, 0b10001, 0b10001, 0b01110],
+        'P' => [0b11110, 0b10001, 0b10001, 0b11110, 0b10000, 0b10000, 0b10000],
+        'Q' => [0b01110, 0b10001, 0b10001, 0b10001, 0b10101, 0b10010, 0b01101],
+        'R' => [0b11110, 0b10001, 0b10001, 0b11110, 0b10100, 0b10010, 0b10001],
+        'S' => [0b01111, 0b10000, 0b10000, 0b01110, 0b00001, 0b00001, 0b11110],
+        'T' => [0b11111, 0b00100, 0b00100, 0b00100, 0b00100, 0b00100, 0b00100],
+        'U' => [0b10001, 0b10001, 0b10001, 0b10001, 0b10001, 0b10001, 0b01110],
+        'V' => [0b10001, 0b10001, 0b10001, 0b10001, 0b10001, 0b01010, 0b00100],
+        'W' => [0b10001, 0b10001, 0b10001, 0b10101, 0b10101, 0b10101, 0b01010],
+        'X' => [0b10001, 0b10001, 0b01010, 0b00100, 0b01010, 0b10001, 0b10001],
+        'Y' => [0b10001, 0b10001, 0b10001, 0b01010, 0b00100, 0b00100, 0b00100],
+        'Z' => [0b11111, 0b00001, 0b00010, 0b00100, 0b01000, 0b10000, 0b11111],
+        '0' => [0b01110, 0b10001, 0b10011, 0b10101, 0b11001, 0b10001, 0b01110],
+        '1' => [0b00100, 0b01100, 0b00100, 0b00100, 0b00100, 0b00100, 0b01110],
+        '2' => [0b01110, 0b10001, 0b00001, 0b00010, 0b00100, 0b01000, 0b11111],
+        '3' => [0b11111, 0b00010, 0b00100, 0b00010, 0b00001, 0b10001, 0b01110],
+        '4' => [0b00010, 0b00110, 0b01010, 0b10010, 0b11111, 0b00010, 0b00010],
+        '5' => [0b11111, 0b10000, 0b11110, 0b00001, 0b00001, 0b10001, 0b01110],
+        '6' => [0b00110, 0b01000, 0b10000, 0b11110, 0b10001, 0b10001, 0b01110],
+        '7' => [0b11111, 0b00001, 0b00010, 0b00100, 0b01000, 0b01000, 0b01000],
+        '8' => [0b01110, 0b10001, 0b10001, 0b01110, 0b10001, 0b10001, 0b01110],
+        '9' => [0b01110, 0b10001, 0b10001, 0b01111, 0b00001, 0b00010, 0b01100],
+        ' ' => [0; 7],
+        '.' => [0b00000, 0b00000, 0b00000, 0b00000, 0b00000, 0b01100, 0b01100],
+        '-' => [0b00000, 0b00000, 0b00000, 0b11111, 0b00000, 0b00000, 0b00000],
+        '+' => [0b00000, 0b00100, 0b00100, 0b11111, 0b00100, 0b00100, 0b00000],
+        '!' => [0b00100, 0b00100, 0b00100, 0b00100, 0b00100, 0b00000, 0b00100],
+        '?' => [0b01110, 0b10001, 0b00001, 0b00010, 0b00100, 0b00000, 0b00100],
+        '#' => [0b01010, 0b01010, 0b11111, 0b01010, 0b11111, 0b01010, 0b01010],
+        '&' => [0b01100, 0b10010, 0b10100, 0b01000, 0b10101, 0b10010, 0b01101],
+        _ => return None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::generator::rasterize_svg;
+
+    fn document(layers: serde_json::Value) -> CanvasDocument {
+        serde_json::from_value(serde_json::json!({
+            "version": 1,
+            "width": 32,
+            "height": 32,
+            "palette": ["#ff0000", "#0000ff"],
+            "layers": layers,
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_to_svg() {
+        let doc = document(serde_json::json!([
+            { "name": "base", "elements": [{ "type": "fill", "color": 0 }] },
+            { "opacity": 0.5, "elements": [
+                { "type": "rect", "x": 4, "y": 4, "width": 8, "height": 8, "radius": 2, "fill": 1 },
+                { "type": "stroke", "points": [[1, 1], [30.126, 30]], "color": "#00ff00", "width": 2 },
+            ] },
+            { "visible": false, "elements": [{ "type": "fill", "color": 1 }] },
+        ]));
+        assert_eq!(
+            String::from_utf8(doc.to_svg()).unwrap(),
+            concat!(
+                r#"<svg xmlns="http://www.w3.org/2000/svg" width="32" height="32" viewBox="0 0 32 32">"#,
+                r##"<rect width="32" height="32" fill="#ff0000"/>"##,
+                r#"<g opacity="0.5">"#,
+                r##"<rect x="4" y="4" width="8" height="8" rx="2" ry="2" fill="#0000ff"/>"##,
+                r##"<polyline points="1,1 30.13,30" fill="none" stroke="#00ff00" stroke-width="2" stroke-linecap="round" stroke-linejoin="round"/>"##,
+                "</g></svg>",
+            )
+        );
+    }
+
+    #[test]
+    fn test_text_is_traced_from_the_pixel_font() {
+        assert_eq!(text_path("i").unwrap(), "M1 0H4V1H3V6H4V7H1V6H2V1H1Z");
+        assert_eq!(text_path("  "), None);
+        assert!(is_supported_char('q') && !is_supported_char('é'));
+
+        let doc = document(serde_json::json!([
+            { "elements": [{ "type": "text", "x": 2, "y": 3, "text": "-", "size": 14, "color": 1 }] },
+        ]));
+        let svg = String::from_utf8(doc.to_svg()).unwrap();
+        assert!(svg.contains(r##"<path transform="translate(2 3) scale(2)" fill="#0000ff" fill-rule="evenodd" d="M0 3H5V4H0Z"/>"##));
+    }
+
+    #[test]
+    fn test_rendering_is_deterministic() {
+        let doc = document(serde_json::json!([
+            { "elements": [
+                { "type": "ellipse", "cx": 16, "cy": 16, "rx": 12, "ry": 12, "fill": 0, "stroke": 1, "strokeWidth": 2 },
+                { "type": "polygon", "points": [[8, 24], [16, 8], [24, 24]], "fill": "#ffffff" },
+            ] },
+        ]));
+        let first = rasterize_svg(&doc.to_svg(), 64).unwrap().to_rgba8();
+        let second = rasterize_svg(&doc.to_svg(), 64).unwrap().to_rgba8();
+        assert_eq!(first, second);
+        assert_eq!(first.get_pixel(32, 36).0, [255, 255, 255, 255]);
+        assert_eq!(first.get_pixel(32, 12).0, [255, 0, 0, 255]);
+        assert_eq!(first.get_pixel(0, 0).0[3], 0);
+    }
+}
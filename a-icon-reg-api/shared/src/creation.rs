@@ -0,0 +1,248 @@
+use crate::database::Database;
+use crate::error::HandlerError;
+use crate::generator::generate_and_store;
+use crate::models::{Favicon, FaviconDetailResponse, GenerationOptions, GenerationStatus, SourceType, UploadIntent, WebhookEvent};
+use crate::ownership::issue_edit_token;
+use crate::similarity::{find_similar_favicons, source_perceptual_hash};
+use crate::storage::StorageService;
+use crate::utils::{generate_short_id, sha256_hex};
+use crate::validation::{validate_color, validate_crop, validate_domain, validate_frame_index, validate_metadata, ImageInfo};
+use crate::webhooks::emit_event;
+use chrono::Utc;
+use uuid::Uuid;
+
+/// Descriptive fields of a new favicon, shared by the creation endpoints
+#[derive(Debug, Clone, Default)]
+pub struct FaviconFields {
+    pub title: Option<String>,
+    pub target_domain: Option<String>,
+    pub metadata: Option<String>,
+    pub theme_color: Option<String>,
+    pub background_color: Option<String>,
+}
+
+impl FaviconFields {
+    /// Validate the fields as sent, keeping the ASCII form of the domain and
+    /// dropping blank metadata
+    pub fn validate(self) -> Result<Self, HandlerError> {
+        if let Some(metadata) = &self.metadata {
+            validate_metadata(metadata)?;
+        }
+        Ok(FaviconFields {
+            title: self.title,
+            target_domain: self.target_domain.as_deref().map(validate_domain).transpose()?,
+            metadata: self.metadata.filter(|metadata| !metadata.trim().is_empty()),
+            theme_color: self.theme_color.as_deref().map(validate_color).transpose()?,
+            background_color: self.background_color.as_deref().map(validate_color).transpose()?,
+        })
+    }
+}
+
+/// Where the stored sources of a new favicon come from
+pub enum SourceOrigin {
+    /// The request itself: the source, dark source and canvas document are
+    /// uploaded under the new favicon
+    Request,
+    /// A direct upload staged for an intent. The staged object is moved into
+    /// place, the favicon takes the intent's id, and the intent is marked
+    /// finalized.
+    UploadIntent(Box<UploadIntent>),
+    /// The favicon being forked, whose sources are copied
+    Fork(Box<Favicon>),
+}
+
+/// A favicon to create, with its fields validated and its source decoded
+pub struct NewFavicon {
+    pub fields: FaviconFields,
+    pub source_type: SourceType,
+    pub source: Vec<u8>,
+    pub source_info: ImageInfo,
+    /// Separate dark-mode source and its MIME type
+    pub dark_source: Option<(Vec<u8>, String)>,
+    /// Serialized canvas document the source was rendered from
+    pub document: Option<Vec<u8>>,
+    pub generation_options: Option<GenerationOptions>,
+    pub origin: SourceOrigin,
+    pub api_key_id: Option<String>,
+    /// Report existing favicons that look the same
+    pub include_similar: bool,
+}
+
+/// Create a favicon and generate its assets, or return the existing
+/// favicon made from the same source with the same settings. Only a newly
+/// created favicon's response carries its edit token.
+pub async fn create_favicon(
+    db: &Database,
+    storage: &StorageService,
+    new: NewFavicon,
+) -> Result<FaviconDetailResponse, HandlerError> {
+    // Default options are stored as none, so duplicates compare equal
+    let generation_options = new.generation_options.clone().filter(|options| !options.is_default());
+    validate_frame_index(generation_options.as_ref(), new.source_info.frame_count)?;
+    validate_crop(generation_options.as_ref(), new.source_info.width, new.source_info.height)?;
+
+    let source_hash = sha256_hex(&new.source);
+    let source_size = new.source.len() as i64;
+
+    // Reuse a published favicon generated with the same options and manifest
+    // colors. Forks, separate dark sources and canvas documents always make a
+    // new favicon, since a document may hold hidden layers the source does
+    // not show.
+    let deduplicate = !matches!(new.origin, SourceOrigin::Fork(_))
+        && new.dark_source.is_none()
+        && new.document.is_none();
+    if deduplicate {
+        let duplicate = db.find_duplicates(&source_hash, source_size)?
+            .into_iter()
+            .find(|existing| {
+                existing.generated_with(
+                    generation_options.as_ref(),
+                    new.fields.theme_color.as_deref(),
+                    new.fields.background_color.as_deref(),
+                )
+            });
+        if let Some(existing) = duplicate {
+            if let SourceOrigin::UploadIntent(intent) = &new.origin {
+                storage.delete_object(&intent.staging_key).await?;
+                db.finalize_upload_intent(&intent.id, &existing.id)?;
+            }
+            let assets = db.get_assets_by_favicon_id(&existing.id)?;
+            return Ok(FaviconDetailResponse::from_favicon_and_assets(existing, assets));
+        }
+    }
+
+    let id = match &new.origin {
+        SourceOrigin::UploadIntent(intent) => intent.id.clone(),
+        _ => Uuid::new_v4().to_string(),
+    };
+    store_sources(storage, &id, &new).await?;
+
+    // Fingerprint the image for near-duplicate lookups
+    let perceptual_hash = source_perceptual_hash(&new.source, &new.source_info.mime_type).ok().map(|h| h as i64);
+
+    let (has_canvas_document, parent_id) = match &new.origin {
+        SourceOrigin::Fork(parent) => (parent.has_canvas_document, Some(parent.id.clone())),
+        _ => (new.document.is_some(), None),
+    };
+
+    let slug = generate_short_id(10);
+    let now = Utc::now();
+    let mut favicon = Favicon {
+        id,
+        slug: slug.clone(),
+        title: new.fields.title,
+        target_domain: new.fields.target_domain,
+        published_url: format!("/f/{}", slug),
+        canonical_svg_key: None,
+        source_type: new.source_type,
+        source_original_mime: Some(new.source_info.mime_type),
+        source_hash: Some(source_hash),
+        source_size: Some(source_size),
+        is_published: true,
+        created_at: now,
+        updated_at: now,
+        generated_at: None,
+        generation_status: GenerationStatus::Pending,
+        generation_error: None,
+        metadata: new.fields.metadata,
+        has_steganography: false,
+        source_width: Some(new.source_info.width as i64),
+        source_height: Some(new.source_info.height as i64),
+        source_color_type: Some(new.source_info.color_type),
+        perceptual_hash,
+        theme_color: new.fields.theme_color,
+        background_color: new.fields.background_color,
+        generation_options,
+        dark_source_mime: new.dark_source.as_ref().map(|(_, mime_type)| mime_type.clone()),
+        svg_method: None,
+        has_canvas_document,
+        parent_id,
+        domain_verified_at: None,
+        api_key_id: new.api_key_id,
+    };
+
+    db.insert_favicon(&favicon)?;
+    let edit_token = issue_edit_token(db, &favicon.id)?;
+    emit_event(db, WebhookEvent::FaviconCreated, &favicon, favicon.created_at)?;
+    if let SourceOrigin::UploadIntent(intent) = &new.origin {
+        db.finalize_upload_intent(&intent.id, &favicon.id)?;
+    }
+
+    // Generate favicon assets
+    generate_and_store(
+        db,
+        storage,
+        &mut favicon,
+        &new.source,
+        new.dark_source.as_ref().map(|(data, _)| data.as_slice()),
+    ).await?;
+
+    let similar_favicons = if new.include_similar {
+        Some(find_similar_favicons(db, &favicon)?)
+    } else {
+        None
+    };
+
+    let assets = db.get_assets_by_favicon_id(&favicon.id)?;
+    let mut response = FaviconDetailResponse::from_favicon_and_assets(favicon, assets);
+    response.similar_favicons = similar_favicons;
+    response.edit_token = Some(edit_token);
+    Ok(response)
+}
+
+/// Put the sources of a new favicon under `sources/{id}/`
+async fn store_sources(storage: &StorageService, id: &str, new: &NewFavicon) -> Result<(), HandlerError> {
+    let source_key = format!("sources/{}/original", id);
+    let dark_key = format!("sources/{}/dark", id);
+    let document_key = format!("sources/{}/document", id);
+
+    match &new.origin {
+        SourceOrigin::Request => {
+            storage.upload_object(&source_key, new.source.clone(), &new.source_info.mime_type).await?;
+            if let Some((data, mime_type)) = &new.dark_source {
+                storage.upload_object(&dark_key, data.clone(), mime_type).await?;
+            }
+            if let Some(document) = &new.document {
+                storage.upload_object(&document_key, document.clone(), "application/json").await?;
+            }
+        }
+        SourceOrigin::UploadIntent(intent) => {
+            storage.copy_object(&intent.staging_key, &source_key).await?;
+            storage.delete_object(&intent.staging_key).await?;
+        }
+        SourceOrigin::Fork(parent) => {
+            storage.copy_object(&format!("sources/{}/original", parent.id), &source_key).await?;
+            if new.dark_source.is_some() {
+                storage.copy_object(&format!("sources/{}/dark", parent.id), &dark_key).await?;
+            }
+            if parent.has_canvas_document {
+                storage.copy_object(&format!("sources/{}/document", parent.id), &document_key).await?;
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate_fields() {
+        let fields = FaviconFields {
+            title: Some("Title".to_string()),
+            target_domain: Some("Bücher.example".to_string()),
+            metadata: Some("  ".to_string()),
+            theme_color: Some("#FFFFFF".to_string()),
+            background_color: None,
+        }
+        .validate()
+        .unwrap();
+        assert_eq!(fields.target_domain.as_deref(), Some("xn--bcher-kva.example"));
+        assert_eq!(fields.metadata, None);
+        assert!(fields.theme_color.is_some());
+
+        let invalid = FaviconFields { theme_color: Some("white".to_string()), ..FaviconFields::default() };
+        assert!(invalid.validate().is_err());
+    }
+}
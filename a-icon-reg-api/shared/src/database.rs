@@ -1,7 +1,9 @@
 use crate::error::HandlerError;
 use crate::db_err;
-use crate::models::{Favicon, FaviconAsset, SourceType, GenerationStatus, AssetType, DirectoryItem};
-use rusqlite::{Connection, params, OptionalExtension};
+use crate::models::{Favicon, FaviconAsset, SourceType, GenerationStatus, AssetType, DirectoryItem, UploadIntent, GenerationOptions, AssetVariant, SvgMethod, DomainChallenge, RateLimitState, ApiKey, ApiKeyScope, Webhook, WebhookEvent, WebhookDelivery, WebhookDeliveryCounts, DeliveryStatus};
+use crate::similarity::{hamming_distance, phash_bands, PHASH_BANDS};
+use crate::validation::display_domain;
+use rusqlite::{Connection, params, OptionalExtension, Transaction, TransactionBehavior};
 use chrono::{DateTime, Utc};
 use std::path::Path;
 
@@ -9,15 +11,165 @@ pub struct Database {
     conn: Connection,
 }
 
+/// Columns selected for a `Favicon`, in the order `favicon_from_row` expects
+const FAVICON_COLUMNS: &str =
+    "id, slug, title, target_domain, published_url, canonical_svg_key,
+     source_type, source_original_mime, source_hash, source_size, is_published,
+     created_at, updated_at, generated_at, generation_status, generation_error,
+     metadata, has_steganography, source_width, source_height, source_color_type,
+     perceptual_hash, theme_color, background_color, generation_options, dark_source_mime, svg_method,
+     has_canvas_document, parent_id, domain_verified_at, api_key_id";
+
+fn favicon_from_row(row: &rusqlite::Row) -> rusqlite::Result<Favicon> {
+    Ok(Favicon {
+        id: row.get(0)?,
+        slug: row.get(1)?,
+        title: row.get(2)?,
+        target_domain: row.get(3)?,
+        published_url: row.get(4)?,
+        canonical_svg_key: row.get(5)?,
+        source_type: SourceType::from_str(&row.get::<_, String>(6)?).unwrap(),
+        source_original_mime: row.get(7)?,
+        source_hash: row.get(8)?,
+        source_size: row.get(9)?,
+        is_published: row.get::<_, i32>(10)? == 1,
+        created_at: DateTime::parse_from_rfc3339(&row.get::<_, String>(11)?)
+            .unwrap().with_timezone(&Utc),
+        updated_at: DateTime::parse_from_rfc3339(&row.get::<_, String>(12)?)
+            .unwrap().with_timezone(&Utc),
+        generated_at: row.get::<_, Option<String>>(13)?
+            .map(|s| DateTime::parse_from_rfc3339(&s).unwrap().with_timezone(&Utc)),
+        generation_status: GenerationStatus::from_str(&row.get::<_, String>(14)?).unwrap(),
+        generation_error: row.get(15)?,
+        metadata: row.get(16)?,
+        has_steganography: row.get::<_, i32>(17)? == 1,
+        source_width: row.get(18)?,
+        source_height: row.get(19)?,
+        source_color_type: row.get(20)?,
+        perceptual_hash: row.get(21)?,
+        theme_color: row.get(22)?,
+        background_color: row.get(23)?,
+        generation_options: row.get::<_, Option<String>>(24)?
+            .and_then(|json| GenerationOptions::from_json(&json)),
+        dark_source_mime: row.get(25)?,
+        svg_method: row.get::<_, Option<String>>(26)?
+            .and_then(|method| SvgMethod::from_str(&method)),
+        has_canvas_document: row.get::<_, i32>(27)? == 1,
+        parent_id: row.get(28)?,
+        domain_verified_at: row.get::<_, Option<String>>(29)?
+            .map(|s| DateTime::parse_from_rfc3339(&s).unwrap().with_timezone(&Utc)),
+        api_key_id: row.get(30)?,
+    })
+}
+
+/// Columns selected for an `ApiKey`, in the order `api_key_from_row` expects
+const API_KEY_COLUMNS: &str =
+    "id, name, key_hash, prefix, scopes, created_at, expires_at, last_used_at, revoked_at";
+
+fn api_key_from_row(row: &rusqlite::Row) -> rusqlite::Result<ApiKey> {
+    let timestamp = |index: usize| -> rusqlite::Result<Option<DateTime<Utc>>> {
+        Ok(row.get::<_, Option<String>>(index)?
+            .map(|s| DateTime::parse_from_rfc3339(&s).unwrap().with_timezone(&Utc)))
+    };
+    Ok(ApiKey {
+        id: row.get(0)?,
+        name: row.get(1)?,
+        key_hash: row.get(2)?,
+        prefix: row.get(3)?,
+        // Stored space-separated; scopes this version does not know are dropped
+        scopes: row.get::<_, String>(4)?
+            .split_whitespace()
+            .filter_map(ApiKeyScope::from_str)
+            .collect(),
+        created_at: DateTime::parse_from_rfc3339(&row.get::<_, String>(5)?)
+            .unwrap().with_timezone(&Utc),
+        expires_at: timestamp(6)?,
+        last_used_at: timestamp(7)?,
+        revoked_at: timestamp(8)?,
+    })
+}
+
+fn webhook_from_row(row: &rusqlite::Row) -> rusqlite::Result<Webhook> {
+    Ok(Webhook {
+        id: row.get(0)?,
+        url: row.get(1)?,
+        // Stored space-separated; events this version does not know are dropped
+        events: row.get::<_, String>(2)?
+            .split_whitespace()
+            .filter_map(WebhookEvent::from_str)
+            .collect(),
+        secret: row.get(3)?,
+        created_at: DateTime::parse_from_rfc3339(&row.get::<_, String>(4)?)
+            .unwrap().with_timezone(&Utc),
+    })
+}
+
+/// Columns selected for a `WebhookDelivery`, in the order
+/// `webhook_delivery_from_row` expects
+const WEBHOOK_DELIVERY_COLUMNS: &str =
+    "id, webhook_id, event, payload, status, attempts, next_attempt_at, last_error, created_at, delivered_at";
+
+fn webhook_delivery_from_row(row: &rusqlite::Row) -> rusqlite::Result<WebhookDelivery> {
+    Ok(WebhookDelivery {
+        id: row.get(0)?,
+        webhook_id: row.get(1)?,
+        event: WebhookEvent::from_str(&row.get::<_, String>(2)?).unwrap(),
+        payload: row.get(3)?,
+        status: DeliveryStatus::from_str(&row.get::<_, String>(4)?).unwrap(),
+        attempts: row.get(5)?,
+        next_attempt_at: DateTime::parse_from_rfc3339(&row.get::<_, String>(6)?)
+            .unwrap().with_timezone(&Utc),
+        last_error: row.get(7)?,
+        created_at: DateTime::parse_from_rfc3339(&row.get::<_, String>(8)?)
+            .unwrap().with_timezone(&Utc),
+        delivered_at: row.get::<_, Option<String>>(9)?
+            .map(|s| DateTime::parse_from_rfc3339(&s).unwrap().with_timezone(&Utc)),
+    })
+}
+
+/// Perceptual hash bands stored with a favicon, none without a hash
+fn stored_phash_bands(favicon: &Favicon) -> [Option<i64>; PHASH_BANDS] {
+    match favicon.perceptual_hash {
+        Some(hash) => phash_bands(hash as u64).map(Some),
+        None => [None; PHASH_BANDS],
+    }
+}
+
+/// Schema version recorded in `PRAGMA user_version` once `migrate` has run.
+/// Bump it whenever `migrate` gains a step.
+const SCHEMA_VERSION: i64 = 2;
+
 impl Database {
     pub fn new<P: AsRef<Path>>(db_path: P) -> Result<Self, HandlerError> {
         let conn = db_err!(Connection::open(db_path))?;
 
         let db = Database { conn };
-        db.init_schema()?;
+        db.upgrade()?;
         Ok(db)
     }
 
+    /// Create and migrate the schema unless it is already at
+    /// [`SCHEMA_VERSION`]. The version is checked again under the write lock,
+    /// so connections opened at the same time migrate only once.
+    fn upgrade(&self) -> Result<(), HandlerError> {
+        if self.schema_version()? >= SCHEMA_VERSION {
+            return Ok(());
+        }
+
+        let tx = db_err!(Transaction::new_unchecked(&self.conn, TransactionBehavior::Immediate))?;
+        if self.schema_version()? < SCHEMA_VERSION {
+            self.init_schema()?;
+            self.migrate()?;
+            db_err!(tx.pragma_update(None, "user_version", SCHEMA_VERSION))?;
+        }
+        db_err!(tx.commit())?;
+        Ok(())
+    }
+
+    fn schema_version(&self) -> Result<i64, HandlerError> {
+        db_err!(self.conn.pragma_query_value(None, "user_version", |row| row.get(0)))
+    }
+
     fn init_schema(&self) -> Result<(), HandlerError> {
         db_err!(self.conn.execute_batch(
             r#"
@@ -28,7 +180,7 @@ impl Database {
                 target_domain TEXT,
                 published_url TEXT NOT NULL,
                 canonical_svg_key TEXT,
-                source_type TEXT NOT NULL CHECK(source_type IN ('UPLOAD', 'CANVAS')),
+                source_type TEXT NOT NULL CHECK(source_type IN ('UPLOAD', 'CANVAS', 'IMPORT')),
                 source_original_mime TEXT,
                 source_hash TEXT,
                 source_size INTEGER,
@@ -61,109 +213,400 @@ impl Database {
             );
 
             CREATE INDEX IF NOT EXISTS idx_favicon_assets_favicon_id ON favicon_assets(favicon_id);
+
+            CREATE TABLE IF NOT EXISTS upload_intents (
+                id TEXT PRIMARY KEY,
+                staging_key TEXT NOT NULL,
+                content_type TEXT NOT NULL,
+                title TEXT,
+                target_domain TEXT,
+                metadata TEXT,
+                expires_at TEXT NOT NULL,
+                created_at TEXT NOT NULL
+            );
+
+            CREATE INDEX IF NOT EXISTS idx_upload_intents_expires_at ON upload_intents(expires_at);
+
+            CREATE TABLE IF NOT EXISTS edit_tokens (
+                favicon_id TEXT PRIMARY KEY,
+                token_hash TEXT NOT NULL,
+                created_at TEXT NOT NULL,
+                revoked_at TEXT
+            );
+
+            CREATE TABLE IF NOT EXISTS webhooks (
+                id TEXT PRIMARY KEY,
+                url TEXT NOT NULL,
+                events TEXT NOT NULL,
+                secret TEXT NOT NULL,
+                created_at TEXT NOT NULL
+            );
+
+            CREATE TABLE IF NOT EXISTS webhook_deliveries (
+                id TEXT PRIMARY KEY,
+                webhook_id TEXT NOT NULL,
+                event TEXT NOT NULL,
+                payload TEXT NOT NULL,
+                status TEXT NOT NULL CHECK(status IN ('PENDING', 'DELIVERED', 'FAILED')),
+                attempts INTEGER NOT NULL DEFAULT 0,
+                next_attempt_at TEXT NOT NULL,
+                last_error TEXT,
+                created_at TEXT NOT NULL,
+                delivered_at TEXT
+            );
+
+            CREATE INDEX IF NOT EXISTS idx_webhook_deliveries_due ON webhook_deliveries(status, next_attempt_at);
+            CREATE INDEX IF NOT EXISTS idx_webhook_deliveries_webhook_id ON webhook_deliveries(webhook_id);
+
+            CREATE TABLE IF NOT EXISTS domain_challenges (
+                favicon_id TEXT PRIMARY KEY,
+                domain TEXT NOT NULL,
+                token TEXT NOT NULL,
+                created_at TEXT NOT NULL
+            );
+
+            CREATE TABLE IF NOT EXISTS rate_limits (
+                endpoint TEXT NOT NULL,
+                client TEXT NOT NULL,
+                tokens REAL NOT NULL,
+                refilled_at TEXT NOT NULL,
+                day TEXT NOT NULL,
+                requests INTEGER NOT NULL,
+                bytes INTEGER NOT NULL,
+                PRIMARY KEY (endpoint, client)
+            );
+
+            CREATE INDEX IF NOT EXISTS idx_rate_limits_day ON rate_limits(day);
+
+            CREATE TABLE IF NOT EXISTS api_keys (
+                id TEXT PRIMARY KEY,
+                name TEXT NOT NULL,
+                key_hash TEXT UNIQUE NOT NULL,
+                prefix TEXT NOT NULL,
+                scopes TEXT NOT NULL,
+                created_at TEXT NOT NULL,
+                expires_at TEXT,
+                last_used_at TEXT,
+                revoked_at TEXT
+            );
             "#
         ))?;
         Ok(())
     }
 
-    pub fn get_favicon_by_id(&self, id: &str) -> Result<Option<Favicon>, HandlerError> {
+    /// Bring databases created by older versions up to date. Columns added
+    /// after the initial schema are created here rather than in `init_schema`.
+    fn migrate(&self) -> Result<(), HandlerError> {
+        self.add_column_if_missing("favicons", "source_width", "INTEGER")?;
+        self.add_column_if_missing("favicons", "source_height", "INTEGER")?;
+        self.add_column_if_missing("favicons", "source_color_type", "TEXT")?;
+        self.add_column_if_missing("favicons", "perceptual_hash", "INTEGER")?;
+        // Similarity is a Hamming distance, which an index on the whole hash
+        // cannot answer; favicons are looked up by indexed bands of it instead
+        db_err!(self.conn.execute_batch(
+            "DROP INDEX IF EXISTS idx_favicons_perceptual_hash;"
+        ))?;
+        for band in 0..PHASH_BANDS {
+            self.add_column_if_missing("favicons", &format!("phash_band{}", band), "INTEGER")?;
+            db_err!(self.conn.execute_batch(&format!(
+                "CREATE INDEX IF NOT EXISTS idx_favicons_phash_band{0} ON favicons(phash_band{0});",
+                band
+            )))?;
+        }
+        self.backfill_phash_bands()?;
+        self.add_column_if_missing("favicons", "theme_color", "TEXT")?;
+        self.add_column_if_missing("favicons", "background_color", "TEXT")?;
+        self.add_column_if_missing("upload_intents", "theme_color", "TEXT")?;
+        self.add_column_if_missing("upload_intents", "background_color", "TEXT")?;
+        self.add_column_if_missing("favicons", "generation_options", "TEXT")?;
+        self.add_column_if_missing("favicons", "dark_source_mime", "TEXT")?;
+        self.add_column_if_missing("favicons", "svg_method", "TEXT")?;
+        self.add_column_if_missing("favicons", "has_canvas_document", "INTEGER NOT NULL DEFAULT 0")?;
+        self.add_column_if_missing("favicons", "parent_id", "TEXT")?;
+        db_err!(self.conn.execute_batch(
+            "CREATE INDEX IF NOT EXISTS idx_favicons_parent_id ON favicons(parent_id);"
+        ))?;
+        self.add_column_if_missing("favicons", "domain_verified_at", "TEXT")?;
+        db_err!(self.conn.execute_batch(
+            "CREATE UNIQUE INDEX IF NOT EXISTS idx_favicons_verified_domain
+             ON favicons(lower(target_domain)) WHERE domain_verified_at IS NOT NULL;
+             CREATE INDEX IF NOT EXISTS idx_favicons_target_domain_lower ON favicons(lower(target_domain));"
+        ))?;
+        self.add_column_if_missing("favicon_assets", "variant", "TEXT NOT NULL DEFAULT 'LIGHT'")?;
+        self.add_column_if_missing("favicons", "api_key_id", "TEXT")?;
+        self.add_column_if_missing("upload_intents", "api_key_id", "TEXT")?;
+        self.add_column_if_missing("upload_intents", "secret_hash", "TEXT NOT NULL DEFAULT ''")?;
+        self.add_column_if_missing("upload_intents", "favicon_id", "TEXT")?;
+        self.add_column_if_missing("upload_intents", "generation_options", "TEXT")?;
+        db_err!(self.conn.execute_batch(
+            "CREATE INDEX IF NOT EXISTS idx_favicons_api_key_id ON favicons(api_key_id);"
+        ))?;
+        self.rewrite_table_definition(
+            "favicons",
+            "CHECK(source_type IN ('UPLOAD', 'CANVAS'))",
+            "CHECK(source_type IN ('UPLOAD', 'CANVAS', 'IMPORT'))",
+        )?;
+        Ok(())
+    }
+
+    /// Change part of a table definition that `ALTER TABLE` cannot, such as a
+    /// CHECK constraint. The table is rebuilt under the new definition with
+    /// its rows and indexes, unless `from` is no longer in the definition.
+    /// Runs inside the transaction opened by `upgrade`.
+    fn rewrite_table_definition(&self, table: &str, from: &str, to: &str) -> Result<(), HandlerError> {
+        let definition: String = db_err!(self.conn.query_row(
+            "SELECT sql FROM sqlite_master WHERE type = 'table' AND name = ?",
+            [table],
+            |row| row.get(0),
+        ))?;
+        if !definition.contains(from) {
+            return Ok(());
+        }
+
         let mut stmt = db_err!(self.conn.prepare(
-            "SELECT id, slug, title, target_domain, published_url, canonical_svg_key,
-                    source_type, source_original_mime, source_hash, source_size, is_published,
-                    created_at, updated_at, generated_at, generation_status, generation_error,
-                    metadata, has_steganography
-             FROM favicons WHERE id = ?"
+            "SELECT sql FROM sqlite_master WHERE type = 'index' AND tbl_name = ? AND sql IS NOT NULL"
         ))?;
+        let indexes = db_err!(db_err!(stmt.query_map([table], |row| row.get::<_, String>(0)))?
+            .collect::<Result<Vec<_>, _>>())?;
+        drop(stmt);
 
-        let favicon = db_err!(stmt.query_row([id], |row| {
-            Ok(Favicon {
-                id: row.get(0)?,
-                slug: row.get(1)?,
-                title: row.get(2)?,
-                target_domain: row.get(3)?,
-                published_url: row.get(4)?,
-                canonical_svg_key: row.get(5)?,
-                source_type: SourceType::from_str(&row.get::<_, String>(6)?).unwrap(),
-                source_original_mime: row.get(7)?,
-                source_hash: row.get(8)?,
-                source_size: row.get(9)?,
-                is_published: row.get::<_, i32>(10)? == 1,
-                created_at: DateTime::parse_from_rfc3339(&row.get::<_, String>(11)?)
-                    .unwrap().with_timezone(&Utc),
-                updated_at: DateTime::parse_from_rfc3339(&row.get::<_, String>(12)?)
-                    .unwrap().with_timezone(&Utc),
-                generated_at: row.get::<_, Option<String>>(13)?
-                    .map(|s| DateTime::parse_from_rfc3339(&s).unwrap().with_timezone(&Utc)),
-                generation_status: GenerationStatus::from_str(&row.get::<_, String>(14)?).unwrap(),
-                generation_error: row.get(15)?,
-                metadata: row.get(16)?,
-                has_steganography: row.get::<_, i32>(17)? == 1,
-            })
-        }).optional())?;
+        // The table name is the first occurrence in its CREATE TABLE statement
+        let rebuilt = format!("{}_rebuild", table);
+        let create = definition.replacen(from, to, 1).replacen(table, &rebuilt, 1);
+        // Copy by name, since added columns need not line up by position
+        let columns = self.table_columns(table)?.join(", ");
 
-        Ok(favicon)
+        db_err!(self.conn.execute_batch(&format!(
+            "{};
+             INSERT INTO {} ({}) SELECT {} FROM {};
+             DROP TABLE {};
+             ALTER TABLE {} RENAME TO {};",
+            create, rebuilt, columns, columns, table, table, rebuilt, table
+        )))?;
+        for index in &indexes {
+            db_err!(self.conn.execute_batch(index))?;
+        }
+        Ok(())
     }
 
-    pub fn get_favicon_by_slug(&self, slug: &str) -> Result<Option<Favicon>, HandlerError> {
+    /// Fill in the bands of perceptual hashes stored before they were indexed
+    fn backfill_phash_bands(&self) -> Result<(), HandlerError> {
         let mut stmt = db_err!(self.conn.prepare(
-            "SELECT id, slug, title, target_domain, published_url, canonical_svg_key,
-                    source_type, source_original_mime, source_hash, source_size, is_published,
-                    created_at, updated_at, generated_at, generation_status, generation_error,
-                    metadata, has_steganography
-             FROM favicons WHERE slug = ?"
+            "SELECT id, perceptual_hash FROM favicons WHERE perceptual_hash IS NOT NULL AND phash_band0 IS NULL"
         ))?;
+        let hashes = db_err!(db_err!(stmt.query_map([], |row| Ok((row.get::<_, String>(0)?, row.get::<_, i64>(1)?))))?
+            .collect::<Result<Vec<_>, _>>())?;
 
-        let favicon = db_err!(stmt.query_row([slug], |row| {
-            Ok(Favicon {
-                id: row.get(0)?,
-                slug: row.get(1)?,
-                title: row.get(2)?,
-                target_domain: row.get(3)?,
-                published_url: row.get(4)?,
-                canonical_svg_key: row.get(5)?,
-                source_type: SourceType::from_str(&row.get::<_, String>(6)?).unwrap(),
-                source_original_mime: row.get(7)?,
-                source_hash: row.get(8)?,
-                source_size: row.get(9)?,
-                is_published: row.get::<_, i32>(10)? == 1,
-                created_at: DateTime::parse_from_rfc3339(&row.get::<_, String>(11)?)
-                    .unwrap().with_timezone(&Utc),
-                updated_at: DateTime::parse_from_rfc3339(&row.get::<_, String>(12)?)
-                    .unwrap().with_timezone(&Utc),
-                generated_at: row.get::<_, Option<String>>(13)?
-                    .map(|s| DateTime::parse_from_rfc3339(&s).unwrap().with_timezone(&Utc)),
-                generation_status: GenerationStatus::from_str(&row.get::<_, String>(14)?).unwrap(),
-                generation_error: row.get(15)?,
-                metadata: row.get(16)?,
-                has_steganography: row.get::<_, i32>(17)? == 1,
-            })
-        }).optional())?;
+        let assignments: Vec<String> = (0..PHASH_BANDS).map(|band| format!("phash_band{} = ?", band)).collect();
+        let sql = format!("UPDATE favicons SET {} WHERE id = ?", assignments.join(", "));
+        for (id, hash) in hashes {
+            let bands = phash_bands(hash as u64);
+            db_err!(self.conn.execute(&sql, params![
+                bands[0], bands[1], bands[2], bands[3], bands[4],
+                bands[5], bands[6], bands[7], bands[8],
+                id,
+            ]))?;
+        }
+        Ok(())
+    }
+
+    fn table_columns(&self, table: &str) -> Result<Vec<String>, HandlerError> {
+        let mut stmt = db_err!(self.conn.prepare(&format!("PRAGMA table_info({})", table)))?;
+        let columns = db_err!(db_err!(stmt.query_map([], |row| row.get::<_, String>(1)))?
+            .collect::<Result<Vec<_>, _>>())?;
+        Ok(columns)
+    }
+
+    fn add_column_if_missing(&self, table: &str, column: &str, definition: &str) -> Result<(), HandlerError> {
+        let columns = self.table_columns(table)?;
+
+        if !columns.iter().any(|c| c == column) {
+            db_err!(self.conn.execute_batch(&format!(
+                "ALTER TABLE {} ADD COLUMN {} {};",
+                table, column, definition
+            )))?;
+        }
+        Ok(())
+    }
+
+    pub fn get_favicon_by_id(&self, id: &str) -> Result<Option<Favicon>, HandlerError> {
+        let mut stmt = db_err!(self.conn.prepare(&format!(
+            "SELECT {} FROM favicons WHERE id = ?",
+            FAVICON_COLUMNS
+        )))?;
+
+        let favicon = db_err!(stmt.query_row([id], favicon_from_row).optional())?;
+
+        Ok(favicon)
+    }
+
+    pub fn get_favicon_by_slug(&self, slug: &str) -> Result<Option<Favicon>, HandlerError> {
+        let mut stmt = db_err!(self.conn.prepare(&format!(
+            "SELECT {} FROM favicons WHERE slug = ?",
+            FAVICON_COLUMNS
+        )))?;
+
+        let favicon = db_err!(stmt.query_row([slug], favicon_from_row).optional())?;
 
         Ok(favicon)
     }
 
-    pub fn find_duplicate(&self, hash: &str, size: i64) -> Result<Option<Favicon>, HandlerError> {
+    /// Published favicons with identical source bytes, oldest first. They
+    /// may have been generated with different settings. `hash` is a SHA-256
+    /// hex digest, so legacy MD5 hashes never match. Unpublished favicons
+    /// are left out, since whoever uploads the same bytes would get them back.
+    pub fn find_duplicates(&self, hash: &str, size: i64) -> Result<Vec<Favicon>, HandlerError> {
+        let mut stmt = db_err!(self.conn.prepare(&format!(
+            "SELECT {} FROM favicons
+             WHERE source_hash = ? AND source_size = ? AND is_published = 1
+             ORDER BY created_at ASC",
+            FAVICON_COLUMNS
+        )))?;
+
+        let favicons = db_err!(db_err!(stmt.query_map(params![hash, size], favicon_from_row))?
+            .collect::<Result<Vec<_>, _>>())?;
+
+        Ok(favicons)
+    }
+
+    /// Published favicons whose perceptual hash is within `max_distance` of
+    /// `hash`, closest first. SQLite has no popcount, so distances are
+    /// computed here. Below `PHASH_BANDS` only favicons sharing a band with
+    /// `hash` can match, and only those are read, through the band indexes;
+    /// wider searches compare every published hash.
+    pub fn find_similar(
+        &self,
+        hash: u64,
+        max_distance: u32,
+        exclude_id: &str,
+        limit: usize,
+    ) -> Result<Vec<(Favicon, u32)>, HandlerError> {
+        let band_filter = if max_distance < PHASH_BANDS as u32 {
+            let bands: Vec<String> = phash_bands(hash)
+                .iter()
+                .enumerate()
+                .map(|(band, value)| format!("phash_band{} = {}", band, value))
+                .collect();
+            format!(" AND ({})", bands.join(" OR "))
+        } else {
+            String::new()
+        };
+        let mut stmt = db_err!(self.conn.prepare(&format!(
+            "SELECT id, perceptual_hash FROM favicons
+             WHERE perceptual_hash IS NOT NULL AND is_published = 1 AND id != ?{}",
+            band_filter
+        )))?;
+        let candidates = db_err!(db_err!(stmt.query_map([exclude_id], |row| {
+            Ok((row.get::<_, String>(0)?, row.get::<_, i64>(1)? as u64))
+        }))?
+        .collect::<Result<Vec<_>, _>>())?;
+
+        let mut matches: Vec<(String, u32)> = candidates
+            .into_iter()
+            .map(|(id, other)| (id, hamming_distance(hash, other)))
+            .filter(|(_, distance)| *distance <= max_distance)
+            .collect();
+        matches.sort_by_key(|(_, distance)| *distance);
+        matches.truncate(limit);
+
+        let mut similar = Vec::with_capacity(matches.len());
+        for (id, distance) in matches {
+            if let Some(favicon) = self.get_favicon_by_id(&id)? {
+                similar.push((favicon, distance));
+            }
+        }
+        Ok(similar)
+    }
+
+    /// All `(id, perceptual_hash)` pairs, oldest favicon first
+    pub fn list_perceptual_hashes(&self) -> Result<Vec<(String, u64)>, HandlerError> {
         let mut stmt = db_err!(self.conn.prepare(
-            "SELECT id FROM favicons WHERE source_hash = ? AND source_size = ? LIMIT 1"
+            "SELECT id, perceptual_hash FROM favicons
+             WHERE perceptual_hash IS NOT NULL
+             ORDER BY created_at ASC"
         ))?;
 
-        let id: Option<String> = db_err!(stmt.query_row(params![hash, size], |row| row.get(0))
-            .optional())?;
+        let hashes = db_err!(db_err!(stmt.query_map([], |row| {
+            Ok((row.get::<_, String>(0)?, row.get::<_, i64>(1)? as u64))
+        }))?
+        .collect::<Result<Vec<_>, _>>())?;
 
-        match id {
-            Some(id) => self.get_favicon_by_id(&id),
-            None => Ok(None),
-        }
+        Ok(hashes)
+    }
+
+    /// The favicon to serve for a domain (in ASCII form): among published,
+    /// successfully generated favicons, a verified one, else the most recent
+    pub fn find_favicon_for_domain(&self, domain: &str) -> Result<Option<Favicon>, HandlerError> {
+        let mut stmt = db_err!(self.conn.prepare(&format!(
+            "SELECT {} FROM favicons
+             WHERE lower(target_domain) = lower(?) AND is_published = 1 AND generation_status = 'SUCCESS'
+             ORDER BY domain_verified_at IS NULL, created_at DESC
+             LIMIT 1",
+            FAVICON_COLUMNS
+        )))?;
+
+        let favicon = db_err!(stmt.query_row([domain], favicon_from_row).optional())?;
+        Ok(favicon)
+    }
+
+    /// Published favicons forked from `parent_id`, oldest first
+    pub fn list_forks(&self, parent_id: &str, limit: i64) -> Result<Vec<Favicon>, HandlerError> {
+        let mut stmt = db_err!(self.conn.prepare(&format!(
+            "SELECT {} FROM favicons WHERE parent_id = ? AND is_published = 1 ORDER BY created_at ASC LIMIT ?",
+            FAVICON_COLUMNS
+        )))?;
+
+        let favicons = db_err!(db_err!(stmt.query_map(params![parent_id, limit], favicon_from_row))?
+            .collect::<Result<Vec<_>, _>>())?;
+
+        Ok(favicons)
+    }
+
+    /// Favicons whose `source_hash` is still an MD5 digest, oldest first
+    pub fn list_legacy_hashed_favicons(&self, limit: i64) -> Result<Vec<Favicon>, HandlerError> {
+        let mut stmt = db_err!(self.conn.prepare(&format!(
+            "SELECT {} FROM favicons WHERE length(source_hash) = 32 ORDER BY created_at ASC LIMIT ?",
+            FAVICON_COLUMNS
+        )))?;
+
+        let favicons = db_err!(db_err!(stmt.query_map([limit], favicon_from_row))?
+            .collect::<Result<Vec<_>, _>>())?;
+
+        Ok(favicons)
+    }
+
+    pub fn count_legacy_hashed_favicons(&self) -> Result<i64, HandlerError> {
+        db_err!(self.conn.query_row(
+            "SELECT COUNT(*) FROM favicons WHERE length(source_hash) = 32",
+            [],
+            |row| row.get(0),
+        ))
+    }
+
+    /// Replace the content hash of a favicon. `None` clears a hash that can
+    /// no longer be verified, e.g. because the source is missing.
+    pub fn update_source_hash(&self, id: &str, hash: Option<&str>, size: Option<i64>) -> Result<(), HandlerError> {
+        db_err!(self.conn.execute(
+            "UPDATE favicons SET source_hash = ?, source_size = ? WHERE id = ?",
+            params![hash, size, id]
+        ))?;
+        Ok(())
     }
 
     pub fn insert_favicon(&self, favicon: &Favicon) -> Result<(), HandlerError> {
+        let bands = stored_phash_bands(favicon);
         db_err!(self.conn.execute(
             "INSERT INTO favicons (
                 id, slug, title, target_domain, published_url, canonical_svg_key,
                 source_type, source_original_mime, source_hash, source_size, is_published,
                 created_at, updated_at, generated_at, generation_status, generation_error,
-                metadata, has_steganography
-            ) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+                metadata, has_steganography, source_width, source_height, source_color_type,
+                perceptual_hash, theme_color, background_color, generation_options, dark_source_mime,
+                svg_method, has_canvas_document, parent_id, domain_verified_at, api_key_id,
+                phash_band0, phash_band1, phash_band2, phash_band3, phash_band4,
+                phash_band5, phash_band6, phash_band7, phash_band8
+            ) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?,
+                      ?, ?, ?, ?, ?, ?, ?, ?, ?)",
             params![
                 favicon.id,
                 favicon.slug,
@@ -183,18 +626,40 @@ impl Database {
                 favicon.generation_error,
                 favicon.metadata,
                 if favicon.has_steganography { 1 } else { 0 },
+                favicon.source_width,
+                favicon.source_height,
+                favicon.source_color_type,
+                favicon.perceptual_hash,
+                favicon.theme_color,
+                favicon.background_color,
+                favicon.generation_options.as_ref().map(GenerationOptions::to_json),
+                favicon.dark_source_mime,
+                favicon.svg_method.as_ref().map(SvgMethod::as_str),
+                if favicon.has_canvas_document { 1 } else { 0 },
+                favicon.parent_id,
+                favicon.domain_verified_at.map(|dt| dt.to_rfc3339()),
+                favicon.api_key_id,
+                bands[0], bands[1], bands[2], bands[3], bands[4],
+                bands[5], bands[6], bands[7], bands[8],
             ]
         ))?;
         Ok(())
     }
 
     pub fn update_favicon(&self, favicon: &Favicon) -> Result<(), HandlerError> {
+        let bands = stored_phash_bands(favicon);
         db_err!(self.conn.execute(
             "UPDATE favicons SET
                 slug = ?, title = ?, target_domain = ?, published_url = ?, canonical_svg_key = ?,
                 source_type = ?, source_original_mime = ?, source_hash = ?, source_size = ?,
                 is_published = ?, updated_at = ?, generated_at = ?, generation_status = ?,
-                generation_error = ?, metadata = ?, has_steganography = ?
+                generation_error = ?, metadata = ?, has_steganography = ?,
+                source_width = ?, source_height = ?, source_color_type = ?,
+                perceptual_hash = ?, theme_color = ?, background_color = ?,
+                generation_options = ?, dark_source_mime = ?, svg_method = ?,
+                has_canvas_document = ?, parent_id = ?, domain_verified_at = ?, api_key_id = ?,
+                phash_band0 = ?, phash_band1 = ?, phash_band2 = ?, phash_band3 = ?, phash_band4 = ?,
+                phash_band5 = ?, phash_band6 = ?, phash_band7 = ?, phash_band8 = ?
              WHERE id = ?",
             params![
                 favicon.slug,
@@ -213,20 +678,42 @@ impl Database {
                 favicon.generation_error,
                 favicon.metadata,
                 if favicon.has_steganography { 1 } else { 0 },
+                favicon.source_width,
+                favicon.source_height,
+                favicon.source_color_type,
+                favicon.perceptual_hash,
+                favicon.theme_color,
+                favicon.background_color,
+                favicon.generation_options.as_ref().map(GenerationOptions::to_json),
+                favicon.dark_source_mime,
+                favicon.svg_method.as_ref().map(SvgMethod::as_str),
+                if favicon.has_canvas_document { 1 } else { 0 },
+                favicon.parent_id,
+                favicon.domain_verified_at.map(|dt| dt.to_rfc3339()),
+                favicon.api_key_id,
+                bands[0], bands[1], bands[2], bands[3], bands[4],
+                bands[5], bands[6], bands[7], bands[8],
                 favicon.id,
             ]
         ))?;
         Ok(())
     }
 
+    /// Delete a favicon with its edit token and domain challenges, all or
+    /// nothing. Its forks are kept and become favicons without a parent.
     pub fn delete_favicon(&self, id: &str) -> Result<(), HandlerError> {
-        db_err!(self.conn.execute("DELETE FROM favicons WHERE id = ?", [id]))?;
+        let tx = db_err!(Transaction::new_unchecked(&self.conn, TransactionBehavior::Immediate))?;
+        db_err!(tx.execute("DELETE FROM edit_tokens WHERE favicon_id = ?", [id]))?;
+        db_err!(tx.execute("DELETE FROM domain_challenges WHERE favicon_id = ?", [id]))?;
+        db_err!(tx.execute("UPDATE favicons SET parent_id = NULL WHERE parent_id = ?", [id]))?;
+        db_err!(tx.execute("DELETE FROM favicons WHERE id = ?", [id]))?;
+        db_err!(tx.commit())?;
         Ok(())
     }
 
     pub fn get_assets_by_favicon_id(&self, favicon_id: &str) -> Result<Vec<FaviconAsset>, HandlerError> {
         let mut stmt = db_err!(self.conn.prepare(
-            "SELECT id, favicon_id, type, size, format, storage_key, mime_type, created_at
+            "SELECT id, favicon_id, type, size, format, storage_key, mime_type, created_at, variant
              FROM favicon_assets WHERE favicon_id = ?"
         ))?;
 
@@ -241,6 +728,7 @@ impl Database {
                 mime_type: row.get(6)?,
                 created_at: DateTime::parse_from_rfc3339(&row.get::<_, String>(7)?)
                     .unwrap().with_timezone(&Utc),
+                variant: AssetVariant::from_str(&row.get::<_, String>(8)?).unwrap_or_default(),
             })
         }))?
         .collect::<Result<Vec<_>, _>>())?;
@@ -250,8 +738,8 @@ impl Database {
 
     pub fn insert_asset(&self, asset: &FaviconAsset) -> Result<(), HandlerError> {
         db_err!(self.conn.execute(
-            "INSERT INTO favicon_assets (id, favicon_id, type, size, format, storage_key, mime_type, created_at)
-             VALUES (?, ?, ?, ?, ?, ?, ?, ?)",
+            "INSERT INTO favicon_assets (id, favicon_id, type, size, format, storage_key, mime_type, created_at, variant)
+             VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)",
             params![
                 asset.id,
                 asset.favicon_id,
@@ -261,6 +749,7 @@ impl Database {
                 asset.storage_key,
                 asset.mime_type,
                 asset.created_at.to_rfc3339(),
+                asset.variant.as_str(),
             ]
         ))?;
         Ok(())
@@ -297,7 +786,8 @@ impl Database {
         let offset = (page - 1) * page_size;
 
         let query = format!(
-            "SELECT id, slug, title, target_domain, published_url, created_at
+            "SELECT id, slug, title, target_domain, published_url, created_at,
+                    domain_verified_at IS NOT NULL
              FROM favicons WHERE is_published = 1
              ORDER BY {} {} LIMIT ? OFFSET ?",
             column, order
@@ -309,14 +799,679 @@ impl Database {
                 id: row.get(0)?,
                 slug: row.get(1)?,
                 title: row.get(2)?,
-                target_domain: row.get(3)?,
+                target_domain: row.get::<_, Option<String>>(3)?.as_deref().map(display_domain),
                 published_url: row.get(4)?,
                 created_at: row.get(5)?,
+                domain_verified: row.get(6)?,
             })
         }))?
         .collect::<Result<Vec<_>, _>>())?;
 
         Ok((items, total))
     }
+
+    pub fn insert_upload_intent(&self, intent: &UploadIntent) -> Result<(), HandlerError> {
+        db_err!(self.conn.execute(
+            "INSERT INTO upload_intents (
+                id, staging_key, content_type, title, target_domain, metadata,
+                theme_color, background_color, expires_at, created_at, api_key_id,
+                secret_hash, favicon_id, generation_options
+             ) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+            params![
+                intent.id,
+                intent.staging_key,
+                intent.content_type,
+                intent.title,
+                intent.target_domain,
+                intent.metadata,
+                intent.theme_color,
+                intent.background_color,
+                intent.expires_at.to_rfc3339(),
+                intent.created_at.to_rfc3339(),
+                intent.api_key_id,
+                intent.secret_hash,
+                intent.favicon_id,
+                intent.generation_options.as_ref().map(GenerationOptions::to_json),
+            ]
+        ))?;
+        Ok(())
+    }
+
+    pub fn get_upload_intent(&self, id: &str) -> Result<Option<UploadIntent>, HandlerError> {
+        let mut stmt = db_err!(self.conn.prepare(
+            "SELECT id, staging_key, content_type, title, target_domain, metadata,
+                    theme_color, background_color, expires_at, created_at, api_key_id,
+                    secret_hash, favicon_id, generation_options
+             FROM upload_intents WHERE id = ?"
+        ))?;
+
+        let intent = db_err!(stmt.query_row([id], |row| {
+            Ok(UploadIntent {
+                id: row.get(0)?,
+                staging_key: row.get(1)?,
+                content_type: row.get(2)?,
+                title: row.get(3)?,
+                target_domain: row.get(4)?,
+                metadata: row.get(5)?,
+                theme_color: row.get(6)?,
+                background_color: row.get(7)?,
+                expires_at: DateTime::parse_from_rfc3339(&row.get::<_, String>(8)?)
+                    .unwrap().with_timezone(&Utc),
+                created_at: DateTime::parse_from_rfc3339(&row.get::<_, String>(9)?)
+                    .unwrap().with_timezone(&Utc),
+                api_key_id: row.get(10)?,
+                secret_hash: row.get(11)?,
+                favicon_id: row.get(12)?,
+                generation_options: row.get::<_, Option<String>>(13)?
+                    .and_then(|json| GenerationOptions::from_json(&json)),
+            })
+        }).optional())?;
+
+        Ok(intent)
+    }
+
+    pub fn delete_upload_intent(&self, id: &str) -> Result<(), HandlerError> {
+        db_err!(self.conn.execute("DELETE FROM upload_intents WHERE id = ?", [id]))?;
+        Ok(())
+    }
+
+    /// Record the favicon an intent was finalized into. The intent is kept
+    /// until it expires so that finalizing again returns the same favicon.
+    pub fn finalize_upload_intent(&self, id: &str, favicon_id: &str) -> Result<(), HandlerError> {
+        db_err!(self.conn.execute(
+            "UPDATE upload_intents SET favicon_id = ? WHERE id = ?",
+            params![favicon_id, id]
+        ))?;
+        Ok(())
+    }
+
+    /// Remove intents that expired before `now` and return the staging keys
+    /// of those never finalized, so the caller can delete any objects that
+    /// were uploaded for them.
+    pub fn delete_expired_upload_intents(&self, now: DateTime<Utc>) -> Result<Vec<String>, HandlerError> {
+        let mut stmt = db_err!(self.conn.prepare(
+            "SELECT staging_key FROM upload_intents WHERE expires_at < ? AND favicon_id IS NULL"
+        ))?;
+        let keys = db_err!(db_err!(stmt.query_map([now.to_rfc3339()], |row| row.get(0)))?
+            .collect::<Result<Vec<String>, _>>())?;
+
+        db_err!(self.conn.execute(
+            "DELETE FROM upload_intents WHERE expires_at < ?",
+            [now.to_rfc3339()]
+        ))?;
+
+        Ok(keys)
+    }
+
+    /// Store the SHA-256 hex digest of a favicon's edit token, replacing any
+    /// earlier token
+    pub fn insert_edit_token(&self, favicon_id: &str, token_hash: &str, now: DateTime<Utc>) -> Result<(), HandlerError> {
+        db_err!(self.conn.execute(
+            "INSERT OR REPLACE INTO edit_tokens (favicon_id, token_hash, created_at, revoked_at)
+             VALUES (?, ?, ?, NULL)",
+            params![favicon_id, token_hash, now.to_rfc3339()]
+        ))?;
+        Ok(())
+    }
+
+    /// Hash of the favicon's edit token, unless it was revoked
+    pub fn get_edit_token_hash(&self, favicon_id: &str) -> Result<Option<String>, HandlerError> {
+        let hash = db_err!(self.conn.query_row(
+            "SELECT token_hash FROM edit_tokens WHERE favicon_id = ? AND revoked_at IS NULL",
+            [favicon_id],
+            |row| row.get(0)
+        ).optional())?;
+        Ok(hash)
+    }
+
+    /// Revoke the favicon's edit token. Returns false if it had none or it
+    /// was already revoked.
+    pub fn revoke_edit_token(&self, favicon_id: &str, now: DateTime<Utc>) -> Result<bool, HandlerError> {
+        let revoked = db_err!(self.conn.execute(
+            "UPDATE edit_tokens SET revoked_at = ? WHERE favicon_id = ? AND revoked_at IS NULL",
+            params![now.to_rfc3339(), favicon_id]
+        ))?;
+        Ok(revoked > 0)
+    }
+
+    /// Store a favicon's domain challenge, replacing any earlier one
+    pub fn insert_domain_challenge(&self, challenge: &DomainChallenge) -> Result<(), HandlerError> {
+        db_err!(self.conn.execute(
+            "INSERT OR REPLACE INTO domain_challenges (favicon_id, domain, token, created_at)
+             VALUES (?, ?, ?, ?)",
+            params![
+                challenge.favicon_id,
+                challenge.domain,
+                challenge.token,
+                challenge.created_at.to_rfc3339(),
+            ]
+        ))?;
+        Ok(())
+    }
+
+    pub fn get_domain_challenge(&self, favicon_id: &str) -> Result<Option<DomainChallenge>, HandlerError> {
+        let challenge = db_err!(self.conn.query_row(
+            "SELECT favicon_id, domain, token, created_at FROM domain_challenges WHERE favicon_id = ?",
+            [favicon_id],
+            |row| {
+                Ok(DomainChallenge {
+                    favicon_id: row.get(0)?,
+                    domain: row.get(1)?,
+                    token: row.get(2)?,
+                    created_at: DateTime::parse_from_rfc3339(&row.get::<_, String>(3)?)
+                        .unwrap().with_timezone(&Utc),
+                })
+            }
+        ).optional())?;
+        Ok(challenge)
+    }
+
+    /// Mark the favicon's domain as verified, taking the badge away from any
+    /// other favicon verified for the same domain
+    pub fn mark_domain_verified(&self, favicon_id: &str, domain: &str, now: DateTime<Utc>) -> Result<(), HandlerError> {
+        let tx = db_err!(self.conn.unchecked_transaction())?;
+        db_err!(tx.execute(
+            "UPDATE favicons SET domain_verified_at = NULL
+             WHERE lower(target_domain) = lower(?) AND id != ? AND domain_verified_at IS NOT NULL",
+            params![domain, favicon_id]
+        ))?;
+        db_err!(tx.execute(
+            "UPDATE favicons SET domain_verified_at = ? WHERE id = ?",
+            params![now.to_rfc3339(), favicon_id]
+        ))?;
+        db_err!(tx.commit())?;
+        Ok(())
+    }
+
+    /// Replace a client's rate limit state for an endpoint with `update`
+    /// applied to it. The write lock is taken before reading, so concurrent
+    /// handler processes cannot both spend the same token. Rows of days
+    /// before `today` are pruned first.
+    pub fn update_rate_limit<T>(
+        &self,
+        endpoint: &str,
+        client: &str,
+        today: &str,
+        update: impl FnOnce(Option<RateLimitState>) -> (RateLimitState, T),
+    ) -> Result<T, HandlerError> {
+        let tx = db_err!(Transaction::new_unchecked(&self.conn, TransactionBehavior::Immediate))?;
+        db_err!(tx.execute("DELETE FROM rate_limits WHERE day < ?", [today]))?;
+
+        let state = db_err!(tx.query_row(
+            "SELECT tokens, refilled_at, day, requests, bytes FROM rate_limits WHERE endpoint = ? AND client = ?",
+            params![endpoint, client],
+            |row| Ok(RateLimitState {
+                tokens: row.get(0)?,
+                refilled_at: DateTime::parse_from_rfc3339(&row.get::<_, String>(1)?).unwrap().with_timezone(&Utc),
+                day: row.get(2)?,
+                requests: row.get(3)?,
+                bytes: row.get(4)?,
+            }),
+        ).optional())?;
+
+        let (state, result) = update(state);
+        db_err!(tx.execute(
+            "INSERT OR REPLACE INTO rate_limits (endpoint, client, tokens, refilled_at, day, requests, bytes)
+             VALUES (?, ?, ?, ?, ?, ?, ?)",
+            params![endpoint, client, state.tokens, state.refilled_at.to_rfc3339(), state.day, state.requests, state.bytes]
+        ))?;
+        db_err!(tx.commit())?;
+        Ok(result)
+    }
+
+    pub fn insert_api_key(&self, api_key: &ApiKey) -> Result<(), HandlerError> {
+        let scopes: Vec<&str> = api_key.scopes.iter().map(ApiKeyScope::as_str).collect();
+        db_err!(self.conn.execute(
+            "INSERT INTO api_keys (id, name, key_hash, prefix, scopes, created_at, expires_at, last_used_at, revoked_at)
+             VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)",
+            params![
+                api_key.id,
+                api_key.name,
+                api_key.key_hash,
+                api_key.prefix,
+                scopes.join(" "),
+                api_key.created_at.to_rfc3339(),
+                api_key.expires_at.map(|dt| dt.to_rfc3339()),
+                api_key.last_used_at.map(|dt| dt.to_rfc3339()),
+                api_key.revoked_at.map(|dt| dt.to_rfc3339()),
+            ]
+        ))?;
+        Ok(())
+    }
+
+    pub fn get_api_key_by_id(&self, id: &str) -> Result<Option<ApiKey>, HandlerError> {
+        let api_key = db_err!(self.conn.query_row(
+            &format!("SELECT {} FROM api_keys WHERE id = ?", API_KEY_COLUMNS),
+            [id],
+            api_key_from_row
+        ).optional())?;
+        Ok(api_key)
+    }
+
+    /// Look a key up by the SHA-256 hex digest of the key, whether or not
+    /// it is still active
+    pub fn get_api_key_by_hash(&self, key_hash: &str) -> Result<Option<ApiKey>, HandlerError> {
+        let api_key = db_err!(self.conn.query_row(
+            &format!("SELECT {} FROM api_keys WHERE key_hash = ?", API_KEY_COLUMNS),
+            [key_hash],
+            api_key_from_row
+        ).optional())?;
+        Ok(api_key)
+    }
+
+    /// All keys, newest first, including expired and revoked ones
+    pub fn list_api_keys(&self) -> Result<Vec<ApiKey>, HandlerError> {
+        let mut stmt = db_err!(self.conn.prepare(&format!(
+            "SELECT {} FROM api_keys ORDER BY created_at DESC",
+            API_KEY_COLUMNS
+        )))?;
+        let api_keys = db_err!(db_err!(stmt.query_map([], api_key_from_row))?
+            .collect::<Result<Vec<_>, _>>())?;
+        Ok(api_keys)
+    }
+
+    pub fn touch_api_key(&self, id: &str, now: DateTime<Utc>) -> Result<(), HandlerError> {
+        db_err!(self.conn.execute(
+            "UPDATE api_keys SET last_used_at = ? WHERE id = ?",
+            params![now.to_rfc3339(), id]
+        ))?;
+        Ok(())
+    }
+
+    /// Revoke a key. Returns false if it was already revoked.
+    pub fn revoke_api_key(&self, id: &str, now: DateTime<Utc>) -> Result<bool, HandlerError> {
+        let revoked = db_err!(self.conn.execute(
+            "UPDATE api_keys SET revoked_at = ? WHERE id = ? AND revoked_at IS NULL",
+            params![now.to_rfc3339(), id]
+        ))?;
+        Ok(revoked > 0)
+    }
+
+    pub fn insert_webhook(&self, webhook: &Webhook) -> Result<(), HandlerError> {
+        let events: Vec<&str> = webhook.events.iter().map(WebhookEvent::as_str).collect();
+        db_err!(self.conn.execute(
+            "INSERT INTO webhooks (id, url, events, secret, created_at) VALUES (?, ?, ?, ?, ?)",
+            params![webhook.id, webhook.url, events.join(" "), webhook.secret, webhook.created_at.to_rfc3339()]
+        ))?;
+        Ok(())
+    }
+
+    /// All webhooks, oldest first
+    pub fn list_webhooks(&self) -> Result<Vec<Webhook>, HandlerError> {
+        let mut stmt = db_err!(self.conn.prepare(
+            "SELECT id, url, events, secret, created_at FROM webhooks ORDER BY created_at ASC"
+        ))?;
+        let webhooks = db_err!(db_err!(stmt.query_map([], webhook_from_row))?
+            .collect::<Result<Vec<_>, _>>())?;
+        Ok(webhooks)
+    }
+
+    pub fn get_webhook_by_id(&self, id: &str) -> Result<Option<Webhook>, HandlerError> {
+        let webhook = db_err!(self.conn.query_row(
+            "SELECT id, url, events, secret, created_at FROM webhooks WHERE id = ?",
+            [id],
+            webhook_from_row
+        ).optional())?;
+        Ok(webhook)
+    }
+
+    /// Delete a webhook and its outbox, delivered or not. Returns false if
+    /// it did not exist.
+    pub fn delete_webhook(&self, id: &str) -> Result<bool, HandlerError> {
+        let tx = db_err!(self.conn.unchecked_transaction())?;
+        db_err!(tx.execute("DELETE FROM webhook_deliveries WHERE webhook_id = ?", [id]))?;
+        let deleted = db_err!(tx.execute("DELETE FROM webhooks WHERE id = ?", [id]))?;
+        db_err!(tx.commit())?;
+        Ok(deleted > 0)
+    }
+
+    pub fn insert_webhook_delivery(&self, delivery: &WebhookDelivery) -> Result<(), HandlerError> {
+        db_err!(self.conn.execute(
+            &format!("INSERT INTO webhook_deliveries ({}) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?)", WEBHOOK_DELIVERY_COLUMNS),
+            params![
+                delivery.id,
+                delivery.webhook_id,
+                delivery.event.as_str(),
+                delivery.payload,
+                delivery.status.as_str(),
+                delivery.attempts,
+                delivery.next_attempt_at.to_rfc3339(),
+                delivery.last_error,
+                delivery.created_at.to_rfc3339(),
+                delivery.delivered_at.map(|dt| dt.to_rfc3339()),
+            ]
+        ))?;
+        Ok(())
+    }
+
+    /// Take up to `limit` pending deliveries due at `now`, oldest first, and
+    /// push them back to `lease_until` so concurrent handler processes do not
+    /// attempt them too. A delivery whose attempt never reports back becomes
+    /// due again once the lease runs out.
+    pub fn claim_due_webhook_deliveries(
+        &self,
+        now: DateTime<Utc>,
+        lease_until: DateTime<Utc>,
+        limit: usize,
+    ) -> Result<Vec<WebhookDelivery>, HandlerError> {
+        let tx = db_err!(Transaction::new_unchecked(&self.conn, TransactionBehavior::Immediate))?;
+        let deliveries = {
+            let mut stmt = db_err!(tx.prepare(&format!(
+                "SELECT {} FROM webhook_deliveries
+                 WHERE status = 'PENDING' AND next_attempt_at <= ?
+                 ORDER BY next_attempt_at ASC LIMIT ?",
+                WEBHOOK_DELIVERY_COLUMNS
+            )))?;
+            let deliveries = db_err!(db_err!(stmt.query_map(params![now.to_rfc3339(), limit as i64], webhook_delivery_from_row))?
+                .collect::<Result<Vec<_>, _>>())?;
+            deliveries
+        };
+        for delivery in &deliveries {
+            db_err!(tx.execute(
+                "UPDATE webhook_deliveries SET next_attempt_at = ? WHERE id = ?",
+                params![lease_until.to_rfc3339(), delivery.id]
+            ))?;
+        }
+        db_err!(tx.commit())?;
+        Ok(deliveries)
+    }
+
+    /// Record the outcome of an attempt
+    pub fn update_webhook_delivery(&self, delivery: &WebhookDelivery) -> Result<(), HandlerError> {
+        db_err!(self.conn.execute(
+            "UPDATE webhook_deliveries SET
+                status = ?, attempts = ?, next_attempt_at = ?, last_error = ?, delivered_at = ?
+             WHERE id = ?",
+            params![
+                delivery.status.as_str(),
+                delivery.attempts,
+                delivery.next_attempt_at.to_rfc3339(),
+                delivery.last_error,
+                delivery.delivered_at.map(|dt| dt.to_rfc3339()),
+                delivery.id,
+            ]
+        ))?;
+        Ok(())
+    }
+
+    pub fn get_webhook_delivery(&self, id: &str) -> Result<Option<WebhookDelivery>, HandlerError> {
+        let delivery = db_err!(self.conn.query_row(
+            &format!("SELECT {} FROM webhook_deliveries WHERE id = ?", WEBHOOK_DELIVERY_COLUMNS),
+            [id],
+            webhook_delivery_from_row
+        ).optional())?;
+        Ok(delivery)
+    }
+
+    /// A webhook's deliveries by status, with the most recent error
+    pub fn get_webhook_delivery_counts(&self, webhook_id: &str) -> Result<WebhookDeliveryCounts, HandlerError> {
+        let mut counts = WebhookDeliveryCounts::default();
+        let mut stmt = db_err!(self.conn.prepare(
+            "SELECT status, COUNT(*) FROM webhook_deliveries WHERE webhook_id = ? GROUP BY status"
+        ))?;
+        let rows = db_err!(db_err!(stmt.query_map([webhook_id], |row| Ok((row.get::<_, String>(0)?, row.get::<_, i64>(1)?))))?
+            .collect::<Result<Vec<_>, _>>())?;
+        for (status, count) in rows {
+            match DeliveryStatus::from_str(&status) {
+                Some(DeliveryStatus::Pending) => counts.pending = count,
+                Some(DeliveryStatus::Delivered) => counts.delivered = count,
+                Some(DeliveryStatus::Failed) => counts.failed = count,
+                None => {}
+            }
+        }
+
+        counts.last_error = db_err!(self.conn.query_row(
+            "SELECT last_error FROM webhook_deliveries
+             WHERE webhook_id = ? AND status != 'DELIVERED' AND last_error IS NOT NULL
+             ORDER BY created_at DESC LIMIT 1",
+            [webhook_id],
+            |row| row.get(0)
+        ).optional())?;
+        Ok(counts)
+    }
+
+    /// Number of favicons created with each key, by key id
+    pub fn count_favicons_by_api_key(&self) -> Result<Vec<(String, i64)>, HandlerError> {
+        let mut stmt = db_err!(self.conn.prepare(
+            "SELECT api_key_id, COUNT(*) FROM favicons WHERE api_key_id IS NOT NULL GROUP BY api_key_id"
+        ))?;
+        let counts = db_err!(db_err!(stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?))))?
+            .collect::<Result<Vec<_>, _>>())?;
+        Ok(counts)
+    }
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::NamedTempFile;
+
+    #[test]
+    fn test_source_type_check_is_widened() {
+        let file = NamedTempFile::new().unwrap();
+        {
+            // The favicons table as first released, with one row
+            let conn = Connection::open(file.path()).unwrap();
+            conn.execute_batch(
+                "CREATE TABLE favicons (
+                    id TEXT PRIMARY KEY,
+                    slug TEXT UNIQUE NOT NULL,
+                    title TEXT,
+                    target_domain TEXT,
+                    published_url TEXT NOT NULL,
+                    canonical_svg_key TEXT,
+                    source_type TEXT NOT NULL CHECK(source_type IN ('UPLOAD', 'CANVAS')),
+                    source_original_mime TEXT,
+                    source_hash TEXT,
+                    source_size INTEGER,
+                    is_published INTEGER NOT NULL DEFAULT 0,
+                    created_at TEXT NOT NULL,
+                    updated_at TEXT NOT NULL,
+                    generated_at TEXT,
+                    generation_status TEXT NOT NULL CHECK(generation_status IN ('PENDING', 'SUCCESS', 'FAILED')),
+                    generation_error TEXT,
+                    metadata TEXT,
+                    has_steganography INTEGER NOT NULL DEFAULT 0
+                );
+                CREATE INDEX idx_favicons_created_at ON favicons(created_at);
+                INSERT INTO favicons (id, slug, published_url, source_type, created_at, updated_at, generation_status)
+                VALUES ('old', 'old-slug', '/f/old-slug', 'UPLOAD', '2024-01-01T00:00:00Z', '2024-01-01T00:00:00Z', 'SUCCESS');",
+            )
+            .unwrap();
+        }
+
+        let db = Database::new(file.path()).unwrap();
+        let mut favicon = db.get_favicon_by_id("old").unwrap().unwrap();
+        assert_eq!(favicon.slug, "old-slug");
+
+        favicon.id = "imported".to_string();
+        favicon.slug = "imported-slug".to_string();
+        favicon.source_type = SourceType::Import;
+        db.insert_favicon(&favicon).unwrap();
+        assert_eq!(db.get_favicon_by_id("imported").unwrap().unwrap().source_type.as_str(), "IMPORT");
+
+        // Indexes survive the rebuild, and opening again leaves the table alone
+        let indexes: i64 = db.conn.query_row(
+            "SELECT COUNT(*) FROM sqlite_master WHERE type = 'index' AND name = 'idx_favicons_verified_domain'",
+            [],
+            |row| row.get(0),
+        ).unwrap();
+        assert_eq!(indexes, 1);
+        assert_eq!(db.schema_version().unwrap(), SCHEMA_VERSION);
+
+        // Once the version is recorded, opening again skips the migration
+        db.conn.execute_batch("DROP INDEX idx_favicons_parent_id").unwrap();
+        drop(db);
+        let db = Database::new(file.path()).unwrap();
+        assert!(db.get_favicon_by_id("old").unwrap().is_some());
+        let indexes: i64 = db.conn.query_row(
+            "SELECT COUNT(*) FROM sqlite_master WHERE type = 'index' AND name = 'idx_favicons_parent_id'",
+            [],
+            |row| row.get(0),
+        ).unwrap();
+        assert_eq!(indexes, 0);
+    }
+
+    #[test]
+    fn test_find_similar_skips_unpublished() {
+        let file = NamedTempFile::new().unwrap();
+        let db = Database::new(file.path()).unwrap();
+
+        // One bit in each of 8 bands: within the distance, sharing one band
+        let spread = (0..8).fold(0i64, |hash, band| hash | 1 << (band * 7));
+        let favicons = [
+            ("new", 0b0000, true),
+            ("close", 0b0011, true),
+            ("spread", spread, true),
+            ("hidden", 0b0001, false),
+            ("far", -1, true),
+        ];
+        for (id, hash, is_published) in favicons {
+            db.insert_favicon(&Favicon {
+                id: id.to_string(),
+                slug: format!("slug-{}", id),
+                published_url: format!("/f/slug-{}", id),
+                is_published,
+                perceptual_hash: Some(hash),
+                ..Favicon::test_default()
+            }).unwrap();
+        }
+
+        let similar = db.find_similar(0, 8, "new", 10).unwrap();
+        let ids: Vec<(&str, u32)> = similar
+            .iter()
+            .map(|(favicon, distance)| (favicon.id.as_str(), *distance))
+            .collect();
+        assert_eq!(ids, vec![("close", 2), ("spread", 8)]);
+
+        // Wider searches compare every hash
+        let similar = db.find_similar(0, 64, "new", 10).unwrap();
+        assert_eq!(similar.len(), 3);
+    }
+
+    #[test]
+    fn test_phash_bands_are_backfilled() {
+        let file = NamedTempFile::new().unwrap();
+        {
+            let db = Database::new(file.path()).unwrap();
+            for (id, hash) in [("new", 0), ("old", 0b0101)] {
+                db.insert_favicon(&Favicon {
+                    id: id.to_string(),
+                    slug: format!("slug-{}", id),
+                    published_url: format!("/f/slug-{}", id),
+                    perceptual_hash: Some(hash),
+                    ..Favicon::test_default()
+                }).unwrap();
+            }
+            // As stored before the bands were indexed
+            db.conn.execute_batch(
+                "UPDATE favicons SET phash_band0 = NULL, phash_band1 = NULL, phash_band2 = NULL,
+                     phash_band3 = NULL, phash_band4 = NULL, phash_band5 = NULL, phash_band6 = NULL,
+                     phash_band7 = NULL, phash_band8 = NULL;
+                 PRAGMA user_version = 1;",
+            ).unwrap();
+            assert!(db.find_similar(0, 8, "new", 10).unwrap().is_empty());
+        }
+
+        let db = Database::new(file.path()).unwrap();
+        let similar = db.find_similar(0, 8, "new", 10).unwrap();
+        assert_eq!(similar.len(), 1);
+        assert_eq!(similar[0].0.id, "old");
+    }
+
+    #[test]
+    fn test_find_duplicates_by_generation_settings() {
+        let file = NamedTempFile::new().unwrap();
+        let db = Database::new(file.path()).unwrap();
+
+        let settings = [("plain", None, true), ("dark", Some("#1a2b3c"), true), ("hidden", Some("#000000"), false)];
+        for (id, theme_color, is_published) in settings {
+            db.insert_favicon(&Favicon {
+                id: id.to_string(),
+                slug: format!("slug-{}", id),
+                published_url: format!("/f/slug-{}", id),
+                source_hash: Some("hash".to_string()),
+                source_size: Some(10),
+                theme_color: theme_color.map(str::to_string),
+                is_published,
+                ..Favicon::test_default()
+            }).unwrap();
+        }
+
+        let duplicates = db.find_duplicates("hash", 10).unwrap();
+        assert_eq!(duplicates.len(), 2);
+        let reusable = |theme_color, background_color| {
+            duplicates
+                .iter()
+                .find(|existing| existing.generated_with(None, theme_color, background_color))
+                .map(|existing| existing.id.as_str())
+        };
+
+        // Unset colors match their defaults
+        assert_eq!(reusable(None, None), Some("plain"));
+        assert_eq!(reusable(Some("#ffffff"), Some("#ffffff")), Some("plain"));
+        assert_eq!(reusable(Some("#1a2b3c"), None), Some("dark"));
+        // Unpublished favicons are never reused
+        assert_eq!(reusable(Some("#000000"), None), None);
+        assert_eq!(reusable(None, Some("#000000")), None);
+        assert!(db.find_duplicates("hash", 11).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_upload_intent_keeps_generation_options() {
+        let file = NamedTempFile::new().unwrap();
+        let db = Database::new(file.path()).unwrap();
+        let now = Utc::now();
+
+        let generation_options = GenerationOptions { corner_radius: Some(50), ..GenerationOptions::default() };
+        db.insert_upload_intent(&UploadIntent {
+            id: "intent".to_string(),
+            staging_key: "uploads/intent/original".to_string(),
+            content_type: "image/png".to_string(),
+            title: None,
+            target_domain: None,
+            metadata: None,
+            theme_color: None,
+            background_color: None,
+            generation_options: Some(generation_options.clone()),
+            expires_at: now,
+            created_at: now,
+            api_key_id: None,
+            secret_hash: "hash".to_string(),
+            favicon_id: None,
+        }).unwrap();
+
+        let intent = db.get_upload_intent("intent").unwrap().unwrap();
+        assert_eq!(intent.generation_options, Some(generation_options));
+        assert_eq!(intent.secret_hash, "hash");
+    }
+
+    #[test]
+    fn test_delete_favicon_keeps_forks() {
+        let file = NamedTempFile::new().unwrap();
+        let db = Database::new(file.path()).unwrap();
+        let now = Utc::now();
+
+        db.insert_favicon(&Favicon::test_default()).unwrap();
+        db.insert_favicon(&Favicon {
+            id: "fork".to_string(),
+            slug: "fork-slug".to_string(),
+            published_url: "/f/fork-slug".to_string(),
+            parent_id: Some("abc".to_string()),
+            ..Favicon::test_default()
+        }).unwrap();
+        db.insert_edit_token("abc", "hash", now).unwrap();
+        db.insert_domain_challenge(&DomainChallenge {
+            favicon_id: "abc".to_string(),
+            domain: "example.com".to_string(),
+            token: "token".to_string(),
+            created_at: now,
+        }).unwrap();
+
+        db.delete_favicon("abc").unwrap();
+        assert!(db.get_favicon_by_id("abc").unwrap().is_none());
+        assert!(db.get_edit_token_hash("abc").unwrap().is_none());
+        assert!(db.get_domain_challenge("abc").unwrap().is_none());
+        assert_eq!(db.get_favicon_by_id("fork").unwrap().unwrap().parent_id, None);
+    }
+}
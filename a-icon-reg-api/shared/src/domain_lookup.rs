@@ -0,0 +1,95 @@
+use crate::database::Database;
+use crate::error::HandlerError;
+use crate::models::Favicon;
+use crate::validation::is_public_suffix;
+
+/// Domains to try for a lookup, most specific first: the domain itself, then
+/// each parent domain with at least two labels. Public suffixes are skipped
+/// when a Public Suffix List is configured.
+pub fn lookup_candidates(domain: &str) -> Result<Vec<String>, HandlerError> {
+    let labels: Vec<&str> = domain.split('.').collect();
+    let mut candidates = Vec::new();
+
+    for start in 0..labels.len().saturating_sub(1) {
+        let candidate = labels[start..].join(".");
+        if start > 0 && is_public_suffix(&candidate)? {
+            break;
+        }
+        candidates.push(candidate);
+    }
+    Ok(candidates)
+}
+
+/// The best favicon for a domain (in ASCII form) and the domain it was found
+/// under. The closest domain with a favicon wins; within a domain, a verified
+/// favicon is preferred over the most recent one.
+pub fn find_domain_favicon(db: &Database, domain: &str) -> Result<Option<(Favicon, String)>, HandlerError> {
+    for candidate in lookup_candidates(domain)? {
+        if let Some(favicon) = db.find_favicon_for_domain(&candidate)? {
+            return Ok(Some((favicon, candidate)));
+        }
+    }
+    Ok(None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::GenerationStatus;
+    use chrono::{Duration, Utc};
+    use tempfile::NamedTempFile;
+
+    fn favicon(id: &str, domain: &str, age_minutes: i64) -> Favicon {
+        let created_at = Utc::now() - Duration::minutes(age_minutes);
+        Favicon {
+            id: id.to_string(),
+            slug: format!("slug-{}", id),
+            target_domain: Some(domain.to_string()),
+            published_url: format!("/f/slug-{}", id),
+            created_at,
+            updated_at: created_at,
+            ..Favicon::test_default()
+        }
+    }
+
+    #[test]
+    fn test_lookup_candidates() {
+        assert_eq!(lookup_candidates("a.b.example.com").unwrap(), vec![
+            "a.b.example.com",
+            "b.example.com",
+            "example.com",
+        ]);
+        assert_eq!(lookup_candidates("example.com").unwrap(), vec!["example.com"]);
+    }
+
+    #[test]
+    fn test_find_domain_favicon() {
+        let file = NamedTempFile::new().unwrap();
+        let db = Database::new(file.path()).unwrap();
+
+        let mut verified = favicon("verified", "example.com", 30);
+        verified.domain_verified_at = Some(Utc::now());
+        db.insert_favicon(&verified).unwrap();
+        db.insert_favicon(&favicon("recent", "Example.com", 1)).unwrap();
+        let mut hidden = favicon("hidden", "www.example.com", 0);
+        hidden.is_published = false;
+        db.insert_favicon(&hidden).unwrap();
+        let mut failed = favicon("failed", "www.example.com", 0);
+        failed.generation_status = GenerationStatus::Failed;
+        db.insert_favicon(&failed).unwrap();
+        db.insert_favicon(&favicon("older", "blog.example.com", 20)).unwrap();
+        db.insert_favicon(&favicon("newer", "blog.example.com", 10)).unwrap();
+
+        let (found, matched) = find_domain_favicon(&db, "example.com").unwrap().unwrap();
+        assert_eq!((found.id.as_str(), matched.as_str()), ("verified", "example.com"));
+
+        // Unpublished and failed favicons are skipped, falling back to the parent
+        let (found, matched) = find_domain_favicon(&db, "www.example.com").unwrap().unwrap();
+        assert_eq!((found.id.as_str(), matched.as_str()), ("verified", "example.com"));
+
+        let (found, matched) = find_domain_favicon(&db, "new.blog.example.com").unwrap().unwrap();
+        assert_eq!((found.id.as_str(), matched.as_str()), ("newer", "blog.example.com"));
+
+        assert!(find_domain_favicon(&db, "example.org").unwrap().is_none());
+    }
+}
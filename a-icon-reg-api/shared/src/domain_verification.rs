@@ -0,0 +1,313 @@
+use crate::database::Database;
+use crate::error::HandlerError;
+use crate::import::PublicAddressResolver;
+use crate::models::{DomainChallenge, Favicon};
+use chrono::{DateTime, Utc};
+use hickory_resolver::error::ResolveErrorKind;
+use hickory_resolver::Resolver;
+use std::io::Read;
+use std::time::Duration;
+use uuid::Uuid;
+
+/// Prefix of the TXT record value that carries the token
+pub const TXT_RECORD_PREFIX: &str = "a-icon-verification=";
+
+/// Path of the file that carries the token
+pub const WELL_KNOWN_PATH: &str = "/.well-known/a-icon-verification.txt";
+
+/// Largest well-known file read; it only needs to hold the 32-character token
+const MAX_WELL_KNOWN_BYTES: u64 = 1024;
+
+const FETCH_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Looks up TXT records. Implemented over the system resolver in production
+/// and by fixed tables in tests.
+pub trait DnsResolver {
+    /// Each record's strings joined together; empty if the name has none
+    fn txt_records(&self, name: &str) -> Result<Vec<String>, String>;
+}
+
+/// Fetches a small text file over HTTPS
+pub trait HttpFetcher {
+    fn fetch_text(&self, url: &str) -> Result<String, String>;
+}
+
+/// Where the token was found
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VerificationMethod {
+    DnsTxt,
+    WellKnown,
+}
+
+impl VerificationMethod {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            VerificationMethod::DnsTxt => "DNS_TXT",
+            VerificationMethod::WellKnown => "WELL_KNOWN",
+        }
+    }
+}
+
+/// Value of the TXT record to publish at the domain itself
+pub fn txt_record_value(challenge: &DomainChallenge) -> String {
+    format!("{}{}", TXT_RECORD_PREFIX, challenge.token)
+}
+
+/// URL of the file to publish, containing just the token
+pub fn well_known_url(challenge: &DomainChallenge) -> String {
+    format!("https://{}{}", challenge.domain, WELL_KNOWN_PATH)
+}
+
+/// The favicon's challenge for its current target domain, created if it has
+/// none. Asking again returns the same token until the domain changes, so
+/// records that are already published stay valid.
+pub fn issue_challenge(db: &Database, favicon: &Favicon) -> Result<DomainChallenge, HandlerError> {
+    let domain = challenge_domain(favicon)?;
+
+    if let Some(challenge) = db.get_domain_challenge(&favicon.id)? {
+        if challenge.domain == domain {
+            return Ok(challenge);
+        }
+    }
+
+    let challenge = DomainChallenge {
+        favicon_id: favicon.id.clone(),
+        domain,
+        token: Uuid::new_v4().simple().to_string(),
+        created_at: Utc::now(),
+    };
+    db.insert_domain_challenge(&challenge)?;
+    Ok(challenge)
+}
+
+/// Look for the challenge token in DNS, then in the well-known file, and
+/// mark the favicon's domain verified if either has it
+pub fn verify_domain(
+    db: &Database,
+    favicon: &Favicon,
+    resolver: &dyn DnsResolver,
+    fetcher: &dyn HttpFetcher,
+) -> Result<(VerificationMethod, DateTime<Utc>), HandlerError> {
+    let domain = challenge_domain(favicon)?;
+    let challenge = db.get_domain_challenge(&favicon.id)?
+        .filter(|challenge| challenge.domain == domain)
+        .ok_or_else(|| HandlerError::BadRequest(format!(
+            "No verification challenge for {}; request one first", domain
+        )))?;
+
+    let method = check_challenge(&challenge, resolver, fetcher)?;
+    let now = Utc::now();
+    db.mark_domain_verified(&favicon.id, &challenge.domain, now)?;
+    Ok((method, now))
+}
+
+/// Find the token in a TXT record of the domain or in its well-known file
+pub fn check_challenge(
+    challenge: &DomainChallenge,
+    resolver: &dyn DnsResolver,
+    fetcher: &dyn HttpFetcher,
+) -> Result<VerificationMethod, HandlerError> {
+    let expected = txt_record_value(challenge);
+    let dns = match resolver.txt_records(&challenge.domain) {
+        Ok(records) if records.iter().any(|record| record.trim() == expected) => {
+            return Ok(VerificationMethod::DnsTxt);
+        }
+        Ok(_) => "no matching TXT record".to_string(),
+        Err(e) => format!("TXT lookup failed ({})", e),
+    };
+
+    let url = well_known_url(challenge);
+    let well_known = match fetcher.fetch_text(&url) {
+        Ok(body) if body.trim() == challenge.token => return Ok(VerificationMethod::WellKnown),
+        Ok(_) => format!("{} does not contain the token", url),
+        // The cause is not echoed, since it could describe hosts the caller cannot reach
+        Err(_) => format!("{} could not be fetched", url),
+    };
+
+    Err(HandlerError::ValidationError(format!(
+        "Could not verify {}: {}; {}", challenge.domain, dns, well_known
+    )))
+}
+
+fn challenge_domain(favicon: &Favicon) -> Result<String, HandlerError> {
+    favicon.target_domain.as_deref()
+        .map(str::to_ascii_lowercase)
+        .ok_or_else(|| HandlerError::ValidationError("Favicon has no target domain to verify".to_string()))
+}
+
+/// Resolver using the system DNS configuration. Its lookups block on their
+/// own runtime, so it must not be used from async code.
+pub struct SystemDnsResolver {
+    resolver: Resolver,
+}
+
+impl SystemDnsResolver {
+    pub fn new() -> Result<Self, HandlerError> {
+        let resolver = Resolver::from_system_conf()
+            .map_err(|e| HandlerError::InternalError(format!("Failed to initialize DNS resolver: {}", e)))?;
+        Ok(SystemDnsResolver { resolver })
+    }
+}
+
+impl DnsResolver for SystemDnsResolver {
+    fn txt_records(&self, name: &str) -> Result<Vec<String>, String> {
+        // Fully qualified, so search domains are not appended
+        match self.resolver.txt_lookup(format!("{}.", name).as_str()) {
+            Ok(lookup) => Ok(lookup.iter().map(|txt| txt.to_string()).collect()),
+            Err(e) if matches!(e.kind(), ResolveErrorKind::NoRecordsFound { .. }) => Ok(Vec::new()),
+            Err(e) => Err(e.to_string()),
+        }
+    }
+}
+
+/// Fetcher for well-known files. Redirects are not followed: the file must
+/// be served by the domain itself, from a public address.
+pub struct HttpsFetcher {
+    agent: ureq::Agent,
+}
+
+impl HttpsFetcher {
+    pub fn new() -> Self {
+        let agent = ureq::AgentBuilder::new()
+            .resolver(PublicAddressResolver { allow_private: false })
+            .https_only(true)
+            .redirects(0)
+            .timeout(FETCH_TIMEOUT)
+            .user_agent("a-icon-domain-verifier")
+            .build();
+        HttpsFetcher { agent }
+    }
+}
+
+impl Default for HttpsFetcher {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl HttpFetcher for HttpsFetcher {
+    fn fetch_text(&self, url: &str) -> Result<String, String> {
+        let response = self.agent.get(url).call().map_err(|e| e.to_string())?;
+        if response.status() != 200 {
+            return Err(format!("HTTP {}", response.status()));
+        }
+
+        let mut body = String::new();
+        response.into_reader()
+            .take(MAX_WELL_KNOWN_BYTES + 1)
+            .read_to_string(&mut body)
+            .map_err(|e| e.to_string())?;
+        if body.len() as u64 > MAX_WELL_KNOWN_BYTES {
+            return Err("file is too large".to_string());
+        }
+        Ok(body)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use tempfile::NamedTempFile;
+
+    /// TXT records and files served from fixed tables
+    #[derive(Default)]
+    struct StandIn {
+        records: HashMap<String, Vec<String>>,
+        files: HashMap<String, String>,
+    }
+
+    impl DnsResolver for StandIn {
+        fn txt_records(&self, name: &str) -> Result<Vec<String>, String> {
+            Ok(self.records.get(name).cloned().unwrap_or_default())
+        }
+    }
+
+    impl HttpFetcher for StandIn {
+        fn fetch_text(&self, url: &str) -> Result<String, String> {
+            self.files.get(url).cloned().ok_or_else(|| "HTTP 404".to_string())
+        }
+    }
+
+    fn favicon(id: &str, domain: &str) -> Favicon {
+        Favicon {
+            id: id.to_string(),
+            slug: format!("slug-{}", id),
+            target_domain: Some(domain.to_string()),
+            published_url: format!("/f/slug-{}", id),
+            ..Favicon::test_default()
+        }
+    }
+
+    #[test]
+    fn test_check_challenge() {
+        let challenge = DomainChallenge {
+            favicon_id: "a".to_string(),
+            domain: "example.com".to_string(),
+            token: "0123456789abcdef".to_string(),
+            created_at: Utc::now(),
+        };
+        let mut stand_in = StandIn::default();
+        // Why the fetch failed is not passed on
+        let error = check_challenge(&challenge, &stand_in, &stand_in).unwrap_err().to_string();
+        assert!(error.contains("could not be fetched") && !error.contains("404"), "{}", error);
+
+        stand_in.files.insert(
+            "https://example.com/.well-known/a-icon-verification.txt".to_string(),
+            "0123456789abcdef\n".to_string(),
+        );
+        assert_eq!(check_challenge(&challenge, &stand_in, &stand_in).unwrap(), VerificationMethod::WellKnown);
+
+        stand_in.records.insert("example.com".to_string(), vec![
+            "v=spf1 -all".to_string(),
+            "a-icon-verification=0123456789abcdef".to_string(),
+        ]);
+        assert_eq!(check_challenge(&challenge, &stand_in, &stand_in).unwrap(), VerificationMethod::DnsTxt);
+    }
+
+    #[test]
+    fn test_https_fetcher_refuses_private_addresses() {
+        let error = HttpsFetcher::new().fetch_text("https://127.0.0.1:9/.well-known/a-icon-verification.txt").unwrap_err();
+        assert!(error.contains("public address"), "{}", error);
+    }
+
+    #[test]
+    fn test_one_verified_favicon_per_domain() {
+        let file = NamedTempFile::new().unwrap();
+        let db = Database::new(file.path()).unwrap();
+        db.insert_favicon(&favicon("first", "Example.com")).unwrap();
+        db.insert_favicon(&favicon("second", "example.com")).unwrap();
+        let mut stand_in = StandIn::default();
+
+        let first = db.get_favicon_by_id("first").unwrap().unwrap();
+        assert!(verify_domain(&db, &first, &stand_in, &stand_in).is_err());
+
+        let challenge = issue_challenge(&db, &first).unwrap();
+        assert_eq!(challenge.domain, "example.com");
+        assert_eq!(issue_challenge(&db, &first).unwrap().token, challenge.token);
+        assert!(verify_domain(&db, &first, &stand_in, &stand_in).is_err());
+
+        stand_in.records.insert("example.com".to_string(), vec![txt_record_value(&challenge)]);
+        let (method, _) = verify_domain(&db, &first, &stand_in, &stand_in).unwrap();
+        assert_eq!(method, VerificationMethod::DnsTxt);
+        assert!(db.get_favicon_by_id("first").unwrap().unwrap().domain_verified_at.is_some());
+
+        // A newer proof for the same domain takes the badge over
+        let second = db.get_favicon_by_id("second").unwrap().unwrap();
+        let challenge = issue_challenge(&db, &second).unwrap();
+        stand_in.files.insert(well_known_url(&challenge), challenge.token.clone());
+        let (method, _) = verify_domain(&db, &second, &stand_in, &stand_in).unwrap();
+        assert_eq!(method, VerificationMethod::WellKnown);
+        assert!(db.get_favicon_by_id("first").unwrap().unwrap().domain_verified_at.is_none());
+        assert!(db.get_favicon_by_id("second").unwrap().unwrap().domain_verified_at.is_some());
+
+        let (items, _) = db.list_published_favicons(1, 10, "url", "asc").unwrap();
+        let verified: Vec<bool> = items.iter().map(|item| item.domain_verified).collect();
+        assert_eq!(verified, vec![false, true]);
+
+        // A challenge issued for an earlier domain no longer applies
+        let mut moved = db.get_favicon_by_id("second").unwrap().unwrap();
+        moved.target_domain = Some("example.org".to_string());
+        assert!(verify_domain(&db, &moved, &stand_in, &stand_in).is_err());
+    }
+}
@@ -0,0 +1,782 @@
+use crate::animation::{
+    decode_gif_frames, encode_animated_webp, encode_apng, render_frames, select_frame, ANIMATED_SIZE,
+};
+use crate::database::Database;
+use crate::error::HandlerError;
+use crate::ico;
+use crate::manifest::build_manifest_assets;
+use crate::models::{
+    AssetType, AssetVariant, CropRect, DarkVariant, Favicon, FaviconAsset, FitMode, GenerationOptions, GenerationStatus,
+    SvgMethod, WebhookEvent,
+};
+use crate::silhouette::build_mask_icon_svg;
+use crate::storage::StorageService;
+use crate::validation::parse_hex_color;
+use crate::vectorize::trace_svg;
+use crate::webhooks::emit_event;
+use base64::Engine;
+use chrono::Utc;
+use image::codecs::ico::{IcoEncoder, IcoFrame};
+use image::imageops::FilterType;
+use image::{DynamicImage, ExtendedColorType, ImageFormat, RgbaImage};
+use resvg::{tiny_skia, usvg};
+use std::borrow::Cow;
+use std::io::Cursor;
+use uuid::Uuid;
+
+/// Standard favicon sizes (PNG)
+pub const PNG_SIZES: [u32; 9] = [16, 32, 48, 64, 96, 128, 192, 256, 512];
+
+/// Apple touch icon sizes (PNG)
+pub const APPLE_TOUCH_SIZES: [u32; 4] = [120, 152, 167, 180];
+
+/// Sizes embedded in the multi-resolution favicon.ico
+pub const ICO_SIZES: [u32; 3] = [16, 32, 48];
+
+/// Maskable icon sizes referenced from the web app manifest (PNG)
+pub const MASKABLE_SIZES: [u32; 2] = [192, 512];
+
+/// Windows tile sizes referenced from browserconfig.xml (PNG)
+pub const MSTILE_SIZES: [u32; 3] = [70, 150, 310];
+
+/// Padding on each edge of maskable icons, in percent, unless overridden by
+/// the generation options. Keeps the image inside the 80% safe zone platforms
+/// never crop into.
+pub const DEFAULT_MASKABLE_PADDING: u32 = 10;
+
+/// Resolution the source is normalized to before deriving assets
+pub const CANONICAL_SIZE: u32 = 512;
+
+/// An encoded asset ready to be stored
+pub struct GeneratedAsset {
+    pub r#type: AssetType,
+    pub size: Option<String>,
+    pub variant: AssetVariant,
+    pub format: String,
+    pub mime_type: String,
+    pub data: Vec<u8>,
+}
+
+impl GeneratedAsset {
+    /// Storage key for this asset: `{faviconId}/{type}-{size}[-{variant}].{format}`
+    pub fn storage_key(&self, favicon_id: &str) -> String {
+        format!(
+            "{}/{}-{}{}{}",
+            favicon_id,
+            self.r#type.as_str(),
+            self.size.as_deref().unwrap_or("canonical"),
+            self.variant.suffix(),
+            self.format
+        )
+    }
+}
+
+/// Decode a source image. SVG sources are rasterized at `CANONICAL_SIZE`,
+/// and the largest frame of ICO/CUR sources is used as the master image.
+pub fn load_source(data: &[u8], mime_type: &str) -> Result<DynamicImage, HandlerError> {
+    if mime_type == "image/svg+xml" {
+        return rasterize_svg(data, CANONICAL_SIZE);
+    }
+
+    if mime_type == "image/x-icon" {
+        let frame = ico::largest_frame(data)?;
+        return image::load_from_memory_with_format(&frame, ImageFormat::Ico)
+            .map_err(|e| HandlerError::ValidationError(format!("Failed to decode image: {}", e)));
+    }
+
+    image::load_from_memory(data)
+        .map_err(|e| HandlerError::ValidationError(format!("Failed to decode image: {}", e)))
+}
+
+/// Decode the frame of a source used for static assets: the chosen frame of an
+/// animated GIF, otherwise the image itself. Single-frame sources only accept
+/// frame index 0.
+pub fn load_source_frame(data: &[u8], mime_type: &str, frame_index: Option<u32>) -> Result<DynamicImage, HandlerError> {
+    match frame_index {
+        Some(index) if index > 0 && mime_type == "image/gif" => select_frame(decode_gif_frames(data)?, Some(index)),
+        Some(index) if index > 0 => Err(HandlerError::ValidationError(format!(
+            "frameIndex {} is out of range; the source has 1 frame(s)",
+            index
+        ))),
+        _ => load_source(data, mime_type),
+    }
+}
+
+/// Decode the frame of a source used for static assets and apply the
+/// generation options to it (see `prepare_source`)
+pub fn load_prepared_source(data: &[u8], mime_type: &str, options: &GenerationOptions) -> Result<DynamicImage, HandlerError> {
+    let image = load_source_frame(data, mime_type, options.frame_index)?;
+    let options = raster_options(data, mime_type, options)?;
+    prepare_source(image, &options)
+}
+
+/// The options to prepare a decoded source with. The crop rectangle of an
+/// SVG source is given in the SVG's own units, the size its upload reported,
+/// so it is mapped onto the `CANONICAL_SIZE` raster the SVG is decoded to.
+fn raster_options<'a>(data: &[u8], mime_type: &str, options: &'a GenerationOptions) -> Result<Cow<'a, GenerationOptions>, HandlerError> {
+    match options.crop {
+        Some(crop) if mime_type == "image/svg+xml" => Ok(Cow::Owned(GenerationOptions {
+            crop: Some(svg_crop_on_raster(&parse_svg(data)?, crop, CANONICAL_SIZE)),
+            ..options.clone()
+        })),
+        _ => Ok(Cow::Borrowed(options)),
+    }
+}
+
+/// Map a crop rectangle in an SVG's units onto its raster at `size` (see
+/// `rasterize_svg`), keeping at least one pixel of it
+fn svg_crop_on_raster(tree: &usvg::Tree, crop: CropRect, size: u32) -> CropRect {
+    let (scale, offset_x, offset_y) = svg_placement(tree, size);
+    let edge = |value: u32, offset: f32, min: f32| (value as f32 * scale + offset).round().clamp(min, size as f32) as u32;
+
+    let x = edge(crop.x, offset_x, 0.0).min(size - 1);
+    let y = edge(crop.y, offset_y, 0.0).min(size - 1);
+    let right = edge(crop.x.saturating_add(crop.width), offset_x, (x + 1) as f32);
+    let bottom = edge(crop.y.saturating_add(crop.height), offset_y, (y + 1) as f32);
+    CropRect { x, y, width: right - x, height: bottom - y }
+}
+
+fn parse_svg(data: &[u8]) -> Result<usvg::Tree, HandlerError> {
+    usvg::Tree::from_data(data, &usvg::Options::default())
+        .map_err(|e| HandlerError::ValidationError(format!("Invalid SVG: {}", e)))
+}
+
+/// Scale and offsets that center an SVG in a square of `size` pixels
+fn svg_placement(tree: &usvg::Tree, size: u32) -> (f32, f32, f32) {
+    let svg_size = tree.size();
+    let scale = size as f32 / svg_size.width().max(svg_size.height());
+    let offset_x = (size as f32 - svg_size.width() * scale) / 2.0;
+    let offset_y = (size as f32 - svg_size.height() * scale) / 2.0;
+    (scale, offset_x, offset_y)
+}
+
+/// Render an SVG document into a square RGBA image, preserving aspect ratio
+pub fn rasterize_svg(data: &[u8], size: u32) -> Result<DynamicImage, HandlerError> {
+    let tree = parse_svg(data)?;
+    let (scale, offset_x, offset_y) = svg_placement(&tree, size);
+
+    let mut pixmap = tiny_skia::Pixmap::new(size, size)
+        .ok_or_else(|| HandlerError::InternalError("Failed to allocate SVG canvas".to_string()))?;
+    let transform = tiny_skia::Transform::from_scale(scale, scale).post_translate(offset_x, offset_y);
+    resvg::render(&tree, transform, &mut pixmap.as_mut());
+
+    // tiny-skia stores premultiplied alpha
+    let mut rgba = Vec::with_capacity((size * size * 4) as usize);
+    for pixel in pixmap.pixels() {
+        let color = pixel.demultiply();
+        rgba.extend_from_slice(&[color.red(), color.green(), color.blue(), color.alpha()]);
+    }
+
+    RgbaImage::from_raw(size, size, rgba)
+        .map(DynamicImage::ImageRgba8)
+        .ok_or_else(|| HandlerError::InternalError("Failed to build SVG raster".to_string()))
+}
+
+/// Resize to an exact square, cropping the longer side around the center
+pub fn render_square(image: &DynamicImage, size: u32) -> DynamicImage {
+    image.resize_to_fill(size, size, FilterType::Lanczos3)
+}
+
+/// Apply the crop rectangle and fit mode from the generation options,
+/// producing the square image every asset is derived from
+pub fn prepare_source(image: DynamicImage, options: &GenerationOptions) -> Result<DynamicImage, HandlerError> {
+    let image = match (options.fit, options.crop) {
+        (FitMode::Crop, Some(crop)) => {
+            if crop.x.saturating_add(crop.width) > image.width() || crop.y.saturating_add(crop.height) > image.height() {
+                return Err(HandlerError::ValidationError(format!(
+                    "Crop rectangle exceeds the {}x{} source",
+                    image.width(),
+                    image.height()
+                )));
+            }
+            image.crop_imm(crop.x, crop.y, crop.width, crop.height)
+        }
+        _ => image,
+    };
+
+    let (width, height) = (image.width(), image.height());
+    if width == height {
+        return Ok(image);
+    }
+
+    Ok(match options.fit {
+        FitMode::Contain => {
+            let side = width.max(height);
+            let mut canvas = RgbaImage::new(side, side);
+            let (x, y) = ((side - width) / 2, (side - height) / 2);
+            image::imageops::overlay(&mut canvas, &image.to_rgba8(), x as i64, y as i64);
+            DynamicImage::ImageRgba8(canvas)
+        }
+        FitMode::Cover | FitMode::Crop => {
+            let side = width.min(height);
+            image.crop_imm((width - side) / 2, (height - side) / 2, side, side)
+        }
+    })
+}
+
+/// Render a prepared source at `size`, flattening it onto the background and
+/// rounding its corners as the generation options ask
+pub fn render_icon(image: &DynamicImage, size: u32, options: &GenerationOptions) -> DynamicImage {
+    let mut icon = render_square(image, size).to_rgba8();
+
+    if let Some([r, g, b]) = options.background.as_deref().and_then(parse_hex_color) {
+        let mut flat = RgbaImage::from_pixel(size, size, image::Rgba([r, g, b, 255]));
+        image::imageops::overlay(&mut flat, &icon, 0, 0);
+        icon = flat;
+    }
+
+    if let Some(percent) = options.corner_radius.filter(|&percent| percent > 0) {
+        round_corners(&mut icon, size as f32 * percent as f32 / 100.0);
+    }
+
+    DynamicImage::ImageRgba8(icon)
+}
+
+/// Make everything outside a rounded rectangle transparent, anti-aliasing the
+/// curve by pixel coverage
+fn round_corners(image: &mut RgbaImage, radius: f32) {
+    let (width, height) = (image.width() as f32, image.height() as f32);
+    for (x, y, pixel) in image.enumerate_pixels_mut() {
+        // Distance from the pixel center to the nearest corner circle's center
+        let (px, py) = (x as f32 + 0.5, y as f32 + 0.5);
+        let dx = (radius - px).max(px - (width - radius)).max(0.0);
+        let dy = (radius - py).max(py - (height - radius)).max(0.0);
+        if dx == 0.0 || dy == 0.0 {
+            continue;
+        }
+
+        let coverage = (radius - (dx * dx + dy * dy).sqrt() + 0.5).clamp(0.0, 1.0);
+        pixel.0[3] = (pixel.0[3] as f32 * coverage).round() as u8;
+    }
+}
+
+pub fn encode_png(image: &DynamicImage) -> Result<Vec<u8>, HandlerError> {
+    let mut buffer = Cursor::new(Vec::new());
+    image
+        .write_to(&mut buffer, ImageFormat::Png)
+        .map_err(|e| HandlerError::InternalError(format!("Failed to encode PNG: {}", e)))?;
+    Ok(buffer.into_inner())
+}
+
+/// Build a multi-resolution ICO containing one PNG frame per size
+pub fn encode_ico(image: &DynamicImage, sizes: &[u32], options: &GenerationOptions) -> Result<Vec<u8>, HandlerError> {
+    let frames = sizes
+        .iter()
+        .map(|&size| {
+            let frame = render_icon(image, size, options).to_rgba8();
+            IcoFrame::as_png(frame.as_raw(), size, size, ExtendedColorType::Rgba8)
+                .map_err(|e| HandlerError::InternalError(format!("Failed to encode ICO frame: {}", e)))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let mut buffer = Vec::new();
+    IcoEncoder::new(&mut buffer)
+        .encode_images(&frames)
+        .map_err(|e| HandlerError::InternalError(format!("Failed to encode ICO: {}", e)))?;
+    Ok(buffer)
+}
+
+/// Center the image on a solid background, leaving `padding` percent of each
+/// edge free for platforms to crop into
+pub fn render_maskable(image: &DynamicImage, size: u32, background: [u8; 3], padding: u32) -> DynamicImage {
+    let [r, g, b] = background;
+    let mut canvas = RgbaImage::from_pixel(size, size, image::Rgba([r, g, b, 255]));
+
+    let inner = ((size as f32 * (100 - 2 * padding.min(49)) as f32 / 100.0).round() as u32).max(1);
+    let offset = ((size - inner) / 2) as i64;
+    image::imageops::overlay(&mut canvas, &render_square(image, inner).to_rgba8(), offset, offset);
+    DynamicImage::ImageRgba8(canvas)
+}
+
+/// Wrap a raster in an SVG document so every favicon has a canonical SVG
+pub fn embed_png_in_svg(png: &[u8], size: u32) -> Vec<u8> {
+    let encoded = base64::engine::general_purpose::STANDARD.encode(png);
+    format!(
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{size}" height="{size}" viewBox="0 0 {size} {size}"><image width="{size}" height="{size}" href="data:image/png;base64,{encoded}"/></svg>"#,
+    )
+    .into_bytes()
+}
+
+/// Wrap light and dark rasters in one SVG that shows whichever matches the
+/// viewer's `prefers-color-scheme`
+pub fn embed_adaptive_svg(light_png: &[u8], dark_png: &[u8], size: u32) -> Vec<u8> {
+    let light = base64::engine::general_purpose::STANDARD.encode(light_png);
+    let dark = base64::engine::general_purpose::STANDARD.encode(dark_png);
+    format!(
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{size}" height="{size}" viewBox="0 0 {size} {size}"><style>#dark{{display:none}}@media (prefers-color-scheme: dark){{#light{{display:none}}#dark{{display:inline}}}}</style><image id="light" width="{size}" height="{size}" href="data:image/png;base64,{light}"/><image id="dark" width="{size}" height="{size}" href="data:image/png;base64,{dark}"/></svg>"#,
+    )
+    .into_bytes()
+}
+
+/// Generate the full favicon image set (PNG sizes, Apple touch icons, Windows
+/// tiles, maskable icons, ICO, canonical SVG and Safari mask icon), plus an
+/// animated PNG and WebP for animated GIFs. Maskable icons are padded
+/// with `background`; `options` shape every other asset. When a dark source
+/// (data and MIME type) or a `darkVariant` rule is given, dark PNGs and an
+/// adaptive SVG are added. Also returns how the canonical SVG was produced.
+pub fn generate_assets(
+    source: &[u8],
+    mime_type: &str,
+    background: [u8; 3],
+    options: &GenerationOptions,
+    dark_source: Option<(&[u8], &str)>,
+) -> Result<(Vec<GeneratedAsset>, SvgMethod), HandlerError> {
+    let image = load_prepared_source(source, mime_type, options)?;
+    let maskable_padding = options.maskable_padding.unwrap_or(DEFAULT_MASKABLE_PADDING);
+    let mut assets = Vec::new();
+
+    for size in PNG_SIZES.iter().chain(APPLE_TOUCH_SIZES.iter()).chain(MSTILE_SIZES.iter()) {
+        let data = encode_png(&render_icon(&image, *size, options))?;
+        assets.push(GeneratedAsset {
+            r#type: AssetType::Png,
+            size: Some(format!("{}x{}", size, size)),
+            variant: AssetVariant::Light,
+            format: ".png".to_string(),
+            mime_type: "image/png".to_string(),
+            data,
+        });
+    }
+
+    for size in MASKABLE_SIZES {
+        assets.push(GeneratedAsset {
+            r#type: AssetType::Maskable,
+            size: Some(format!("{}x{}", size, size)),
+            variant: AssetVariant::Light,
+            format: ".png".to_string(),
+            mime_type: "image/png".to_string(),
+            data: encode_png(&render_maskable(&image, size, background, maskable_padding))?,
+        });
+    }
+
+    assets.push(GeneratedAsset {
+        r#type: AssetType::Ico,
+        size: Some("MULTI".to_string()),
+        variant: AssetVariant::Light,
+        format: ".ico".to_string(),
+        mime_type: "image/x-icon".to_string(),
+        data: encode_ico(&image, &ICO_SIZES, options)?,
+    });
+
+    // An SVG source is only kept as-is when no transformation applies to it
+    let transformed = GenerationOptions {
+        dark_variant: None,
+        mask_icon_color: None,
+        frame_index: None,
+        ..options.clone()
+    } != GenerationOptions::default();
+    let canonical_png = encode_png(&render_icon(&image, CANONICAL_SIZE, options))?;
+    let (svg, svg_method) = if mime_type == "image/svg+xml" && !transformed {
+        (source.to_vec(), SvgMethod::Source)
+    } else {
+        // Simple rasters trace into paths; anything else, or a trace larger
+        // than the embedded PNG, is embedded
+        let embedded = embed_png_in_svg(&canonical_png, CANONICAL_SIZE);
+        match trace_svg(&image, options).filter(|traced| traced.len() < embedded.len()) {
+            Some(traced) => (traced, SvgMethod::Traced),
+            None => (embedded, SvgMethod::Embedded),
+        }
+    };
+    assets.push(GeneratedAsset {
+        r#type: AssetType::Svg,
+        size: None,
+        variant: AssetVariant::Light,
+        format: ".svg".to_string(),
+        mime_type: "image/svg+xml".to_string(),
+        data: svg,
+    });
+
+    assets.push(GeneratedAsset {
+        r#type: AssetType::MaskIcon,
+        size: None,
+        variant: AssetVariant::Light,
+        format: ".svg".to_string(),
+        mime_type: "image/svg+xml".to_string(),
+        data: build_mask_icon_svg(&image, options),
+    });
+
+    // Dark-mode PNGs plus an SVG that follows prefers-color-scheme
+    if let Some(dark) = dark_image(&image, dark_source, options)? {
+        for size in PNG_SIZES {
+            assets.push(GeneratedAsset {
+                r#type: AssetType::Png,
+                size: Some(format!("{}x{}", size, size)),
+                variant: AssetVariant::Dark,
+                format: ".png".to_string(),
+                mime_type: "image/png".to_string(),
+                data: encode_png(&render_icon(&dark, size, options))?,
+            });
+        }
+
+        let dark_png = encode_png(&render_icon(&dark, CANONICAL_SIZE, options))?;
+        assets.push(GeneratedAsset {
+            r#type: AssetType::Svg,
+            size: None,
+            variant: AssetVariant::Adaptive,
+            format: ".svg".to_string(),
+            mime_type: "image/svg+xml".to_string(),
+            data: embed_adaptive_svg(&canonical_png, &dark_png, CANONICAL_SIZE),
+        });
+    }
+
+    // Animated sources keep their frames and timing
+    if mime_type == "image/gif" {
+        let frames = decode_gif_frames(source)?;
+        if frames.len() > 1 {
+            let rendered = render_frames(&frames, ANIMATED_SIZE, options)?;
+            let size = Some(format!("{}x{}", ANIMATED_SIZE, ANIMATED_SIZE));
+            assets.push(GeneratedAsset {
+                r#type: AssetType::AnimatedPng,
+                size: size.clone(),
+                variant: AssetVariant::Light,
+                format: ".png".to_string(),
+                mime_type: "image/apng".to_string(),
+                data: encode_apng(&rendered)?,
+            });
+            assets.push(GeneratedAsset {
+                r#type: AssetType::AnimatedWebp,
+                size,
+                variant: AssetVariant::Light,
+                format: ".webp".to_string(),
+                mime_type: "image/webp".to_string(),
+                data: encode_animated_webp(&rendered)?,
+            });
+        }
+    }
+
+    Ok((assets, svg_method))
+}
+
+/// The prepared dark-mode image: the dark source if one was uploaded, else
+/// derived from the light image by the `darkVariant` rule, else none
+fn dark_image(
+    light: &DynamicImage,
+    dark_source: Option<(&[u8], &str)>,
+    options: &GenerationOptions,
+) -> Result<Option<DynamicImage>, HandlerError> {
+    if let Some((data, mime_type)) = dark_source {
+        let options = raster_options(data, mime_type, options)?;
+        return Ok(Some(prepare_source(load_source(data, mime_type)?, &options)?));
+    }
+    Ok(options.dark_variant.as_ref().map(|rule| derive_dark(light, rule)))
+}
+
+/// Apply a dark-mode rule to an image, keeping its transparency
+pub fn derive_dark(image: &DynamicImage, rule: &DarkVariant) -> DynamicImage {
+    let mut rgba = image.to_rgba8();
+    match rule {
+        DarkVariant::Invert => {
+            for pixel in rgba.pixels_mut() {
+                let [r, g, b, a] = pixel.0;
+                pixel.0 = [255 - r, 255 - g, 255 - b, a];
+            }
+        }
+        DarkVariant::Recolor { color } => {
+            let [r, g, b] = parse_hex_color(color).unwrap_or([255, 255, 255]);
+            for pixel in rgba.pixels_mut() {
+                pixel.0 = [r, g, b, pixel.0[3]];
+            }
+        }
+    }
+    DynamicImage::ImageRgba8(rgba)
+}
+
+/// Generate, store and record all assets for a favicon, then update its
+/// generation status. Generation failures are recorded on the favicon as
+/// `FAILED` rather than returned, so the caller can still respond with it.
+/// A `favicon.generated` or `favicon.generation_failed` webhook event is
+/// queued accordingly.
+pub async fn generate_and_store(
+    db: &Database,
+    storage: &StorageService,
+    favicon: &mut Favicon,
+    source: &[u8],
+    dark_source: Option<&[u8]>,
+) -> Result<(), HandlerError> {
+    let mime_type = favicon.source_original_mime.clone().unwrap_or_default();
+    let background = favicon.background_color.as_deref()
+        .and_then(parse_hex_color)
+        .unwrap_or([255, 255, 255]);
+
+    let options = favicon.generation_options.clone().unwrap_or_default();
+
+    let dark_source = dark_source.zip(favicon.dark_source_mime.clone());
+    let dark_source = dark_source.as_ref().map(|(data, mime_type)| (*data, mime_type.as_str()));
+
+    let generated = generate_assets(source, &mime_type, background, &options, dark_source)
+        .and_then(|(mut assets, svg_method)| {
+            let manifests = build_manifest_assets(favicon, &assets)?;
+            assets.extend(manifests);
+            Ok((assets, svg_method))
+        });
+
+    let result = match generated {
+        Ok((assets, svg_method)) => {
+            favicon.svg_method = Some(svg_method);
+            store_assets(db, storage, favicon, assets).await
+        }
+        Err(e) => Err(e),
+    };
+
+    let now = Utc::now();
+    favicon.updated_at = now;
+    let event = match result {
+        Ok(()) => {
+            favicon.generation_status = GenerationStatus::Success;
+            favicon.generated_at = Some(now);
+            favicon.generation_error = None;
+            WebhookEvent::FaviconGenerated
+        }
+        Err(e) => {
+            favicon.generation_status = GenerationStatus::Failed;
+            favicon.generation_error = Some(e.to_string());
+            WebhookEvent::FaviconGenerationFailed
+        }
+    };
+
+    db.update_favicon(favicon)?;
+    emit_event(db, event, favicon, now)
+}
+
+async fn store_assets(
+    db: &Database,
+    storage: &StorageService,
+    favicon: &mut Favicon,
+    assets: Vec<GeneratedAsset>,
+) -> Result<(), HandlerError> {
+    for asset in assets {
+        let storage_key = asset.storage_key(&favicon.id);
+        storage.upload_object(&storage_key, asset.data, &asset.mime_type).await?;
+
+        if asset.r#type == AssetType::Svg && asset.variant == AssetVariant::Light {
+            favicon.canonical_svg_key = Some(storage_key.clone());
+        }
+
+        db.insert_asset(&FaviconAsset {
+            id: Uuid::new_v4().to_string(),
+            favicon_id: favicon.id.clone(),
+            r#type: asset.r#type,
+            size: asset.size,
+            variant: asset.variant,
+            format: asset.format,
+            storage_key,
+            mime_type: asset.mime_type,
+            created_at: Utc::now(),
+        })?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WHITE: [u8; 3] = [255, 255, 255];
+
+    fn sample_png() -> Vec<u8> {
+        let image = DynamicImage::ImageRgba8(RgbaImage::from_pixel(40, 20, image::Rgba([200, 10, 10, 255])));
+        encode_png(&image).unwrap()
+    }
+
+    #[test]
+    fn test_generate_assets_from_png() {
+        let (assets, svg_method) = generate_assets(&sample_png(), "image/png", WHITE, &GenerationOptions::default(), None).unwrap();
+        // A flat-colored source traces into paths
+        assert_eq!(svg_method, SvgMethod::Traced);
+
+        let png_count = assets.iter().filter(|a| matches!(a.r#type, AssetType::Png)).count();
+        assert_eq!(png_count, PNG_SIZES.len() + APPLE_TOUCH_SIZES.len() + MSTILE_SIZES.len());
+        let maskable_count = assets.iter().filter(|a| matches!(a.r#type, AssetType::Maskable)).count();
+        assert_eq!(maskable_count, MASKABLE_SIZES.len());
+        assert!(assets.iter().any(|a| matches!(a.r#type, AssetType::Ico)));
+        assert!(assets.iter().any(|a| matches!(a.r#type, AssetType::Svg)));
+        assert!(assets.iter().any(|a| matches!(a.r#type, AssetType::MaskIcon)));
+
+        let icon_180 = assets.iter().find(|a| a.size.as_deref() == Some("180x180")).unwrap();
+        let decoded = image::load_from_memory(&icon_180.data).unwrap();
+        assert_eq!((decoded.width(), decoded.height()), (180, 180));
+    }
+
+    #[test]
+    fn test_render_maskable_pads_with_background() {
+        let image = DynamicImage::ImageRgba8(RgbaImage::from_pixel(10, 10, image::Rgba([200, 10, 10, 255])));
+        let maskable = render_maskable(&image, 100, [0, 0, 255], DEFAULT_MASKABLE_PADDING).to_rgba8();
+
+        assert_eq!(maskable.get_pixel(2, 2).0, [0, 0, 255, 255]);
+        assert_eq!(maskable.get_pixel(50, 50).0, [200, 10, 10, 255]);
+    }
+
+    #[test]
+    fn test_generate_assets_with_options() {
+        // 40x20 red source; contain pads it top and bottom before resizing
+        let options = GenerationOptions {
+            background: Some("#00ff00".to_string()),
+            corner_radius: Some(50),
+            fit: FitMode::Contain,
+            ..Default::default()
+        };
+        let (assets, _) = generate_assets(&sample_png(), "image/png", WHITE, &options, None).unwrap();
+        let icon = assets.iter().find(|a| a.size.as_deref() == Some("64x64")).unwrap();
+        let decoded = image::load_from_memory(&icon.data).unwrap().to_rgba8();
+
+        assert_eq!(decoded.get_pixel(32, 32).0, [200, 10, 10, 255]);
+        assert_eq!(decoded.get_pixel(32, 4).0, [0, 255, 0, 255]);
+        assert_eq!(decoded.get_pixel(1, 1).0[3], 0);
+    }
+
+    #[test]
+    fn test_generate_dark_variants() {
+        let (light_only, _) = generate_assets(&sample_png(), "image/png", WHITE, &GenerationOptions::default(), None).unwrap();
+        assert!(light_only.iter().all(|a| a.variant == AssetVariant::Light));
+
+        let options = GenerationOptions { dark_variant: Some(DarkVariant::Invert), ..Default::default() };
+        let (assets, _) = generate_assets(&sample_png(), "image/png", WHITE, &options, None).unwrap();
+        let dark_count = assets.iter().filter(|a| a.variant == AssetVariant::Dark).count();
+        assert_eq!(dark_count, PNG_SIZES.len());
+
+        let dark_32 = assets.iter()
+            .find(|a| a.variant == AssetVariant::Dark && a.size.as_deref() == Some("32x32"))
+            .unwrap();
+        let decoded = image::load_from_memory(&dark_32.data).unwrap().to_rgba8();
+        assert_eq!(decoded.get_pixel(16, 16).0, [55, 245, 245, 255]);
+
+        let adaptive = assets.iter().find(|a| a.variant == AssetVariant::Adaptive).unwrap();
+        assert_eq!(adaptive.r#type, AssetType::Svg);
+        assert!(String::from_utf8_lossy(&adaptive.data).contains("@media (prefers-color-scheme: dark)"));
+
+        // An uploaded dark source takes precedence over the rule
+        let dark_source = encode_png(&DynamicImage::ImageRgba8(RgbaImage::from_pixel(8, 8, image::Rgba([1, 2, 3, 255])))).unwrap();
+        let (assets, _) = generate_assets(&sample_png(), "image/png", WHITE, &options, Some((&dark_source, "image/png"))).unwrap();
+        let dark_32 = assets.iter()
+            .find(|a| a.variant == AssetVariant::Dark && a.size.as_deref() == Some("32x32"))
+            .unwrap();
+        assert_eq!(image::load_from_memory(&dark_32.data).unwrap().to_rgba8().get_pixel(16, 16).0, [1, 2, 3, 255]);
+    }
+
+    #[test]
+    fn test_prepare_source_crop() {
+        let mut source = RgbaImage::from_pixel(40, 20, image::Rgba([200, 10, 10, 255]));
+        source.put_pixel(30, 10, image::Rgba([0, 0, 255, 255]));
+        let options = GenerationOptions {
+            fit: FitMode::Crop,
+            crop: Some(CropRect { x: 30, y: 10, width: 5, height: 5 }),
+            ..Default::default()
+        };
+
+        let prepared = prepare_source(DynamicImage::ImageRgba8(source.clone()), &options).unwrap().to_rgba8();
+        assert_eq!(prepared.dimensions(), (5, 5));
+        assert_eq!(prepared.get_pixel(0, 0).0, [0, 0, 255, 255]);
+
+        let outside = GenerationOptions { crop: Some(CropRect { x: 30, y: 10, width: 20, height: 5 }), ..options };
+        assert!(prepare_source(DynamicImage::ImageRgba8(source), &outside).is_err());
+    }
+
+    #[test]
+    fn test_svg_crop_is_in_svg_units() {
+        // The right half of a 100x50 SVG is blue; it is centered vertically on the raster
+        let svg = br##"<svg xmlns="http://www.w3.org/2000/svg" width="100" height="50">
+            <rect width="50" height="50" fill="#f00"/><rect x="50" width="50" height="50" fill="#00f"/></svg>"##;
+        let crop = CropRect { x: 50, y: 0, width: 50, height: 50 };
+        assert_eq!(
+            svg_crop_on_raster(&parse_svg(svg).unwrap(), crop, CANONICAL_SIZE),
+            CropRect { x: 256, y: 128, width: 256, height: 256 }
+        );
+
+        let options = GenerationOptions { fit: FitMode::Crop, crop: Some(crop), ..Default::default() };
+        let prepared = load_prepared_source(svg, "image/svg+xml", &options).unwrap().to_rgba8();
+        assert_eq!(prepared.dimensions(), (256, 256));
+        assert_eq!(prepared.get_pixel(0, 0).0, [0, 0, 255, 255]);
+        assert_eq!(prepared.get_pixel(255, 255).0, [0, 0, 255, 255]);
+    }
+
+    #[test]
+    fn test_generate_assets_from_svg() {
+        let svg = br##"<svg xmlns="http://www.w3.org/2000/svg" width="10" height="10"><rect width="10" height="10" fill="#00f"/></svg>"##;
+        let (assets, svg_method) = generate_assets(svg, "image/svg+xml", WHITE, &GenerationOptions::default(), None).unwrap();
+        assert_eq!(svg_method, SvgMethod::Source);
+
+        let canonical = assets.iter().find(|a| matches!(a.r#type, AssetType::Svg)).unwrap();
+        assert_eq!(canonical.data, svg.to_vec());
+
+        let icon_32 = assets.iter().find(|a| a.size.as_deref() == Some("32x32")).unwrap();
+        let decoded = image::load_from_memory(&icon_32.data).unwrap().to_rgba8();
+        assert_eq!(decoded.get_pixel(16, 16).0, [0, 0, 255, 255]);
+    }
+
+    #[test]
+    fn test_generate_assets_from_ico_uses_largest_frame() {
+        let ico = include_bytes!("../tests/fixtures/icon.ico");
+        let (assets, _) = generate_assets(ico, "image/x-icon", WHITE, &GenerationOptions::default(), None).unwrap();
+
+        let icon_192 = assets.iter().find(|a| a.size.as_deref() == Some("192x192")).unwrap();
+        let decoded = image::load_from_memory(&icon_192.data).unwrap().to_rgba8();
+        assert_eq!(decoded.get_pixel(96, 96).0, [0, 0, 255, 255]);
+    }
+
+    #[test]
+    fn test_generate_assets_from_webp_and_bmp() {
+        for (source, mime_type) in [
+            (&include_bytes!("../tests/fixtures/icon.webp")[..], "image/webp"),
+            (&include_bytes!("../tests/fixtures/icon.bmp")[..], "image/bmp"),
+        ] {
+            let (assets, _) = generate_assets(source, mime_type, WHITE, &GenerationOptions::default(), None).unwrap();
+            let icon_32 = assets.iter().find(|a| a.size.as_deref() == Some("32x32")).unwrap();
+            let decoded = image::load_from_memory(&icon_32.data).unwrap().to_rgba8();
+            assert_eq!(decoded.get_pixel(16, 16).0, [0, 128, 255, 255]);
+        }
+    }
+
+    #[test]
+    fn test_generate_assets_from_animated_gif() {
+        let mut gif = Vec::new();
+        {
+            let mut encoder = image::codecs::gif::GifEncoder::new(&mut gif);
+            for color in [[255, 0, 0, 255], [0, 255, 0, 255]] {
+                let frame = image::Frame::from_parts(
+                    RgbaImage::from_pixel(8, 8, image::Rgba(color)),
+                    0,
+                    0,
+                    image::Delay::from_numer_denom_ms(80, 1),
+                );
+                encoder.encode_frame(frame).unwrap();
+            }
+        }
+
+        let options = GenerationOptions { frame_index: Some(1), ..Default::default() };
+        let (assets, _) = generate_assets(&gif, "image/gif", WHITE, &options, None).unwrap();
+
+        let animated: Vec<_> = assets.iter().filter(|a| a.r#type.is_animated()).collect();
+        assert_eq!(animated.len(), 2);
+        assert_eq!(animated[0].storage_key("abc"), "abc/ANIMATED_PNG-64x64.png");
+        assert_eq!(animated[1].mime_type, "image/webp");
+
+        // Static assets come from the chosen frame
+        let icon_32 = assets.iter().find(|a| a.size.as_deref() == Some("32x32")).unwrap();
+        let decoded = image::load_from_memory(&icon_32.data).unwrap().to_rgba8();
+        assert_eq!(decoded.get_pixel(16, 16).0, [0, 255, 0, 255]);
+
+        let out_of_range = GenerationOptions { frame_index: Some(2), ..Default::default() };
+        assert!(generate_assets(&gif, "image/gif", WHITE, &out_of_range, None).is_err());
+        assert!(generate_assets(&sample_png(), "image/png", WHITE, &options, None).is_err());
+    }
+
+    #[test]
+    fn test_generate_assets_rejects_corrupt_source() {
+        assert!(generate_assets(&[0x89, 0x50, 0x4E, 0x47, 0, 0, 0], "image/png", WHITE, &GenerationOptions::default(), None).is_err());
+    }
+
+    #[test]
+    fn test_storage_key() {
+        let asset = GeneratedAsset {
+            r#type: AssetType::Png,
+            size: Some("192x192".to_string()),
+            variant: AssetVariant::Light,
+            format: ".png".to_string(),
+            mime_type: "image/png".to_string(),
+            data: Vec::new(),
+        };
+        assert_eq!(asset.storage_key("abc"), "abc/PNG-192x192.png");
+
+        let dark = GeneratedAsset { variant: AssetVariant::Dark, ..asset };
+        assert_eq!(dark.storage_key("abc"), "abc/PNG-192x192-dark.png");
+    }
+}
@@ -0,0 +1,182 @@
+use crate::error::HandlerError;
+
+const ICONDIR_SIZE: usize = 6;
+const ICONDIRENTRY_SIZE: usize = 16;
+
+/// ICONDIR resource types
+const TYPE_ICON: u16 = 1;
+const TYPE_CURSOR: u16 = 2;
+
+/// Most images accepted in one ICO or CUR file. Entries may share image
+/// data, so the file size alone does not bound the work of decoding them.
+pub const MAX_FRAMES: usize = 64;
+
+/// A single image referenced from an ICO/CUR directory
+#[derive(Debug, Clone, Copy)]
+struct DirEntry {
+    width: u32,
+    height: u32,
+    bits_per_pixel: u16,
+    length: usize,
+    offset: usize,
+}
+
+impl DirEntry {
+    fn area(&self) -> u32 {
+        self.width * self.height
+    }
+}
+
+/// Extract the largest frame of an ICO or CUR file as a single-frame ICO.
+///
+/// Frames are ranked by pixel area, then by bit depth. Cursor files store the
+/// hotspot where icons store planes and bit depth, so only icons use the bit
+/// depth as a tie-breaker. The returned buffer can be decoded by any ICO
+/// decoder, which would otherwise pick a frame on its own.
+pub fn largest_frame(buffer: &[u8]) -> Result<Vec<u8>, HandlerError> {
+    let mut best: Option<DirEntry> = None;
+    for entry in read_directory(buffer)? {
+        let is_better = match best {
+            None => true,
+            Some(current) => {
+                (entry.area(), entry.bits_per_pixel) > (current.area(), current.bits_per_pixel)
+            }
+        };
+        if is_better {
+            best = Some(entry);
+        }
+    }
+    let entry = best.ok_or_else(|| invalid("file contains no images"))?;
+    single_frame(buffer, &entry)
+}
+
+/// Every frame of an ICO or CUR file in directory order, each as a
+/// single-frame ICO
+pub fn frames(buffer: &[u8]) -> Result<Vec<Vec<u8>>, HandlerError> {
+    read_directory(buffer)?
+        .iter()
+        .map(|entry| single_frame(buffer, entry))
+        .collect()
+}
+
+fn read_directory(buffer: &[u8]) -> Result<Vec<DirEntry>, HandlerError> {
+    let resource_type = read_u16(buffer, 2)?;
+    if read_u16(buffer, 0)? != 0 || !matches!(resource_type, TYPE_ICON | TYPE_CURSOR) {
+        return Err(invalid("not an ICO or CUR file"));
+    }
+
+    let count = read_u16(buffer, 4)? as usize;
+    if count == 0 {
+        return Err(invalid("file contains no images"));
+    }
+    if count > MAX_FRAMES {
+        return Err(invalid(&format!("file contains more than {} images", MAX_FRAMES)));
+    }
+
+    (0..count)
+        .map(|index| read_entry(buffer, ICONDIR_SIZE + index * ICONDIRENTRY_SIZE, resource_type))
+        .collect()
+}
+
+/// Copy one directory entry and its image data into a single-frame ICO
+fn single_frame(buffer: &[u8], entry: &DirEntry) -> Result<Vec<u8>, HandlerError> {
+    let data = buffer
+        .get(entry.offset..entry.offset.saturating_add(entry.length))
+        .ok_or_else(|| invalid("image data is out of bounds"))?;
+
+    let mut frame = Vec::with_capacity(ICONDIR_SIZE + ICONDIRENTRY_SIZE + data.len());
+    frame.extend_from_slice(&0u16.to_le_bytes());
+    frame.extend_from_slice(&TYPE_ICON.to_le_bytes());
+    frame.extend_from_slice(&1u16.to_le_bytes());
+    // Directory sizes of 256 are stored as 0
+    frame.push((entry.width % 256) as u8);
+    frame.push((entry.height % 256) as u8);
+    frame.extend_from_slice(&[0, 0]);
+    frame.extend_from_slice(&1u16.to_le_bytes());
+    frame.extend_from_slice(&entry.bits_per_pixel.to_le_bytes());
+    frame.extend_from_slice(&(data.len() as u32).to_le_bytes());
+    frame.extend_from_slice(&((ICONDIR_SIZE + ICONDIRENTRY_SIZE) as u32).to_le_bytes());
+    frame.extend_from_slice(data);
+    Ok(frame)
+}
+
+fn read_entry(buffer: &[u8], at: usize, resource_type: u16) -> Result<DirEntry, HandlerError> {
+    let dimension = |value: u8| if value == 0 { 256 } else { value as u32 };
+    let width = dimension(*buffer.get(at).ok_or_else(|| invalid("directory is truncated"))?);
+    let height = dimension(*buffer.get(at + 1).ok_or_else(|| invalid("directory is truncated"))?);
+    let bits_per_pixel = if resource_type == TYPE_ICON { read_u16(buffer, at + 6)? } else { 0 };
+
+    Ok(DirEntry {
+        width,
+        height,
+        bits_per_pixel,
+        length: read_u32(buffer, at + 8)? as usize,
+        offset: read_u32(buffer, at + 12)? as usize,
+    })
+}
+
+fn read_u16(buffer: &[u8], at: usize) -> Result<u16, HandlerError> {
+    buffer
+        .get(at..at + 2)
+        .map(|b| u16::from_le_bytes([b[0], b[1]]))
+        .ok_or_else(|| invalid("directory is truncated"))
+}
+
+fn read_u32(buffer: &[u8], at: usize) -> Result<u32, HandlerError> {
+    buffer
+        .get(at..at + 4)
+        .map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+        .ok_or_else(|| invalid("directory is truncated"))
+}
+
+fn invalid(reason: &str) -> HandlerError {
+    HandlerError::ValidationError(format!("Invalid ICO: {}", reason))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_largest_frame_ico() {
+        let frame = largest_frame(include_bytes!("../tests/fixtures/icon.ico")).unwrap();
+        let image = image::load_from_memory(&frame).unwrap().to_rgba8();
+        assert_eq!(image.dimensions(), (64, 64));
+        assert_eq!(image.get_pixel(0, 0).0, [0, 0, 255, 255]);
+    }
+
+    #[test]
+    fn test_largest_frame_cur() {
+        let frame = largest_frame(include_bytes!("../tests/fixtures/icon.cur")).unwrap();
+        let image = image::load_from_memory(&frame).unwrap().to_rgba8();
+        assert_eq!(image.dimensions(), (32, 32));
+        assert_eq!(image.get_pixel(0, 0).0, [0, 255, 0, 255]);
+    }
+
+    #[test]
+    fn test_largest_frame_rejects_malformed() {
+        assert!(largest_frame(&[0, 0, 1, 0]).is_err());
+        assert!(largest_frame(&[0, 0, 1, 0, 0, 0]).is_err());
+
+        // Entry pointing past the end of the file
+        let mut ico = include_bytes!("../tests/fixtures/icon.ico").to_vec();
+        ico.truncate(100);
+        assert!(largest_frame(&ico).is_err());
+    }
+
+    #[test]
+    fn test_frames() {
+        let sizes: Vec<(u32, u32)> = frames(include_bytes!("../tests/fixtures/icon.ico"))
+            .unwrap()
+            .iter()
+            .map(|frame| image::load_from_memory(frame).unwrap().to_rgba8().dimensions())
+            .collect();
+        assert!(sizes.len() > 1);
+        assert!(sizes.contains(&(64, 64)));
+
+        // Directories claiming more images than allowed
+        let mut ico = include_bytes!("../tests/fixtures/icon.ico").to_vec();
+        ico[4..6].copy_from_slice(&((MAX_FRAMES + 1) as u16).to_le_bytes());
+        assert!(frames(&ico).is_err());
+    }
+}
@@ -4,7 +4,28 @@ pub mod storage;
 pub mod validation;
 pub mod error;
 pub mod admin;
+pub mod ownership;
 pub mod utils;
+pub mod multipart;
+pub mod generator;
+pub mod creation;
+pub mod ico;
+pub mod similarity;
+pub mod lineage;
+pub mod manifest;
+pub mod snippet;
+pub mod bundle;
+pub mod resize;
+pub mod animation;
+pub mod canvas;
+pub mod silhouette;
+pub mod vectorize;
+pub mod domain_verification;
+pub mod domain_lookup;
+pub mod import;
+pub mod rate_limit;
+pub mod api_keys;
+pub mod webhooks;
 
 pub use error::HandlerError;
 
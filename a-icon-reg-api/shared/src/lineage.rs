@@ -0,0 +1,72 @@
+use crate::database::Database;
+use crate::error::HandlerError;
+use crate::models::{Favicon, FaviconLink};
+
+/// Most forks listed in a favicon's details
+pub const MAX_LISTED_FORKS: i64 = 100;
+
+/// The published parent and the published forks of a favicon. A parent that
+/// was unpublished or deleted is left out.
+pub fn find_lineage(db: &Database, favicon: &Favicon) -> Result<(Option<FaviconLink>, Vec<FaviconLink>), HandlerError> {
+    let parent = match favicon.parent_id.as_deref() {
+        Some(parent_id) => db.get_favicon_by_id(parent_id)?
+            .filter(|parent| parent.is_published)
+            .map(FaviconLink::from_favicon),
+        None => None,
+    };
+
+    let children = db
+        .list_forks(&favicon.id, MAX_LISTED_FORKS)?
+        .into_iter()
+        .map(FaviconLink::from_favicon)
+        .collect();
+
+    Ok((parent, children))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{Duration, Utc};
+    use tempfile::NamedTempFile;
+
+    fn favicon(id: &str, parent_id: Option<&str>, is_published: bool, age_minutes: i64) -> Favicon {
+        let created_at = Utc::now() - Duration::minutes(age_minutes);
+        Favicon {
+            id: id.to_string(),
+            slug: format!("slug-{}", id),
+            published_url: format!("/f/slug-{}", id),
+            is_published,
+            created_at,
+            updated_at: created_at,
+            parent_id: parent_id.map(str::to_string),
+            ..Favicon::test_default()
+        }
+    }
+
+    #[test]
+    fn test_find_lineage() {
+        let file = NamedTempFile::new().unwrap();
+        let db = Database::new(file.path()).unwrap();
+        db.insert_favicon(&favicon("root", None, true, 10)).unwrap();
+        db.insert_favicon(&favicon("later", Some("root"), true, 1)).unwrap();
+        db.insert_favicon(&favicon("earlier", Some("root"), true, 5)).unwrap();
+        db.insert_favicon(&favicon("hidden", Some("root"), false, 3)).unwrap();
+        db.insert_favicon(&favicon("orphan", Some("deleted"), true, 2)).unwrap();
+
+        let root = db.get_favicon_by_id("root").unwrap().unwrap();
+        let (parent, children) = find_lineage(&db, &root).unwrap();
+        assert!(parent.is_none());
+        let ids: Vec<&str> = children.iter().map(|child| child.id.as_str()).collect();
+        assert_eq!(ids, vec!["earlier", "later"]);
+
+        let fork = db.get_favicon_by_id("earlier").unwrap().unwrap();
+        assert_eq!(fork.parent_id.as_deref(), Some("root"));
+        let (parent, children) = find_lineage(&db, &fork).unwrap();
+        assert_eq!(parent.unwrap().slug, "slug-root");
+        assert!(children.is_empty());
+
+        let orphan = db.get_favicon_by_id("orphan").unwrap().unwrap();
+        assert!(find_lineage(&db, &orphan).unwrap().0.is_none());
+    }
+}
@@ -0,0 +1,221 @@
+use crate::error::HandlerError;
+use crate::generator::GeneratedAsset;
+use crate::models::{asset_url, AssetType, AssetVariant, Favicon, FaviconAsset};
+use crate::validation::display_domain;
+use serde_json::json;
+
+pub const DEFAULT_THEME_COLOR: &str = "#ffffff";
+pub const DEFAULT_BACKGROUND_COLOR: &str = "#ffffff";
+
+/// Icon sizes listed in the web app manifest (Android home screen and splash)
+pub const MANIFEST_ICON_SIZES: [&str; 2] = ["192x192", "512x512"];
+
+/// `short_name` is truncated to what launchers display without ellipsis
+const SHORT_NAME_MAX_CHARS: usize = 12;
+
+/// Name shown for a favicon: its title, else its domain in Unicode form
+pub fn display_name(favicon: &Favicon) -> String {
+    favicon.title.clone()
+        .filter(|t| !t.trim().is_empty())
+        .or_else(|| favicon.target_domain.as_deref().map(display_domain))
+        .unwrap_or_else(|| "Favicon".to_string())
+}
+
+/// Build `site.webmanifest` and `browserconfig.xml` for a favicon, linking to
+/// the icons among `icons`
+pub fn build_manifest_assets(favicon: &Favicon, icons: &[GeneratedAsset]) -> Result<Vec<GeneratedAsset>, HandlerError> {
+    let resolve = |r#type: AssetType, size: &str| {
+        icons
+            .iter()
+            .find(|icon| icon.r#type == r#type && icon.size.as_deref() == Some(size) && icon.variant == AssetVariant::Light)
+            .map(|icon| asset_url(&icon.storage_key(&favicon.id)))
+    };
+
+    Ok(vec![
+        GeneratedAsset {
+            r#type: AssetType::Manifest,
+            size: None,
+            variant: AssetVariant::Light,
+            format: ".webmanifest".to_string(),
+            mime_type: "application/manifest+json".to_string(),
+            data: build_manifest(favicon, &resolve)?,
+        },
+        GeneratedAsset {
+            r#type: AssetType::Browserconfig,
+            size: None,
+            variant: AssetVariant::Light,
+            format: ".xml".to_string(),
+            mime_type: "application/xml".to_string(),
+            data: build_browserconfig(favicon, &resolve),
+        },
+    ])
+}
+
+/// Rebuild the stored `site.webmanifest` of a favicon from its existing
+/// assets, after details it includes (name, colors) changed. Returns the
+/// storage key to overwrite and the new contents, or `None` if the favicon
+/// has no manifest.
+pub fn rebuild_stored_manifest(favicon: &Favicon, assets: &[FaviconAsset]) -> Result<Option<(String, Vec<u8>)>, HandlerError> {
+    let Some(manifest) = assets.iter().find(|asset| asset.r#type == AssetType::Manifest) else {
+        return Ok(None);
+    };
+    let resolve = |r#type: AssetType, size: &str| {
+        assets
+            .iter()
+            .find(|asset| asset.r#type == r#type && asset.size.as_deref() == Some(size) && asset.variant == AssetVariant::Light)
+            .map(|asset| asset_url(&asset.storage_key))
+    };
+    Ok(Some((manifest.storage_key.clone(), build_manifest(favicon, &resolve)?)))
+}
+
+/// Encode a web app manifest. `resolve` maps an icon's type and size to the
+/// URL it is served from, or `None` if the icon does not exist.
+pub fn build_manifest(favicon: &Favicon, resolve: &dyn Fn(AssetType, &str) -> Option<String>) -> Result<Vec<u8>, HandlerError> {
+    let name = display_name(favicon);
+    let short_name: String = name.chars().take(SHORT_NAME_MAX_CHARS).collect();
+    let short_name = short_name.trim_end();
+
+    let mut entries = Vec::new();
+    for (r#type, purpose) in [(AssetType::Png, "any"), (AssetType::Maskable, "maskable")] {
+        for size in MANIFEST_ICON_SIZES {
+            if let Some(src) = resolve(r#type, size) {
+                entries.push(json!({
+                    "src": src,
+                    "sizes": size,
+                    "type": "image/png",
+                    "purpose": purpose,
+                }));
+            }
+        }
+    }
+
+    let manifest = json!({
+        "name": name,
+        "short_name": short_name,
+        "icons": entries,
+        "theme_color": favicon.theme_color.as_deref().unwrap_or(DEFAULT_THEME_COLOR),
+        "background_color": favicon.background_color.as_deref().unwrap_or(DEFAULT_BACKGROUND_COLOR),
+        "display": "standalone",
+    });
+
+    serde_json::to_vec_pretty(&manifest)
+        .map_err(|e| HandlerError::InternalError(format!("Failed to encode manifest: {}", e)))
+}
+
+/// Encode a browserconfig.xml, resolving tile images like [`build_manifest`]
+pub fn build_browserconfig(favicon: &Favicon, resolve: &dyn Fn(AssetType, &str) -> Option<String>) -> Vec<u8> {
+    let mut tiles = String::new();
+    for (element, size) in [
+        ("square70x70logo", "70x70"),
+        ("square150x150logo", "150x150"),
+        ("square310x310logo", "310x310"),
+    ] {
+        if let Some(src) = resolve(AssetType::Png, size) {
+            tiles.push_str(&format!("      <{} src=\"{}\"/>\n", element, xml_escape(&src)));
+        }
+    }
+
+    // Colors are validated as #rrggbb, so they need no escaping
+    let tile_color = favicon.theme_color.as_deref().unwrap_or(DEFAULT_THEME_COLOR);
+    format!(
+        "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n<browserconfig>\n  <msapplication>\n    <tile>\n{}      <TileColor>{}</TileColor>\n    </tile>\n  </msapplication>\n</browserconfig>\n",
+        tiles, tile_color
+    )
+    .into_bytes()
+}
+
+pub fn xml_escape(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::GenerationStatus;
+    use chrono::Utc;
+
+    fn favicon() -> Favicon {
+        Favicon {
+            title: Some("A very long favicon title".to_string()),
+            target_domain: Some("example.com".to_string()),
+            generation_status: GenerationStatus::Pending,
+            theme_color: Some("#123456".to_string()),
+            ..Favicon::test_default()
+        }
+    }
+
+    fn icon(r#type: AssetType, size: &str) -> GeneratedAsset {
+        GeneratedAsset {
+            r#type,
+            size: Some(size.to_string()),
+            variant: AssetVariant::Light,
+            format: ".png".to_string(),
+            mime_type: "image/png".to_string(),
+            data: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_manifest() {
+        let icons = vec![
+            icon(AssetType::Png, "192x192"),
+            icon(AssetType::Png, "512x512"),
+            icon(AssetType::Maskable, "512x512"),
+        ];
+        let assets = build_manifest_assets(&favicon(), &icons).unwrap();
+        let manifest: serde_json::Value = serde_json::from_slice(&assets[0].data).unwrap();
+
+        assert_eq!(manifest["name"], "A very long favicon title");
+        assert_eq!(manifest["short_name"], "A very long");
+        assert_eq!(manifest["theme_color"], "#123456");
+        assert_eq!(manifest["background_color"], DEFAULT_BACKGROUND_COLOR);
+        assert_eq!(manifest["icons"].as_array().unwrap().len(), 3);
+        assert_eq!(manifest["icons"][2]["purpose"], "maskable");
+        assert_eq!(manifest["icons"][2]["src"], "/api/storage/abc/MASKABLE-512x512.png");
+    }
+
+    #[test]
+    fn test_browserconfig() {
+        let icons = vec![icon(AssetType::Png, "150x150")];
+        let assets = build_manifest_assets(&favicon(), &icons).unwrap();
+        let xml = String::from_utf8(assets[1].data.clone()).unwrap();
+
+        assert!(xml.contains(r#"<square150x150logo src="/api/storage/abc/PNG-150x150.png"/>"#));
+        assert!(!xml.contains("square70x70logo"));
+        assert!(xml.contains("<TileColor>#123456</TileColor>"));
+    }
+
+    #[test]
+    fn test_rebuild_stored_manifest() {
+        let stored = |r#type: AssetType, size: Option<&str>, format: &str| FaviconAsset {
+            id: r#type.as_str().to_string(),
+            favicon_id: "abc".to_string(),
+            r#type,
+            size: size.map(str::to_string),
+            variant: AssetVariant::Light,
+            format: format.to_string(),
+            storage_key: format!("abc/{}-{}{}", r#type.as_str(), size.unwrap_or("canonical"), format),
+            mime_type: "image/png".to_string(),
+            created_at: Utc::now(),
+        };
+        let mut favicon = favicon();
+        favicon.title = Some("Renamed".to_string());
+
+        assert!(rebuild_stored_manifest(&favicon, &[stored(AssetType::Png, Some("192x192"), ".png")]).unwrap().is_none());
+
+        let assets = vec![
+            stored(AssetType::Png, Some("192x192"), ".png"),
+            stored(AssetType::Manifest, None, ".webmanifest"),
+        ];
+        let (key, data) = rebuild_stored_manifest(&favicon, &assets).unwrap().unwrap();
+        let manifest: serde_json::Value = serde_json::from_slice(&data).unwrap();
+        assert_eq!(key, "abc/MANIFEST-canonical.webmanifest");
+        assert_eq!(manifest["name"], "Renamed");
+        assert_eq!(manifest["icons"][0]["src"], "/api/storage/abc/PNG-192x192.png");
+    }
+}
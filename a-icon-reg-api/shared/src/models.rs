@@ -1,5 +1,7 @@
 use serde::{Deserialize, Serialize};
 use chrono::{DateTime, Utc};
+use crate::manifest::{DEFAULT_BACKGROUND_COLOR, DEFAULT_THEME_COLOR};
+use crate::validation::display_domain;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Favicon {
@@ -21,6 +23,165 @@ pub struct Favicon {
     pub generation_error: Option<String>,
     pub metadata: Option<String>,
     pub has_steganography: bool,
+    pub source_width: Option<i64>,
+    pub source_height: Option<i64>,
+    pub source_color_type: Option<String>,
+    /// 64-bit dHash of the normalized source, stored as its bit pattern
+    pub perceptual_hash: Option<i64>,
+    /// Web app manifest colors as `#rrggbb`
+    pub theme_color: Option<String>,
+    pub background_color: Option<String>,
+    /// Transformations applied when generating assets, stored as JSON so
+    /// regeneration reproduces the same output. `None` means the defaults.
+    pub generation_options: Option<GenerationOptions>,
+    /// MIME type of the optional dark-mode source at `sources/{id}/dark`
+    pub dark_source_mime: Option<String>,
+    /// How the canonical SVG was produced; `None` until generation succeeds
+    pub svg_method: Option<SvgMethod>,
+    /// Whether the editable canvas document is stored at `sources/{id}/document`
+    pub has_canvas_document: bool,
+    /// Favicon this one was forked from
+    pub parent_id: Option<String>,
+    /// When the owner proved control of `target_domain`; at most one favicon
+    /// per domain has it set
+    pub domain_verified_at: Option<DateTime<Utc>>,
+    /// API key the favicon was created with, if any
+    pub api_key_id: Option<String>,
+}
+
+impl Favicon {
+    /// Whether this favicon was generated with the given options and
+    /// manifest colors, so that a new upload of the same source can reuse
+    /// its assets. Unset colors stand for their defaults.
+    pub fn generated_with(
+        &self,
+        generation_options: Option<&GenerationOptions>,
+        theme_color: Option<&str>,
+        background_color: Option<&str>,
+    ) -> bool {
+        self.generation_options.as_ref() == generation_options
+            && self.theme_color.as_deref().unwrap_or(DEFAULT_THEME_COLOR) == theme_color.unwrap_or(DEFAULT_THEME_COLOR)
+            && self.background_color.as_deref().unwrap_or(DEFAULT_BACKGROUND_COLOR)
+                == background_color.unwrap_or(DEFAULT_BACKGROUND_COLOR)
+    }
+}
+
+#[cfg(test)]
+impl Favicon {
+    /// A published, successfully generated PNG upload with no optional
+    /// fields set, for tests to override with struct update syntax
+    pub(crate) fn test_default() -> Self {
+        let now = Utc::now();
+        Favicon {
+            id: "abc".to_string(),
+            slug: "slug".to_string(),
+            title: None,
+            target_domain: None,
+            published_url: "/f/slug".to_string(),
+            canonical_svg_key: None,
+            source_type: SourceType::Upload,
+            source_original_mime: Some("image/png".to_string()),
+            source_hash: None,
+            source_size: None,
+            is_published: true,
+            created_at: now,
+            updated_at: now,
+            generated_at: None,
+            generation_status: GenerationStatus::Success,
+            generation_error: None,
+            metadata: None,
+            has_steganography: false,
+            source_width: None,
+            source_height: None,
+            source_color_type: None,
+            perceptual_hash: None,
+            theme_color: None,
+            background_color: None,
+            generation_options: None,
+            dark_source_mime: None,
+            svg_method: None,
+            has_canvas_document: false,
+            parent_id: None,
+            domain_verified_at: None,
+            api_key_id: None,
+        }
+    }
+}
+
+/// Caller-supplied transformations applied to the source before generation
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct GenerationOptions {
+    /// `transparent` or `#rrggbb` flattened behind the icons. Maskable icons
+    /// are always padded with the favicon's `background_color`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub background: Option<String>,
+    /// Padding on each edge of maskable icons, in percent of the icon size
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub maskable_padding: Option<u32>,
+    /// Corner radius in percent of the icon size; 50 gives a circle
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub corner_radius: Option<u32>,
+    #[serde(default)]
+    pub fit: FitMode,
+    /// Region of the source to use, in source pixels. Required with `fit: crop`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub crop: Option<CropRect>,
+    /// Derive dark-mode icons from the source when no dark source is uploaded
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dark_variant: Option<DarkVariant>,
+    /// `#rrggbb` Safari tints the pinned-tab icon with; defaults to the theme color
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mask_icon_color: Option<String>,
+    /// Frame of an animated source used for static assets; defaults to the first
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub frame_index: Option<u32>,
+}
+
+/// Rule for deriving dark-mode icons from the light source
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "mode", rename_all = "lowercase", deny_unknown_fields)]
+pub enum DarkVariant {
+    /// Invert the colors, keeping transparency
+    Invert,
+    /// Paint every visible pixel in one `#rrggbb` color, keeping transparency
+    Recolor { color: String },
+}
+
+/// How a non-square source is made square
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FitMode {
+    /// Scale to fit inside the square, padding the short side
+    Contain,
+    /// Scale to fill the square, trimming the long side around the center
+    #[default]
+    Cover,
+    /// Use the `crop` rectangle, then fill the square like `cover`
+    Crop,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CropRect {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl GenerationOptions {
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).unwrap_or_default()
+    }
+
+    pub fn from_json(json: &str) -> Option<Self> {
+        serde_json::from_str(json).ok()
+    }
+
+    /// Whether generation leaves the source untouched apart from resizing
+    pub fn is_default(&self) -> bool {
+        *self == GenerationOptions::default()
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -28,6 +189,8 @@ pub struct Favicon {
 pub enum SourceType {
     Upload,
     Canvas,
+    /// Downloaded from the icons a website links to
+    Import,
 }
 
 impl SourceType {
@@ -35,6 +198,7 @@ impl SourceType {
         match self {
             SourceType::Upload => "UPLOAD",
             SourceType::Canvas => "CANVAS",
+            SourceType::Import => "IMPORT",
         }
     }
 
@@ -42,6 +206,7 @@ impl SourceType {
         match s {
             "UPLOAD" => Some(SourceType::Upload),
             "CANVAS" => Some(SourceType::Canvas),
+            "IMPORT" => Some(SourceType::Import),
             _ => None,
         }
     }
@@ -74,24 +239,240 @@ impl GenerationStatus {
     }
 }
 
+/// A pending direct-to-storage upload. The id is reserved as the id of the
+/// favicon that will be created once the upload is finalized.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UploadIntent {
+    pub id: String,
+    pub staging_key: String,
+    pub content_type: String,
+    pub title: Option<String>,
+    pub target_domain: Option<String>,
+    pub metadata: Option<String>,
+    pub theme_color: Option<String>,
+    pub background_color: Option<String>,
+    /// Options the favicon is generated with once the upload is finalized
+    pub generation_options: Option<GenerationOptions>,
+    pub expires_at: DateTime<Utc>,
+    pub created_at: DateTime<Utc>,
+    /// API key the intent was created with, passed on to the favicon
+    pub api_key_id: Option<String>,
+    /// SHA-256 hex digest of the upload secret returned when the intent was
+    /// created; finalizing requires the secret
+    pub secret_hash: String,
+    /// Favicon the upload was finalized into, an existing one if it was a
+    /// duplicate. Set once the intent has been finalized.
+    pub favicon_id: Option<String>,
+}
+
+/// Token the owner of a favicon publishes on its target domain to prove
+/// control of it. The domain is stored lowercase.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DomainChallenge {
+    pub favicon_id: String,
+    pub domain: String,
+    pub token: String,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Token bucket and daily counters of one client for one endpoint
+#[derive(Debug, Clone, PartialEq)]
+pub struct RateLimitState {
+    pub tokens: f64,
+    pub refilled_at: DateTime<Utc>,
+    /// UTC day the counters are for, as `YYYY-MM-DD`
+    pub day: String,
+    pub requests: i64,
+    pub bytes: i64,
+}
+
+/// A key an admin issued to an API client. Only the SHA-256 hex digest of
+/// the key is stored; `prefix` is kept to tell keys apart.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ApiKey {
+    pub id: String,
+    pub name: String,
+    pub key_hash: String,
+    pub prefix: String,
+    pub scopes: Vec<ApiKeyScope>,
+    pub created_at: DateTime<Utc>,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub last_used_at: Option<DateTime<Utc>>,
+    pub revoked_at: Option<DateTime<Utc>>,
+}
+
+impl ApiKey {
+    /// Whether the key can still be used at `now`
+    pub fn is_active(&self, now: DateTime<Utc>) -> bool {
+        self.revoked_at.is_none() && self.expires_at.is_none_or(|expires_at| expires_at > now)
+    }
+
+    pub fn has_scope(&self, scope: ApiKeyScope) -> bool {
+        self.scopes.contains(&scope)
+    }
+}
+
+/// What an API key is allowed to do
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ApiKeyScope {
+    /// Create favicons through any of the creation endpoints
+    #[serde(rename = "favicons:create")]
+    FaviconsCreate,
+    /// Read favicons that are not published
+    #[serde(rename = "favicons:read-unpublished")]
+    FaviconsReadUnpublished,
+    /// Delete favicons through the admin endpoint
+    #[serde(rename = "admin:delete")]
+    AdminDelete,
+}
+
+impl ApiKeyScope {
+    pub const ALL: [ApiKeyScope; 3] = [
+        ApiKeyScope::FaviconsCreate,
+        ApiKeyScope::FaviconsReadUnpublished,
+        ApiKeyScope::AdminDelete,
+    ];
+
+    pub fn as_str(&self) -> &str {
+        match self {
+            ApiKeyScope::FaviconsCreate => "favicons:create",
+            ApiKeyScope::FaviconsReadUnpublished => "favicons:read-unpublished",
+            ApiKeyScope::AdminDelete => "admin:delete",
+        }
+    }
+
+    pub fn from_str(s: &str) -> Option<Self> {
+        ApiKeyScope::ALL.into_iter().find(|scope| scope.as_str() == s)
+    }
+}
+
+/// An admin's subscription to favicon events. The secret signs deliveries,
+/// so it is stored as given.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Webhook {
+    pub id: String,
+    pub url: String,
+    pub events: Vec<WebhookEvent>,
+    pub secret: String,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum WebhookEvent {
+    /// A favicon was created by any of the creation endpoints
+    #[serde(rename = "favicon.created")]
+    FaviconCreated,
+    /// A favicon's assets were generated
+    #[serde(rename = "favicon.generated")]
+    FaviconGenerated,
+    /// Generating a favicon's assets failed; the favicon carries the error
+    #[serde(rename = "favicon.generation_failed")]
+    FaviconGenerationFailed,
+    /// A favicon was deleted by its owner or an admin
+    #[serde(rename = "favicon.deleted")]
+    FaviconDeleted,
+}
+
+impl WebhookEvent {
+    pub const ALL: [WebhookEvent; 4] = [
+        WebhookEvent::FaviconCreated,
+        WebhookEvent::FaviconGenerated,
+        WebhookEvent::FaviconGenerationFailed,
+        WebhookEvent::FaviconDeleted,
+    ];
+
+    pub fn as_str(&self) -> &str {
+        match self {
+            WebhookEvent::FaviconCreated => "favicon.created",
+            WebhookEvent::FaviconGenerated => "favicon.generated",
+            WebhookEvent::FaviconGenerationFailed => "favicon.generation_failed",
+            WebhookEvent::FaviconDeleted => "favicon.deleted",
+        }
+    }
+
+    pub fn from_str(s: &str) -> Option<Self> {
+        WebhookEvent::ALL.into_iter().find(|event| event.as_str() == s)
+    }
+}
+
+/// One event queued for one webhook, in the outbox
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WebhookDelivery {
+    pub id: String,
+    pub webhook_id: String,
+    pub event: WebhookEvent,
+    /// JSON body, the same for every webhook the event is sent to
+    pub payload: String,
+    pub status: DeliveryStatus,
+    pub attempts: i64,
+    /// When the next attempt is due; while an attempt runs, when it may be
+    /// taken over
+    pub next_attempt_at: DateTime<Utc>,
+    pub last_error: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub delivered_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum DeliveryStatus {
+    Pending,
+    Delivered,
+    /// Gave up after the last retry
+    Failed,
+}
+
+impl DeliveryStatus {
+    pub fn as_str(&self) -> &str {
+        match self {
+            DeliveryStatus::Pending => "PENDING",
+            DeliveryStatus::Delivered => "DELIVERED",
+            DeliveryStatus::Failed => "FAILED",
+        }
+    }
+
+    pub fn from_str(s: &str) -> Option<Self> {
+        match s {
+            "PENDING" => Some(DeliveryStatus::Pending),
+            "DELIVERED" => Some(DeliveryStatus::Delivered),
+            "FAILED" => Some(DeliveryStatus::Failed),
+            _ => None,
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct FaviconAsset {
     pub id: String,
     pub favicon_id: String,
     pub r#type: AssetType,
     pub size: Option<String>,
+    pub variant: AssetVariant,
     pub format: String,
     pub storage_key: String,
     pub mime_type: String,
     pub created_at: DateTime<Utc>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 #[serde(rename_all = "UPPERCASE")]
 pub enum AssetType {
     Ico,
     Png,
     Svg,
+    /// PNG with safe-zone padding, for `"purpose": "maskable"` manifest icons
+    Maskable,
+    /// Single-color SVG silhouette for Safari pinned tabs
+    #[serde(rename = "MASK_ICON")]
+    MaskIcon,
+    /// Looping APNG rendered from an animated source
+    #[serde(rename = "ANIMATED_PNG")]
+    AnimatedPng,
+    /// Looping WebP rendered from an animated source
+    #[serde(rename = "ANIMATED_WEBP")]
+    AnimatedWebp,
+    Manifest,
+    Browserconfig,
 }
 
 impl AssetType {
@@ -100,6 +481,12 @@ impl AssetType {
             AssetType::Ico => "ICO",
             AssetType::Png => "PNG",
             AssetType::Svg => "SVG",
+            AssetType::Maskable => "MASKABLE",
+            AssetType::MaskIcon => "MASK_ICON",
+            AssetType::AnimatedPng => "ANIMATED_PNG",
+            AssetType::AnimatedWebp => "ANIMATED_WEBP",
+            AssetType::Manifest => "MANIFEST",
+            AssetType::Browserconfig => "BROWSERCONFIG",
         }
     }
 
@@ -108,6 +495,87 @@ impl AssetType {
             "ICO" => Some(AssetType::Ico),
             "PNG" => Some(AssetType::Png),
             "SVG" => Some(AssetType::Svg),
+            "MASKABLE" => Some(AssetType::Maskable),
+            "MASK_ICON" => Some(AssetType::MaskIcon),
+            "ANIMATED_PNG" => Some(AssetType::AnimatedPng),
+            "ANIMATED_WEBP" => Some(AssetType::AnimatedWebp),
+            "MANIFEST" => Some(AssetType::Manifest),
+            "BROWSERCONFIG" => Some(AssetType::Browserconfig),
+            _ => None,
+        }
+    }
+
+    pub fn is_animated(&self) -> bool {
+        matches!(self, AssetType::AnimatedPng | AssetType::AnimatedWebp)
+    }
+}
+
+/// Color scheme an asset is meant for
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum AssetVariant {
+    #[default]
+    Light,
+    /// Rendered from the dark-mode source
+    Dark,
+    /// SVG that switches between light and dark with `prefers-color-scheme`
+    Adaptive,
+}
+
+impl AssetVariant {
+    pub fn as_str(&self) -> &str {
+        match self {
+            AssetVariant::Light => "LIGHT",
+            AssetVariant::Dark => "DARK",
+            AssetVariant::Adaptive => "ADAPTIVE",
+        }
+    }
+
+    pub fn from_str(s: &str) -> Option<Self> {
+        match s {
+            "LIGHT" => Some(AssetVariant::Light),
+            "DARK" => Some(AssetVariant::Dark),
+            "ADAPTIVE" => Some(AssetVariant::Adaptive),
+            _ => None,
+        }
+    }
+
+    /// Appended to storage keys and bundle filenames of non-default variants
+    pub fn suffix(&self) -> &str {
+        match self {
+            AssetVariant::Light => "",
+            AssetVariant::Dark => "-dark",
+            AssetVariant::Adaptive => "-adaptive",
+        }
+    }
+}
+
+/// How the canonical SVG was produced
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum SvgMethod {
+    /// The uploaded SVG, kept as-is
+    Source,
+    /// Raster source traced into filled paths
+    Traced,
+    /// Raster source embedded as a PNG `<image>`
+    Embedded,
+}
+
+impl SvgMethod {
+    pub fn as_str(&self) -> &str {
+        match self {
+            SvgMethod::Source => "SOURCE",
+            SvgMethod::Traced => "TRACED",
+            SvgMethod::Embedded => "EMBEDDED",
+        }
+    }
+
+    pub fn from_str(s: &str) -> Option<Self> {
+        match s {
+            "SOURCE" => Some(SvgMethod::Source),
+            "TRACED" => Some(SvgMethod::Traced),
+            "EMBEDDED" => Some(SvgMethod::Embedded),
             _ => None,
         }
     }
@@ -119,10 +587,26 @@ pub struct FaviconDetailResponse {
     pub id: String,
     pub slug: String,
     pub title: Option<String>,
+    /// Target domain for display, with internationalized labels in Unicode
     pub target_domain: Option<String>,
+    /// Target domain as stored and matched, in ASCII (punycode) form
+    pub target_domain_ascii: Option<String>,
+    pub domain_verified_at: Option<String>,
     pub published_url: String,
     pub source_url: String,
     pub source_type: String,
+    pub source_width: Option<i64>,
+    pub source_height: Option<i64>,
+    pub source_color_type: Option<String>,
+    pub theme_color: Option<String>,
+    pub background_color: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub generation_options: Option<GenerationOptions>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub svg_method: Option<String>,
+    /// Canvas document to reopen the favicon for editing
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub document_url: Option<String>,
     pub is_published: bool,
     pub created_at: String,
     pub generated_at: Option<String>,
@@ -131,6 +615,67 @@ pub struct FaviconDetailResponse {
     pub metadata: Option<String>,
     pub has_steganography: bool,
     pub assets: Vec<AssetResponse>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub similar_favicons: Option<Vec<SimilarFavicon>>,
+    /// Published favicon this one was forked from
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub parent: Option<FaviconLink>,
+    /// Published forks of this favicon, oldest first
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub children: Option<Vec<FaviconLink>>,
+    /// Secret for the owner endpoints, only returned when the favicon is created
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub edit_token: Option<String>,
+    /// URL of the icon an import downloaded, only returned by the import
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub imported_from: Option<String>,
+}
+
+/// Reference to a related favicon
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FaviconLink {
+    pub id: String,
+    pub slug: String,
+    pub title: Option<String>,
+    pub published_url: String,
+}
+
+impl FaviconLink {
+    pub fn from_favicon(favicon: Favicon) -> Self {
+        FaviconLink {
+            id: favicon.id,
+            slug: favicon.slug,
+            title: favicon.title,
+            published_url: favicon.published_url,
+        }
+    }
+}
+
+/// An existing favicon that looks like another one
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SimilarFavicon {
+    pub id: String,
+    pub slug: String,
+    pub title: Option<String>,
+    pub target_domain: Option<String>,
+    pub published_url: String,
+    /// Hamming distance between the perceptual hashes (0 = visually identical)
+    pub distance: u32,
+}
+
+impl SimilarFavicon {
+    pub fn from_favicon(favicon: Favicon, distance: u32) -> Self {
+        SimilarFavicon {
+            id: favicon.id,
+            slug: favicon.slug,
+            title: favicon.title,
+            target_domain: favicon.target_domain.as_deref().map(display_domain),
+            published_url: favicon.published_url,
+            distance,
+        }
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -139,34 +684,55 @@ pub struct AssetResponse {
     pub id: String,
     pub r#type: String,
     pub size: Option<String>,
+    pub variant: String,
+    pub animated: bool,
     pub format: String,
     pub mime_type: String,
     pub url: String,
 }
 
+/// API path that serves a stored asset
+pub fn asset_url(storage_key: &str) -> String {
+    format!("/api/storage/{}", storage_key)
+}
+
 impl AssetResponse {
     pub fn from_asset(asset: FaviconAsset) -> Self {
         AssetResponse {
             id: asset.id,
             r#type: asset.r#type.as_str().to_string(),
             size: asset.size,
+            variant: asset.variant.as_str().to_string(),
+            animated: asset.r#type.is_animated(),
             format: asset.format,
             mime_type: asset.mime_type,
-            url: format!("/api/storage/{}", asset.storage_key),
+            url: asset_url(&asset.storage_key),
         }
     }
 }
 
 impl FaviconDetailResponse {
     pub fn from_favicon_and_assets(favicon: Favicon, assets: Vec<FaviconAsset>) -> Self {
+        let document_url = favicon.has_canvas_document
+            .then(|| format!("/api/favicons/{}/document", favicon.slug));
         FaviconDetailResponse {
             id: favicon.id.clone(),
             slug: favicon.slug,
             title: favicon.title,
-            target_domain: favicon.target_domain,
+            target_domain: favicon.target_domain.as_deref().map(display_domain),
+            target_domain_ascii: favicon.target_domain,
+            domain_verified_at: favicon.domain_verified_at.map(|dt| dt.to_rfc3339()),
             published_url: favicon.published_url,
             source_url: format!("/api/storage/sources/{}/original", favicon.id),
             source_type: favicon.source_type.as_str().to_string(),
+            source_width: favicon.source_width,
+            source_height: favicon.source_height,
+            source_color_type: favicon.source_color_type,
+            theme_color: favicon.theme_color,
+            background_color: favicon.background_color,
+            generation_options: favicon.generation_options,
+            svg_method: favicon.svg_method.map(|method| method.as_str().to_string()),
+            document_url,
             is_published: favicon.is_published,
             created_at: favicon.created_at.to_rfc3339(),
             generated_at: favicon.generated_at.map(|dt| dt.to_rfc3339()),
@@ -175,16 +741,128 @@ impl FaviconDetailResponse {
             metadata: favicon.metadata,
             has_steganography: favicon.has_steganography,
             assets: assets.into_iter().map(AssetResponse::from_asset).collect(),
+            similar_favicons: None,
+            parent: None,
+            children: None,
+            edit_token: None,
+            imported_from: None,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UploadIntentResponse {
+    pub id: String,
+    pub upload_url: String,
+    pub upload_method: String,
+    pub content_type: String,
+    pub content_length: i64,
+    pub max_size: i64,
+    pub expires_at: String,
+    pub finalize_url: String,
+    /// Secret to finalize the intent with; it cannot be recovered
+    pub upload_secret: String,
+}
+
+/// How to publish a domain verification token
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DomainChallengeResponse {
+    pub domain: String,
+    pub token: String,
+    pub dns_record_name: String,
+    pub dns_record_type: String,
+    pub dns_record_value: String,
+    pub well_known_url: String,
+    pub created_at: String,
+    pub verify_url: String,
+}
+
+/// An API key as shown to admins
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ApiKeyResponse {
+    pub id: String,
+    pub name: String,
+    pub prefix: String,
+    pub scopes: Vec<String>,
+    pub created_at: String,
+    pub expires_at: Option<String>,
+    pub last_used_at: Option<String>,
+    pub revoked_at: Option<String>,
+    /// Favicons created with the key, only returned by the list
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub favicon_count: Option<i64>,
+    /// The key itself, only returned when it is created
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub key: Option<String>,
+}
+
+impl ApiKeyResponse {
+    pub fn from_api_key(api_key: ApiKey) -> Self {
+        ApiKeyResponse {
+            id: api_key.id,
+            name: api_key.name,
+            prefix: api_key.prefix,
+            scopes: api_key.scopes.iter().map(|scope| scope.as_str().to_string()).collect(),
+            created_at: api_key.created_at.to_rfc3339(),
+            expires_at: api_key.expires_at.map(|dt| dt.to_rfc3339()),
+            last_used_at: api_key.last_used_at.map(|dt| dt.to_rfc3339()),
+            revoked_at: api_key.revoked_at.map(|dt| dt.to_rfc3339()),
+            favicon_count: None,
+            key: None,
         }
     }
 }
 
+/// A webhook as shown to admins
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WebhookResponse {
+    pub id: String,
+    pub url: String,
+    pub events: Vec<String>,
+    pub created_at: String,
+    /// Deliveries by status, only returned by the list
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub deliveries: Option<WebhookDeliveryCounts>,
+    /// Signing secret, only returned when the webhook is created
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub secret: Option<String>,
+}
+
+impl WebhookResponse {
+    pub fn from_webhook(webhook: Webhook) -> Self {
+        WebhookResponse {
+            id: webhook.id,
+            url: webhook.url,
+            events: webhook.events.iter().map(|event| event.as_str().to_string()).collect(),
+            created_at: webhook.created_at.to_rfc3339(),
+            deliveries: None,
+            secret: None,
+        }
+    }
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WebhookDeliveryCounts {
+    pub pending: i64,
+    pub delivered: i64,
+    pub failed: i64,
+    /// Error of the most recent failed attempt
+    pub last_error: Option<String>,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct DirectoryItem {
     pub id: String,
     pub slug: String,
     pub title: Option<String>,
     pub target_domain: Option<String>,
+    /// Whether the owner proved control of `target_domain`
+    pub domain_verified: bool,
     pub published_url: String,
     pub created_at: String,
 }
@@ -0,0 +1,726 @@
+use crate::error::HandlerError;
+use memchr::memmem;
+
+/// Limits applied while parsing a multipart/form-data body
+#[derive(Debug, Clone, Copy)]
+pub struct MultipartLimits {
+    /// Maximum size of a single part's content
+    pub max_part_size: usize,
+    /// Maximum size of the whole body, including delimiters and headers
+    pub max_total_size: usize,
+    /// Maximum number of parts
+    pub max_parts: usize,
+    /// Maximum size of a single part's header block
+    pub max_header_size: usize,
+}
+
+impl Default for MultipartLimits {
+    fn default() -> Self {
+        MultipartLimits {
+            max_part_size: 1024 * 1024,
+            max_total_size: 2 * 1024 * 1024,
+            max_parts: 16,
+            max_header_size: 8 * 1024,
+        }
+    }
+}
+
+/// Parse a complete multipart/form-data body with the default limits. The
+/// body is fed in one piece; use [`MultipartParser`] to parse it as it arrives.
+pub fn parse_multipart(body: &[u8], boundary: &str) -> Result<MultipartData, HandlerError> {
+    let mut parser = MultipartParser::new(boundary, MultipartLimits::default())?;
+    parser.feed(body)?;
+    parser.finish()
+}
+
+/// Extract the boundary parameter from a `multipart/form-data` Content-Type header
+pub fn boundary_from_content_type(content_type: &str) -> Result<String, HandlerError> {
+    let (media_type, params) = parse_header_value(content_type)?;
+    if !media_type.eq_ignore_ascii_case("multipart/form-data") {
+        return Err(HandlerError::ValidationError(
+            "Content-Type must be multipart/form-data".to_string(),
+        ));
+    }
+
+    params
+        .into_iter()
+        .find(|(name, _)| name == "boundary")
+        .map(|(_, value)| value)
+        .ok_or_else(|| HandlerError::ValidationError("Missing boundary in Content-Type".to_string()))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum State {
+    /// Before the first delimiter; content is discarded
+    Preamble,
+    /// Just after a delimiter; either `--` (close) or padding + CRLF follows
+    AfterDelimiter,
+    /// Reading a part's header block
+    Headers,
+    /// Reading a part's content
+    Body,
+    /// After the close delimiter; content is discarded
+    Epilogue,
+}
+
+struct PendingPart {
+    name: String,
+    filename: Option<String>,
+    content_type: Option<String>,
+    content: Vec<u8>,
+}
+
+/// Incremental RFC 7578 multipart/form-data parser.
+///
+/// Bytes can be fed in arbitrarily sized chunks; the parser only buffers as
+/// much as it needs to recognize a delimiter, and enforces the configured
+/// limits as data arrives rather than after the whole body is read.
+pub struct MultipartParser {
+    delimiter: Vec<u8>,
+    finder: memmem::Finder<'static>,
+    limits: MultipartLimits,
+    state: State,
+    buffer: Vec<u8>,
+    total_size: usize,
+    part_count: usize,
+    current: Option<PendingPart>,
+    parts: Vec<MultipartPart>,
+}
+
+impl MultipartParser {
+    pub fn new(boundary: &str, limits: MultipartLimits) -> Result<Self, HandlerError> {
+        validate_boundary(boundary)?;
+
+        // Every delimiter, including the first, is preceded by CRLF. Seeding
+        // the buffer with one lets a body that starts with the boundary match.
+        let delimiter = format!("\r\n--{}", boundary).into_bytes();
+        let finder = memmem::Finder::new(&delimiter).into_owned();
+
+        Ok(MultipartParser {
+            delimiter,
+            finder,
+            limits,
+            state: State::Preamble,
+            buffer: b"\r\n".to_vec(),
+            total_size: 0,
+            part_count: 0,
+            current: None,
+            parts: Vec::new(),
+        })
+    }
+
+    /// Feed the next chunk of the body
+    pub fn feed(&mut self, chunk: &[u8]) -> Result<(), HandlerError> {
+        self.total_size += chunk.len();
+        if self.total_size > self.limits.max_total_size {
+            return Err(HandlerError::ValidationError(format!(
+                "Request body exceeds the maximum allowed size of {} bytes",
+                self.limits.max_total_size
+            )));
+        }
+
+        if self.state == State::Epilogue {
+            return Ok(());
+        }
+
+        self.buffer.extend_from_slice(chunk);
+        while self.step()? {}
+        Ok(())
+    }
+
+    /// Finish parsing. Fails if the close delimiter was never seen.
+    pub fn finish(self) -> Result<MultipartData, HandlerError> {
+        if self.state != State::Epilogue {
+            return Err(HandlerError::BadRequest(
+                "Malformed multipart body: missing closing boundary".to_string(),
+            ));
+        }
+        Ok(MultipartData { parts: self.parts })
+    }
+
+    /// Advance the state machine once. Returns false when more input is needed.
+    fn step(&mut self) -> Result<bool, HandlerError> {
+        match self.state {
+            State::Preamble => match self.finder.find(&self.buffer) {
+                Some(pos) => {
+                    self.buffer.drain(..pos + self.delimiter.len());
+                    self.state = State::AfterDelimiter;
+                    Ok(true)
+                }
+                None => {
+                    let keep = self.delimiter.len() - 1;
+                    if self.buffer.len() > keep {
+                        self.buffer.drain(..self.buffer.len() - keep);
+                    }
+                    Ok(false)
+                }
+            },
+            State::AfterDelimiter => {
+                if self.buffer.len() < 2 {
+                    return Ok(false);
+                }
+                if self.buffer.starts_with(b"--") {
+                    self.buffer.clear();
+                    self.state = State::Epilogue;
+                    return Ok(true);
+                }
+
+                // Optional transport padding, then CRLF
+                let line_end = match memmem::find(&self.buffer, b"\r\n") {
+                    Some(pos) => pos,
+                    None if self.buffer.len() > self.limits.max_header_size => {
+                        return Err(malformed("invalid data after boundary"));
+                    }
+                    None => return Ok(false),
+                };
+                if !self.buffer[..line_end].iter().all(|b| *b == b' ' || *b == b'\t') {
+                    return Err(malformed("invalid data after boundary"));
+                }
+                self.buffer.drain(..line_end + 2);
+
+                self.part_count += 1;
+                if self.part_count > self.limits.max_parts {
+                    return Err(HandlerError::ValidationError(format!(
+                        "Multipart body exceeds the maximum of {} parts",
+                        self.limits.max_parts
+                    )));
+                }
+                self.state = State::Headers;
+                Ok(true)
+            }
+            State::Headers => {
+                // A part with no headers starts directly with the blank line
+                let (block_end, consumed) = if self.buffer.starts_with(b"\r\n") {
+                    (0, 2)
+                } else {
+                    match memmem::find(&self.buffer, b"\r\n\r\n") {
+                        Some(pos) => (pos, pos + 4),
+                        None => {
+                            if self.buffer.len() > self.limits.max_header_size {
+                                return Err(HandlerError::ValidationError(format!(
+                                    "Multipart part headers exceed the maximum of {} bytes",
+                                    self.limits.max_header_size
+                                )));
+                            }
+                            return Ok(false);
+                        }
+                    }
+                };
+                if block_end > self.limits.max_header_size {
+                    return Err(HandlerError::ValidationError(format!(
+                        "Multipart part headers exceed the maximum of {} bytes",
+                        self.limits.max_header_size
+                    )));
+                }
+
+                let part = parse_part_headers(&self.buffer[..block_end])?;
+                self.buffer.drain(..consumed);
+                self.current = Some(part);
+                self.state = State::Body;
+                Ok(true)
+            }
+            State::Body => match self.finder.find(&self.buffer) {
+                Some(pos) => {
+                    self.append_content(pos)?;
+                    self.buffer.drain(..self.delimiter.len());
+                    if let Some(part) = self.current.take() {
+                        self.parts.push(MultipartPart {
+                            name: part.name,
+                            filename: part.filename,
+                            content_type: part.content_type,
+                            content: part.content,
+                        });
+                    }
+                    self.state = State::AfterDelimiter;
+                    Ok(true)
+                }
+                None => {
+                    // Hold back enough bytes to recognize a delimiter split across chunks
+                    let keep = self.delimiter.len() - 1;
+                    if self.buffer.len() > keep {
+                        self.append_content(self.buffer.len() - keep)?;
+                    }
+                    Ok(false)
+                }
+            },
+            State::Epilogue => {
+                self.buffer.clear();
+                Ok(false)
+            }
+        }
+    }
+
+    /// Move the first `len` buffered bytes into the current part's content
+    fn append_content(&mut self, len: usize) -> Result<(), HandlerError> {
+        let part = self.current.as_mut()
+            .ok_or_else(|| HandlerError::InternalError("Multipart parser lost its current part".to_string()))?;
+
+        if part.content.len() + len > self.limits.max_part_size {
+            return Err(HandlerError::ValidationError(format!(
+                "Multipart field \"{}\" exceeds the maximum allowed size of {} bytes",
+                part.name, self.limits.max_part_size
+            )));
+        }
+
+        part.content.extend(self.buffer.drain(..len));
+        Ok(())
+    }
+}
+
+fn malformed(reason: &str) -> HandlerError {
+    HandlerError::BadRequest(format!("Malformed multipart body: {}", reason))
+}
+
+/// Boundaries are 1-70 characters from the RFC 2046 `bchars` set
+fn validate_boundary(boundary: &str) -> Result<(), HandlerError> {
+    const SPECIALS: &[u8] = b"'()+_,-./:=? ";
+    let valid = !boundary.is_empty()
+        && boundary.len() <= 70
+        && !boundary.ends_with(' ')
+        && boundary.bytes().all(|b| b.is_ascii_alphanumeric() || SPECIALS.contains(&b));
+
+    if valid {
+        Ok(())
+    } else {
+        Err(HandlerError::ValidationError("Invalid multipart boundary".to_string()))
+    }
+}
+
+fn parse_part_headers(block: &[u8]) -> Result<PendingPart, HandlerError> {
+    let block = std::str::from_utf8(block)
+        .map_err(|_| malformed("part headers are not valid UTF-8"))?;
+
+    let mut disposition = None;
+    let mut content_type = None;
+
+    // An empty block is a part without headers, not one blank header line
+    for line in block.split("\r\n").filter(|line| !line.is_empty()) {
+        let (name, value) = line.split_once(':')
+            .ok_or_else(|| malformed("invalid part header line"))?;
+        let value = value.trim();
+
+        if name.trim().eq_ignore_ascii_case("content-disposition") {
+            disposition = Some(parse_header_value(value)?);
+        } else if name.trim().eq_ignore_ascii_case("content-type") {
+            content_type = Some(value.to_string());
+        }
+    }
+
+    let (disposition_type, params) = disposition
+        .ok_or_else(|| malformed("part is missing Content-Disposition"))?;
+    if !disposition_type.eq_ignore_ascii_case("form-data") {
+        return Err(malformed("Content-Disposition must be form-data"));
+    }
+
+    let mut name = None;
+    let mut filename = None;
+    let mut filename_ext = None;
+    for (param, value) in params {
+        match param.as_str() {
+            "name" => name = Some(value),
+            "filename" => filename = Some(value),
+            "filename*" => filename_ext = Some(decode_ext_value(&value)?),
+            _ => {}
+        }
+    }
+
+    Ok(PendingPart {
+        name: name.ok_or_else(|| malformed("part is missing a name"))?,
+        // RFC 6266: filename* takes precedence when both are present
+        filename: filename_ext.or(filename),
+        content_type,
+        content: Vec::new(),
+    })
+}
+
+/// Parse `value; param=token; param="quoted \"string\""` into the leading
+/// value and a list of (lowercased name, unquoted value) parameters
+fn parse_header_value(input: &str) -> Result<(String, Vec<(String, String)>), HandlerError> {
+    let value_end = input.find(';').unwrap_or(input.len());
+    let value = input[..value_end].trim().to_string();
+
+    let mut chars = input[value_end..].char_indices().peekable();
+    let mut params = Vec::new();
+
+    loop {
+        // Expect ';' or end of input
+        skip_whitespace(&mut chars);
+        match chars.next() {
+            None => break,
+            Some((_, ';')) => {}
+            Some(_) => return Err(malformed("invalid header parameter")),
+        }
+        skip_whitespace(&mut chars);
+        if chars.peek().is_none() {
+            break;
+        }
+
+        let mut name = String::new();
+        while let Some(&(_, c)) = chars.peek() {
+            if c == '=' || c == ';' || c.is_whitespace() {
+                break;
+            }
+            name.push(c.to_ascii_lowercase());
+            chars.next();
+        }
+        skip_whitespace(&mut chars);
+        if name.is_empty() || chars.next().map(|(_, c)| c) != Some('=') {
+            return Err(malformed("invalid header parameter"));
+        }
+        skip_whitespace(&mut chars);
+
+        let mut param_value = String::new();
+        if chars.peek().map(|(_, c)| *c) == Some('"') {
+            chars.next();
+            let mut closed = false;
+            while let Some((_, c)) = chars.next() {
+                match c {
+                    '"' => {
+                        closed = true;
+                        break;
+                    }
+                    '\\' => match chars.next() {
+                        Some((_, escaped)) => param_value.push(escaped),
+                        None => break,
+                    },
+                    _ => param_value.push(c),
+                }
+            }
+            if !closed {
+                return Err(malformed("unterminated quoted string"));
+            }
+        } else {
+            while let Some(&(_, c)) = chars.peek() {
+                if c == ';' || c.is_whitespace() {
+                    break;
+                }
+                param_value.push(c);
+                chars.next();
+            }
+        }
+
+        params.push((name, param_value));
+    }
+
+    Ok((value, params))
+}
+
+fn skip_whitespace(chars: &mut std::iter::Peekable<std::str::CharIndices>) {
+    while chars.peek().map(|(_, c)| c.is_whitespace()).unwrap_or(false) {
+        chars.next();
+    }
+}
+
+/// Decode an RFC 5987 `ext-value` (`charset'language'percent-encoded`)
+fn decode_ext_value(value: &str) -> Result<String, HandlerError> {
+    let mut pieces = value.splitn(3, '\'');
+    let charset = pieces.next().unwrap_or("");
+    let _language = pieces.next();
+    let encoded = pieces.next().ok_or_else(|| malformed("invalid filename* value"))?;
+
+    let mut bytes = Vec::with_capacity(encoded.len());
+    let raw = encoded.as_bytes();
+    let mut i = 0;
+    while i < raw.len() {
+        if raw[i] == b'%' {
+            let hex = raw.get(i + 1..i + 3)
+                .and_then(|h| std::str::from_utf8(h).ok())
+                .and_then(|h| u8::from_str_radix(h, 16).ok())
+                .ok_or_else(|| malformed("invalid percent-encoding in filename*"))?;
+            bytes.push(hex);
+            i += 3;
+        } else {
+            bytes.push(raw[i]);
+            i += 1;
+        }
+    }
+
+    if charset.eq_ignore_ascii_case("utf-8") {
+        String::from_utf8(bytes).map_err(|_| malformed("filename* is not valid UTF-8"))
+    } else if charset.eq_ignore_ascii_case("iso-8859-1") {
+        Ok(bytes.into_iter().map(char::from).collect())
+    } else {
+        Err(malformed("unsupported filename* charset"))
+    }
+}
+
+pub struct MultipartData {
+    pub parts: Vec<MultipartPart>,
+}
+
+impl MultipartData {
+    pub fn get_field(&self, name: &str) -> Option<String> {
+        self.parts
+            .iter()
+            .find(|p| p.name == name)
+            .and_then(|p| String::from_utf8(p.content.clone()).ok())
+    }
+
+    pub fn get_file(&self, name: &str) -> Option<&MultipartPart> {
+        self.parts.iter().find(|p| p.name == name && p.filename.is_some())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct MultipartPart {
+    pub name: String,
+    pub filename: Option<String>,
+    pub content_type: Option<String>,
+    pub content: Vec<u8>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BOUNDARY: &str = "----formboundary7MA4YWxk";
+
+    fn body(parts: &[&str]) -> Vec<u8> {
+        let mut out = Vec::new();
+        for part in parts {
+            out.extend_from_slice(format!("--{}\r\n{}\r\n", BOUNDARY, part).as_bytes());
+        }
+        out.extend_from_slice(format!("--{}--\r\n", BOUNDARY).as_bytes());
+        out
+    }
+
+    #[test]
+    fn test_parse_fields_and_file() {
+        let data = body(&[
+            "Content-Disposition: form-data; name=\"title\"\r\n\r\nMy Icon",
+            "Content-Disposition: form-data; name=\"file\"; filename=\"icon.png\"\r\nContent-Type: image/png\r\n\r\nPNG-DATA",
+        ]);
+        let multipart = parse_multipart(&data, BOUNDARY).unwrap();
+
+        assert_eq!(multipart.get_field("title").as_deref(), Some("My Icon"));
+        let file = multipart.get_file("file").unwrap();
+        assert_eq!(file.filename.as_deref(), Some("icon.png"));
+        assert_eq!(file.content_type.as_deref(), Some("image/png"));
+        assert_eq!(file.content, b"PNG-DATA");
+    }
+
+    #[test]
+    fn test_quoted_parameters_with_semicolons() {
+        let data = body(&[
+            "Content-Disposition: form-data; name=\"a;b\"; filename=\"x; y=\\\"z\\\".png\"\r\n\r\n1",
+        ]);
+        let multipart = parse_multipart(&data, BOUNDARY).unwrap();
+
+        assert_eq!(multipart.parts[0].name, "a;b");
+        assert_eq!(multipart.parts[0].filename.as_deref(), Some("x; y=\"z\".png"));
+    }
+
+    #[test]
+    fn test_rfc5987_filename() {
+        let data = body(&[
+            "Content-Disposition: form-data; name=\"file\"; filename=\"fallback.png\"; filename*=UTF-8''%C3%BCber%20icon.png\r\n\r\n1",
+        ]);
+        let multipart = parse_multipart(&data, BOUNDARY).unwrap();
+        assert_eq!(multipart.parts[0].filename.as_deref(), Some("über icon.png"));
+    }
+
+    #[test]
+    fn test_boundary_text_inside_content() {
+        // The boundary string without a preceding CRLF is ordinary content
+        let content = format!("before --{} after", BOUNDARY);
+        let data = body(&[&format!("Content-Disposition: form-data; name=\"f\"\r\n\r\n{}", content)]);
+        let multipart = parse_multipart(&data, BOUNDARY).unwrap();
+        assert_eq!(multipart.parts[0].content, content.as_bytes());
+    }
+
+    #[test]
+    fn test_preamble_and_epilogue_are_ignored() {
+        let mut data = b"preamble text\r\n".to_vec();
+        data.extend(body(&["Content-Disposition: form-data; name=\"a\"\r\n\r\n1"]));
+        data.extend_from_slice(b"epilogue");
+        let multipart = parse_multipart(&data, BOUNDARY).unwrap();
+        assert_eq!(multipart.parts.len(), 1);
+    }
+
+    #[test]
+    fn test_missing_close_delimiter() {
+        let data = format!("--{}\r\nContent-Disposition: form-data; name=\"a\"\r\n\r\n1", BOUNDARY);
+        assert!(parse_multipart(data.as_bytes(), BOUNDARY).is_err());
+    }
+
+    #[test]
+    fn test_part_without_headers() {
+        let data = body(&["\r\nvalue"]);
+        let error = parse_multipart(&data, BOUNDARY).err().unwrap().to_string();
+        assert!(error.contains("missing Content-Disposition"), "{}", error);
+    }
+
+    #[test]
+    fn test_missing_name_is_rejected() {
+        let data = body(&["Content-Disposition: form-data; filename=\"x.png\"\r\n\r\n1"]);
+        assert!(parse_multipart(&data, BOUNDARY).is_err());
+    }
+
+    #[test]
+    fn test_limits() {
+        let data = body(&[
+            "Content-Disposition: form-data; name=\"a\"\r\n\r\n12345",
+            "Content-Disposition: form-data; name=\"b\"\r\n\r\n1",
+        ]);
+
+        let limits = MultipartLimits { max_part_size: 4, ..MultipartLimits::default() };
+        let mut parser = MultipartParser::new(BOUNDARY, limits).unwrap();
+        assert!(parser.feed(&data).is_err());
+
+        let limits = MultipartLimits { max_parts: 1, ..MultipartLimits::default() };
+        let mut parser = MultipartParser::new(BOUNDARY, limits).unwrap();
+        assert!(parser.feed(&data).is_err());
+
+        let limits = MultipartLimits { max_total_size: data.len() - 1, ..MultipartLimits::default() };
+        let mut parser = MultipartParser::new(BOUNDARY, limits).unwrap();
+        assert!(parser.feed(&data).is_err());
+    }
+
+    #[test]
+    fn test_boundary_from_content_type() {
+        assert_eq!(
+            boundary_from_content_type("multipart/form-data; boundary=abc123").unwrap(),
+            "abc123"
+        );
+        assert_eq!(
+            boundary_from_content_type("Multipart/Form-Data; charset=utf-8; boundary=\"a b:c\"").unwrap(),
+            "a b:c"
+        );
+        assert!(boundary_from_content_type("application/json").is_err());
+        assert!(boundary_from_content_type("multipart/form-data").is_err());
+    }
+
+    #[test]
+    fn test_invalid_boundary() {
+        assert!(MultipartParser::new("", MultipartLimits::default()).is_err());
+        assert!(MultipartParser::new(&"a".repeat(71), MultipartLimits::default()).is_err());
+        assert!(MultipartParser::new("bad\r\nboundary", MultipartLimits::default()).is_err());
+    }
+}
+
+#[cfg(test)]
+mod proptests {
+    use super::*;
+    use proptest::prelude::*;
+
+    const BOUNDARY: &str = "XyZ-boundary";
+
+    #[derive(Debug, Clone)]
+    struct TestPart {
+        name: String,
+        filename: Option<String>,
+        content: Vec<u8>,
+    }
+
+    fn quote(value: &str) -> String {
+        format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+    }
+
+    fn encode(parts: &[TestPart]) -> Vec<u8> {
+        let mut out = Vec::new();
+        for part in parts {
+            out.extend_from_slice(format!("--{}\r\n", BOUNDARY).as_bytes());
+            let mut disposition = format!("Content-Disposition: form-data; name={}", quote(&part.name));
+            if let Some(filename) = &part.filename {
+                disposition.push_str(&format!("; filename={}", quote(filename)));
+            }
+            out.extend_from_slice(disposition.as_bytes());
+            out.extend_from_slice(b"\r\n\r\n");
+            out.extend_from_slice(&part.content);
+            out.extend_from_slice(b"\r\n");
+        }
+        out.extend_from_slice(format!("--{}--\r\n", BOUNDARY).as_bytes());
+        out
+    }
+
+    fn parse_in_chunks(data: &[u8], chunk_sizes: &[usize]) -> Result<MultipartData, HandlerError> {
+        let mut parser = MultipartParser::new(BOUNDARY, MultipartLimits::default())?;
+        let mut rest = data;
+        let mut sizes = chunk_sizes.iter().cycle();
+        while !rest.is_empty() {
+            let size = (*sizes.next().unwrap()).clamp(1, rest.len());
+            parser.feed(&rest[..size])?;
+            rest = &rest[size..];
+        }
+        parser.finish()
+    }
+
+    fn header_text() -> impl Strategy<Value = String> {
+        // Printable text, including characters that break naive parsers
+        proptest::string::string_regex("[a-zA-Z0-9 ;=\"\\\\._-]{1,24}").unwrap()
+    }
+
+    fn content() -> impl Strategy<Value = Vec<u8>> {
+        // Bias content towards CR, LF, '-' and boundary letters so near-miss
+        // delimiters are common
+        let byte = prop_oneof![
+            Just(b'\r'), Just(b'\n'), Just(b'-'),
+            Just(b'X'), Just(b'y'), Just(b'Z'),
+            any::<u8>(),
+        ];
+        proptest::collection::vec(byte, 0..200)
+    }
+
+    fn part() -> impl Strategy<Value = TestPart> {
+        (header_text(), proptest::option::of(header_text()), content())
+            .prop_map(|(name, filename, content)| TestPart { name, filename, content })
+    }
+
+    proptest! {
+        #[test]
+        fn roundtrip_with_arbitrary_chunking(
+            parts in proptest::collection::vec(part(), 1..6),
+            chunk_sizes in proptest::collection::vec(1usize..64, 1..8),
+        ) {
+            let delimiter = format!("\r\n--{}", BOUNDARY);
+            for part in &parts {
+                let framed = [b"\r\n".as_slice(), &part.content, b"\r\n"].concat();
+                prop_assume!(memmem::find(&framed, delimiter.as_bytes()).is_none());
+            }
+
+            let data = encode(&parts);
+            let parsed = parse_in_chunks(&data, &chunk_sizes).unwrap();
+
+            prop_assert_eq!(parsed.parts.len(), parts.len());
+            for (expected, actual) in parts.iter().zip(parsed.parts.iter()) {
+                prop_assert_eq!(&expected.name, &actual.name);
+                prop_assert_eq!(&expected.filename, &actual.filename);
+                prop_assert_eq!(&expected.content, &actual.content);
+            }
+        }
+
+        #[test]
+        fn chunking_does_not_change_result(
+            data in proptest::collection::vec(any::<u8>(), 0..512),
+            chunk_sizes in proptest::collection::vec(1usize..32, 1..8),
+        ) {
+            let whole = parse_in_chunks(&data, &[usize::MAX]).map(|d| d.parts);
+            let chunked = parse_in_chunks(&data, &chunk_sizes).map(|d| d.parts);
+            prop_assert_eq!(whole.is_ok(), chunked.is_ok());
+            if let (Ok(whole), Ok(chunked)) = (whole, chunked) {
+                prop_assert_eq!(whole, chunked);
+            }
+        }
+
+        #[test]
+        fn arbitrary_input_never_panics(
+            prefix in proptest::collection::vec(any::<u8>(), 0..64),
+            parts in proptest::collection::vec(part(), 0..3),
+            noise in proptest::collection::vec(any::<u8>(), 0..64),
+            cut in any::<proptest::sample::Index>(),
+        ) {
+            // Valid structure with random bytes spliced in and a random truncation
+            let mut data = prefix;
+            data.extend(encode(&parts));
+            let at = cut.index(data.len() + 1);
+            data.splice(at..at, noise);
+            let _ = parse_in_chunks(&data, &[7, 1, 13]);
+        }
+
+        #[test]
+        fn header_parser_never_panics(input in "\\PC{0,80}") {
+            let _ = parse_header_value(&input);
+            let _ = decode_ext_value(&input);
+        }
+    }
+}
@@ -0,0 +1,168 @@
+use crate::api_keys::find_api_key;
+use crate::database::Database;
+use crate::error::HandlerError;
+use crate::models::{ApiKeyScope, Favicon, UploadIntent};
+use crate::utils::sha256_hex;
+use chrono::Utc;
+use std::collections::HashMap;
+use uuid::Uuid;
+
+/// Create a favicon's edit token and store its hash. The token itself is
+/// only returned here, for the creation response; it cannot be recovered.
+pub fn issue_edit_token(db: &Database, favicon_id: &str) -> Result<String, HandlerError> {
+    let token = generate_edit_token();
+    db.insert_edit_token(favicon_id, &sha256_hex(token.as_bytes()), Utc::now())?;
+    Ok(token)
+}
+
+/// Check an edit token presented for a favicon. Favicons without a token
+/// (created before tokens existed, or revoked by an admin) reject every token.
+pub fn verify_edit_token(db: &Database, favicon_id: &str, token: &str) -> Result<(), HandlerError> {
+    match db.get_edit_token_hash(favicon_id)? {
+        Some(hash) if hash == sha256_hex(token.as_bytes()) => Ok(()),
+        _ => Err(HandlerError::Unauthorized("Invalid or revoked edit token".to_string())),
+    }
+}
+
+/// Whether a request may read an unpublished favicon: it presents the
+/// favicon's edit token, or an API key with the read-unpublished scope. Any
+/// other key is treated like no key, so it cannot tell unpublished favicons
+/// from missing ones.
+pub fn can_read_unpublished(
+    db: &Database,
+    headers: &HashMap<String, String>,
+    favicon_id: &str,
+) -> Result<bool, HandlerError> {
+    let now = Utc::now();
+    if let Some(api_key) = find_api_key(db, headers, now)? {
+        if api_key.has_scope(ApiKeyScope::FaviconsReadUnpublished) {
+            db.touch_api_key(&api_key.id, now)?;
+            return Ok(true);
+        }
+    }
+
+    Ok(bearer_token(headers).is_some_and(|token| verify_edit_token(db, favicon_id, token).is_ok()))
+}
+
+/// The favicon a read endpoint serves for `slug`. Unpublished favicons look
+/// missing unless the request `can_read_unpublished` them.
+pub fn find_readable_favicon(
+    db: &Database,
+    headers: &HashMap<String, String>,
+    slug: &str,
+) -> Result<Favicon, HandlerError> {
+    let not_found = || HandlerError::NotFoundMessage(format!("Favicon not found: {}", slug));
+    let favicon = db.get_favicon_by_slug(slug)?.ok_or_else(not_found)?;
+    if !favicon.is_published && !can_read_unpublished(db, headers, &favicon.id)? {
+        return Err(not_found());
+    }
+    Ok(favicon)
+}
+
+/// Create the secret that finalizes an upload intent, and the hash stored
+/// with the intent. Only the client given the secret can finalize it.
+pub fn new_upload_secret() -> (String, String) {
+    let secret = generate_edit_token();
+    let hash = sha256_hex(secret.as_bytes());
+    (secret, hash)
+}
+
+/// Whether the request presents the intent's upload secret as
+/// `Authorization: Bearer <uploadSecret>`
+pub fn presents_upload_secret(headers: &HashMap<String, String>, intent: &UploadIntent) -> bool {
+    bearer_token(headers).is_some_and(|secret| sha256_hex(secret.as_bytes()) == intent.secret_hash)
+}
+
+/// The `Authorization: Bearer` value of a request
+fn bearer_token(headers: &HashMap<String, String>) -> Option<&str> {
+    headers
+        .iter()
+        .find(|(name, _)| name.eq_ignore_ascii_case("Authorization"))
+        .and_then(|(_, value)| value.strip_prefix("Bearer "))
+        .map(str::trim)
+}
+
+/// Two random UUIDs as 64 hex characters (244 random bits)
+fn generate_edit_token() -> String {
+    format!("{}{}", Uuid::new_v4().simple(), Uuid::new_v4().simple())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api_keys::create_api_key;
+    use tempfile::NamedTempFile;
+
+    #[test]
+    fn test_edit_token_lifecycle() {
+        let file = NamedTempFile::new().unwrap();
+        let db = Database::new(file.path()).unwrap();
+
+        let token = issue_edit_token(&db, "favicon").unwrap();
+        assert_eq!(token.len(), 64);
+        assert_ne!(db.get_edit_token_hash("favicon").unwrap().unwrap(), token);
+
+        assert!(verify_edit_token(&db, "favicon", &token).is_ok());
+        assert!(verify_edit_token(&db, "favicon", "wrong").is_err());
+        assert!(verify_edit_token(&db, "other", &token).is_err());
+
+        assert!(db.revoke_edit_token("favicon", Utc::now()).unwrap());
+        assert!(!db.revoke_edit_token("favicon", Utc::now()).unwrap());
+        assert!(verify_edit_token(&db, "favicon", &token).is_err());
+    }
+
+    #[test]
+    fn test_find_readable_favicon() {
+        let file = NamedTempFile::new().unwrap();
+        let db = Database::new(file.path()).unwrap();
+        db.insert_favicon(&Favicon { is_published: false, ..Favicon::test_default() }).unwrap();
+        let token = issue_edit_token(&db, "abc").unwrap();
+        let bearer = |value: &str| HashMap::from([("authorization".to_string(), format!("Bearer {}", value))]);
+
+        let hidden = find_readable_favicon(&db, &HashMap::new(), "slug").unwrap_err();
+        assert!(matches!(hidden, HandlerError::NotFoundMessage(_)));
+        assert!(find_readable_favicon(&db, &bearer(&token), "slug").is_ok());
+
+        // A key without the scope is no key at all
+        let now = Utc::now();
+        let (_, creator) = create_api_key(&db, "Creator", vec![ApiKeyScope::FaviconsCreate], None, now).unwrap();
+        let (_, reader) = create_api_key(&db, "Reader", vec![ApiKeyScope::FaviconsReadUnpublished], None, now).unwrap();
+        let hidden = find_readable_favicon(&db, &bearer(&creator), "slug").unwrap_err();
+        assert!(matches!(hidden, HandlerError::NotFoundMessage(_)));
+        assert!(find_readable_favicon(&db, &bearer(&reader), "slug").is_ok());
+
+        assert!(find_readable_favicon(&db, &HashMap::new(), "missing").is_err());
+    }
+
+    #[test]
+    fn test_upload_secret() {
+        let (secret, secret_hash) = new_upload_secret();
+        assert_ne!(secret, secret_hash);
+        let now = Utc::now();
+        let intent = UploadIntent {
+            id: "intent".to_string(),
+            staging_key: "uploads/intent/original".to_string(),
+            content_type: "image/png".to_string(),
+            title: None,
+            target_domain: None,
+            metadata: None,
+            theme_color: None,
+            background_color: None,
+            generation_options: None,
+            expires_at: now,
+            created_at: now,
+            api_key_id: None,
+            secret_hash,
+            favicon_id: None,
+        };
+        let bearer = |value: &str| HashMap::from([("Authorization".to_string(), format!("Bearer {}", value))]);
+
+        assert!(presents_upload_secret(&bearer(&secret), &intent));
+        assert!(!presents_upload_secret(&bearer("wrong"), &intent));
+        assert!(!presents_upload_secret(&HashMap::new(), &intent));
+
+        // Intents stored without a secret cannot be finalized
+        let legacy = UploadIntent { secret_hash: String::new(), ..intent };
+        assert!(!presents_upload_secret(&bearer(""), &legacy));
+    }
+}
@@ -0,0 +1,439 @@
+use crate::api_keys::find_api_key;
+use crate::database::Database;
+use crate::error::HandlerError;
+use crate::models::RateLimitState;
+use chrono::{DateTime, Duration, Utc};
+use rust_edge_gateway_sdk::prelude::{Request, Response};
+use serde_json::json;
+use std::collections::HashMap;
+use std::env;
+use std::net::IpAddr;
+
+/// Proxies in front of the gateway that append to `X-Forwarded-For`, used
+/// when `TRUSTED_PROXIES` is not set
+pub const DEFAULT_TRUSTED_PROXIES: usize = 1;
+
+const SECONDS_PER_DAY: u64 = 24 * 60 * 60;
+
+/// Limits of one endpoint, applied to each client separately
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RateLimitPolicy {
+    /// Requests allowed in a burst, the size of the token bucket
+    pub burst: u32,
+    /// Seconds for one token to refill
+    pub refill_seconds: u32,
+    /// Requests allowed per UTC day
+    pub daily_requests: Option<u32>,
+    /// Bytes allowed per UTC day: the request bodies, or the declared size
+    /// of direct uploads
+    pub daily_bytes: Option<u64>,
+}
+
+/// Limits of endpoints that store new sources, by the name used in
+/// `RATE_LIMIT_<ENDPOINT>`. Endpoints not listed are not limited unless
+/// configured.
+const DEFAULT_POLICIES: [(&str, RateLimitPolicy); 6] = [
+    ("upload", RateLimitPolicy { burst: 10, refill_seconds: 30, daily_requests: Some(100), daily_bytes: Some(50 * 1024 * 1024) }),
+    ("canvas", RateLimitPolicy { burst: 10, refill_seconds: 30, daily_requests: Some(100), daily_bytes: Some(50 * 1024 * 1024) }),
+    ("upload-intent", RateLimitPolicy { burst: 5, refill_seconds: 60, daily_requests: Some(50), daily_bytes: Some(50 * 1024 * 1024) }),
+    ("finalize", RateLimitPolicy { burst: 5, refill_seconds: 60, daily_requests: Some(50), daily_bytes: None }),
+    ("import", RateLimitPolicy { burst: 5, refill_seconds: 60, daily_requests: Some(50), daily_bytes: None }),
+    ("fork", RateLimitPolicy { burst: 10, refill_seconds: 30, daily_requests: Some(100), daily_bytes: None }),
+];
+
+/// Starting point for endpoints configured without a default
+const FALLBACK_POLICY: RateLimitPolicy = RateLimitPolicy { burst: 10, refill_seconds: 30, daily_requests: None, daily_bytes: None };
+
+impl RateLimitPolicy {
+    /// Limits of an endpoint: its default, overridden by `RATE_LIMIT_<ENDPOINT>`
+    /// (uppercase, `-` as `_`). `None` if the endpoint is not limited.
+    pub fn for_endpoint(endpoint: &str) -> Result<Option<Self>, HandlerError> {
+        let default = DEFAULT_POLICIES
+            .iter()
+            .find(|(name, _)| *name == endpoint)
+            .map(|(_, policy)| policy.clone());
+
+        let variable = format!("RATE_LIMIT_{}", endpoint.to_ascii_uppercase().replace('-', "_"));
+        match env::var(&variable) {
+            Ok(value) => Self::parse(&value, default)
+                .map_err(|e| HandlerError::InternalError(format!("Invalid {}: {}", variable, e))),
+            Err(_) => Ok(default),
+        }
+    }
+
+    /// Parse `off`, or comma-separated `key=value` pairs overriding `base`:
+    /// `burst`, `refill` (seconds per request), `daily_requests` and
+    /// `daily_bytes`. A daily quota of 0 removes it.
+    pub fn parse(value: &str, base: Option<Self>) -> Result<Option<Self>, String> {
+        let value = value.trim();
+        if value.eq_ignore_ascii_case("off") {
+            return Ok(None);
+        }
+
+        let mut policy = base.unwrap_or(FALLBACK_POLICY);
+        for pair in value.split(',').map(str::trim).filter(|pair| !pair.is_empty()) {
+            let (key, number) = pair.split_once('=').ok_or_else(|| format!("expected key=value, got '{}'", pair))?;
+            let number: u64 = number.trim().parse().map_err(|_| format!("'{}' is not a number", number.trim()))?;
+            let positive = |number: u64| u32::try_from(number).ok().filter(|n| *n > 0);
+            match key.trim() {
+                "burst" => policy.burst = positive(number).ok_or("burst must be at least 1")?,
+                "refill" => policy.refill_seconds = positive(number).ok_or("refill must be at least 1")?,
+                "daily_requests" => policy.daily_requests = positive(number),
+                "daily_bytes" => policy.daily_bytes = Some(number).filter(|n| *n > 0),
+                other => return Err(format!("unknown key '{}'", other)),
+            }
+        }
+        Ok(Some(policy))
+    }
+
+    /// `RateLimit-Policy` value: the bucket, and the daily request quota
+    fn header(&self) -> String {
+        let mut header = format!("{};w={}", self.burst, u64::from(self.burst) * u64::from(self.refill_seconds));
+        if let Some(daily) = self.daily_requests {
+            header.push_str(&format!(", {};w={}", daily, SECONDS_PER_DAY));
+        }
+        header
+    }
+}
+
+/// Who a request is counted against
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RateLimitClient {
+    /// Client address; IPv6 addresses are grouped by /64, which one
+    /// subscriber usually holds
+    Address(String),
+    /// An authenticated API key, by id
+    ApiKey(String),
+    /// No usable forwarding header; all such requests share limits
+    Unknown,
+}
+
+impl RateLimitClient {
+    /// The client a request comes from: its active API key if it presents
+    /// one, otherwise its address
+    pub fn for_request(db: &Database, headers: &HashMap<String, String>, now: DateTime<Utc>) -> Result<Self, HandlerError> {
+        Ok(match find_api_key(db, headers, now)? {
+            Some(api_key) => RateLimitClient::ApiKey(api_key.id),
+            None => RateLimitClient::from_headers(headers),
+        })
+    }
+
+    /// The client address appended by the last trusted proxy to
+    /// `X-Forwarded-For`. Entries before it are set by the client and ignored.
+    pub fn from_headers(headers: &HashMap<String, String>) -> Self {
+        let trusted_proxies = env::var("TRUSTED_PROXIES")
+            .ok()
+            .and_then(|value| value.trim().parse::<usize>().ok())
+            .unwrap_or(DEFAULT_TRUSTED_PROXIES)
+            .max(1);
+        Self::from_forwarded_for(headers, trusted_proxies)
+    }
+
+    fn from_forwarded_for(headers: &HashMap<String, String>, trusted_proxies: usize) -> Self {
+        let Some((_, forwarded)) = headers.iter().find(|(name, _)| name.eq_ignore_ascii_case("x-forwarded-for")) else {
+            return RateLimitClient::Unknown;
+        };
+        let entries: Vec<&str> = forwarded.split(',').map(str::trim).collect();
+        let address = entries
+            .len()
+            .checked_sub(trusted_proxies)
+            .and_then(|index| entries[index].parse::<IpAddr>().ok());
+
+        match address {
+            Some(IpAddr::V6(ip)) if ip.to_ipv4_mapped().is_none() => {
+                let [a, b, c, d, ..] = ip.segments();
+                RateLimitClient::Address(format!("{:x}:{:x}:{:x}:{:x}::/64", a, b, c, d))
+            }
+            Some(IpAddr::V6(ip)) => RateLimitClient::Address(ip.to_ipv4_mapped().unwrap().to_string()),
+            Some(IpAddr::V4(ip)) => RateLimitClient::Address(ip.to_string()),
+            None => RateLimitClient::Unknown,
+        }
+    }
+
+    /// Key the client's state is stored under
+    pub fn key(&self) -> String {
+        match self {
+            RateLimitClient::Address(address) => format!("ip:{}", address),
+            RateLimitClient::ApiKey(id) => format!("key:{}", id),
+            RateLimitClient::Unknown => "unknown".to_string(),
+        }
+    }
+}
+
+/// Standing of a client after a request, reported in `RateLimit-*` headers.
+/// Whichever of the bucket and the daily quota has fewer requests left is
+/// reported.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RateLimitStatus {
+    pub limit: u32,
+    pub remaining: u32,
+    /// Seconds until `remaining` is back to `limit`
+    pub reset: u64,
+    pub policy: String,
+}
+
+impl RateLimitStatus {
+    pub fn apply(&self, response: Response) -> Response {
+        response
+            .with_header("RateLimit-Limit", self.limit.to_string())
+            .with_header("RateLimit-Remaining", self.remaining.to_string())
+            .with_header("RateLimit-Reset", self.reset.to_string())
+            .with_header("RateLimit-Policy", self.policy.clone())
+    }
+}
+
+/// A request refused for exceeding a limit
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RateLimited {
+    pub status: RateLimitStatus,
+    /// Seconds until the request would be allowed
+    pub retry_after: u64,
+    pub message: String,
+}
+
+impl RateLimited {
+    /// A 429 in the shape of other error responses
+    pub fn to_response(&self) -> Response {
+        let response = Response::json(429, json!({
+            "statusCode": 429,
+            "message": self.message,
+            "error": "Too Many Requests",
+        }));
+        self.status.apply(response).with_header("Retry-After", self.retry_after.to_string())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RateLimitDecision {
+    Allowed(RateLimitStatus),
+    Limited(RateLimited),
+}
+
+/// Run `handler` if the client is within the endpoint's limits, counting the
+/// request and its body against them and adding `RateLimit-*` headers to the
+/// response. Otherwise answer 429 without running it. Requests with an
+/// active API key are counted against the key rather than the address.
+pub fn with_rate_limit(req: &Request, endpoint: &str, handler: impl FnOnce() -> Response) -> Response {
+    let bytes = req.body.as_ref().map_or(0, |body| body.len() as u64);
+    with_rate_limit_bytes(req, endpoint, bytes, handler)
+}
+
+/// [`with_rate_limit`], counting `bytes` instead of the request body. For
+/// requests that declare the size of an upload sent straight to storage.
+pub fn with_rate_limit_bytes(
+    req: &Request,
+    endpoint: &str,
+    bytes: u64,
+    handler: impl FnOnce() -> Response,
+) -> Response {
+    let decision = RateLimitPolicy::for_endpoint(endpoint).and_then(|policy| match policy {
+        Some(policy) => {
+            let db_path = env::var("DB_PATH")
+                .map_err(|e| HandlerError::InternalError(format!("DB_PATH not set: {}", e)))?;
+            let db = Database::new(&db_path)?;
+            let now = Utc::now();
+            let client = RateLimitClient::for_request(&db, &req.headers, now)?;
+            check_rate_limit(&db, endpoint, &policy, &client, bytes, now).map(Some)
+        }
+        None => Ok(None),
+    });
+
+    match decision {
+        Ok(Some(RateLimitDecision::Allowed(status))) => status.apply(handler()),
+        Ok(Some(RateLimitDecision::Limited(limited))) => limited.to_response(),
+        Ok(None) => handler(),
+        Err(e) => e.to_response(),
+    }
+}
+
+/// Count a request of `bytes` against a client's limits for an endpoint at
+/// `now`, unless it would exceed one of them
+pub fn check_rate_limit(
+    db: &Database,
+    endpoint: &str,
+    policy: &RateLimitPolicy,
+    client: &RateLimitClient,
+    bytes: u64,
+    now: DateTime<Utc>,
+) -> Result<RateLimitDecision, HandlerError> {
+    let today = now.format("%Y-%m-%d").to_string();
+
+    db.update_rate_limit(endpoint, &client.key(), &today, |state| {
+        let mut state = state.unwrap_or_else(|| RateLimitState {
+            tokens: f64::from(policy.burst),
+            refilled_at: now,
+            day: today.clone(),
+            requests: 0,
+            bytes: 0,
+        });
+        let decision = decide(&mut state, policy, bytes, now);
+        (state, decision)
+    })
+}
+
+fn decide(state: &mut RateLimitState, policy: &RateLimitPolicy, bytes: u64, now: DateTime<Utc>) -> RateLimitDecision {
+    // Refill for the time since the last request; a lowered burst caps it
+    let elapsed = (now - state.refilled_at).num_milliseconds().max(0) as f64 / 1000.0;
+    state.tokens = (state.tokens + elapsed / f64::from(policy.refill_seconds)).min(f64::from(policy.burst));
+    state.refilled_at = now;
+
+    let until_midnight = seconds_until_midnight(now);
+    let limited = |state: &RateLimitState, retry_after: u64, message: String| {
+        RateLimitDecision::Limited(RateLimited { status: status(state, policy, now), retry_after, message })
+    };
+
+    if state.tokens < 1.0 {
+        let retry_after = ((1.0 - state.tokens) * f64::from(policy.refill_seconds)).ceil() as u64;
+        return limited(state, retry_after.max(1), "Too many requests, please slow down".to_string());
+    }
+    if let Some(daily) = policy.daily_requests {
+        if state.requests >= i64::from(daily) {
+            return limited(state, until_midnight, format!("Daily quota of {} requests reached", daily));
+        }
+    }
+    if let Some(daily) = policy.daily_bytes {
+        if state.bytes as u64 + bytes > daily {
+            return limited(state, until_midnight, format!("Daily quota of {} uploaded bytes reached", daily));
+        }
+    }
+
+    state.tokens -= 1.0;
+    state.requests += 1;
+    state.bytes += bytes as i64;
+    RateLimitDecision::Allowed(status(state, policy, now))
+}
+
+fn status(state: &RateLimitState, policy: &RateLimitPolicy, now: DateTime<Utc>) -> RateLimitStatus {
+    let bucket = (
+        policy.burst,
+        state.tokens.floor() as u32,
+        ((f64::from(policy.burst) - state.tokens) * f64::from(policy.refill_seconds)).ceil() as u64,
+    );
+    let daily = policy.daily_requests.map(|daily| {
+        let remaining = (i64::from(daily) - state.requests).max(0) as u32;
+        (daily, remaining, seconds_until_midnight(now))
+    });
+    let (limit, remaining, reset) = match daily {
+        Some(daily) if daily.1 < bucket.1 => daily,
+        _ => bucket,
+    };
+    RateLimitStatus { limit, remaining, reset, policy: policy.header() }
+}
+
+/// Seconds until the daily counters reset at UTC midnight
+fn seconds_until_midnight(now: DateTime<Utc>) -> u64 {
+    let midnight = (now.date_naive() + Duration::days(1)).and_hms_opt(0, 0, 0).unwrap().and_utc();
+    (midnight - now).num_seconds().max(1) as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+    use tempfile::NamedTempFile;
+
+    fn policy() -> RateLimitPolicy {
+        RateLimitPolicy { burst: 2, refill_seconds: 10, daily_requests: Some(3), daily_bytes: Some(1000) }
+    }
+
+    fn allowed(decision: RateLimitDecision) -> RateLimitStatus {
+        match decision {
+            RateLimitDecision::Allowed(status) => status,
+            RateLimitDecision::Limited(limited) => panic!("limited: {}", limited.message),
+        }
+    }
+
+    fn limited(decision: RateLimitDecision) -> RateLimited {
+        match decision {
+            RateLimitDecision::Allowed(status) => panic!("allowed: {:?}", status),
+            RateLimitDecision::Limited(limited) => limited,
+        }
+    }
+
+    #[test]
+    fn test_parse_policy() {
+        let base = DEFAULT_POLICIES[0].1.clone();
+        assert_eq!(RateLimitPolicy::parse("off", Some(base.clone())).unwrap(), None);
+        assert_eq!(RateLimitPolicy::parse("", Some(base.clone())).unwrap(), Some(base.clone()));
+        assert_eq!(
+            RateLimitPolicy::parse("burst=3, refill=5, daily_requests=0, daily_bytes=2048", Some(base)).unwrap(),
+            Some(RateLimitPolicy { burst: 3, refill_seconds: 5, daily_requests: None, daily_bytes: Some(2048) })
+        );
+        assert_eq!(RateLimitPolicy::parse("burst=4", None).unwrap().unwrap().refill_seconds, FALLBACK_POLICY.refill_seconds);
+        assert!(RateLimitPolicy::parse("burst=0", None).is_err());
+        assert!(RateLimitPolicy::parse("burst", None).is_err());
+        assert!(RateLimitPolicy::parse("speed=1", None).is_err());
+        assert_eq!(policy().header(), "2;w=20, 3;w=86400");
+    }
+
+    #[test]
+    fn test_client_from_forwarded_for() {
+        let headers = |value: &str| HashMap::from([("X-Forwarded-For".to_string(), value.to_string())]);
+
+        assert_eq!(RateLimitClient::from_forwarded_for(&headers("203.0.113.7"), 1).key(), "ip:203.0.113.7");
+        // Entries the client added itself are skipped
+        assert_eq!(RateLimitClient::from_forwarded_for(&headers("1.1.1.1, 203.0.113.7"), 1).key(), "ip:203.0.113.7");
+        assert_eq!(RateLimitClient::from_forwarded_for(&headers("203.0.113.7, 10.0.0.2"), 2).key(), "ip:203.0.113.7");
+        assert_eq!(
+            RateLimitClient::from_forwarded_for(&headers("2001:db8:1:2:3:4:5:6"), 1).key(),
+            "ip:2001:db8:1:2::/64"
+        );
+        assert_eq!(RateLimitClient::from_forwarded_for(&headers("::ffff:203.0.113.7"), 1).key(), "ip:203.0.113.7");
+        assert_eq!(RateLimitClient::from_forwarded_for(&headers("garbage"), 1), RateLimitClient::Unknown);
+        assert_eq!(RateLimitClient::from_forwarded_for(&headers("203.0.113.7"), 2), RateLimitClient::Unknown);
+        assert_eq!(RateLimitClient::from_forwarded_for(&HashMap::new(), 1), RateLimitClient::Unknown);
+        assert_eq!(RateLimitClient::ApiKey("abc".to_string()).key(), "key:abc");
+    }
+
+    #[test]
+    fn test_token_bucket() {
+        let file = NamedTempFile::new().unwrap();
+        let db = Database::new(file.path()).unwrap();
+        let client = RateLimitClient::Address("203.0.113.7".to_string());
+        let start = Utc.with_ymd_and_hms(2024, 5, 1, 12, 0, 0).unwrap();
+        let policy = RateLimitPolicy { daily_requests: None, ..policy() };
+
+        let status = allowed(check_rate_limit(&db, "upload", &policy, &client, 0, start).unwrap());
+        assert_eq!((status.limit, status.remaining, status.reset), (2, 1, 10));
+        allowed(check_rate_limit(&db, "upload", &policy, &client, 0, start).unwrap());
+
+        let refused = limited(check_rate_limit(&db, "upload", &policy, &client, 0, start + Duration::seconds(4)).unwrap());
+        assert_eq!((refused.retry_after, refused.status.remaining), (6, 0));
+
+        // Other clients and endpoints have their own buckets
+        allowed(check_rate_limit(&db, "upload", &policy, &RateLimitClient::Unknown, 0, start).unwrap());
+        allowed(check_rate_limit(&db, "canvas", &policy, &client, 0, start).unwrap());
+
+        // The refused request did not spend a token
+        allowed(check_rate_limit(&db, "upload", &policy, &client, 0, start + Duration::seconds(10)).unwrap());
+        limited(check_rate_limit(&db, "upload", &policy, &client, 0, start + Duration::seconds(10)).unwrap());
+    }
+
+    #[test]
+    fn test_daily_quotas() {
+        let file = NamedTempFile::new().unwrap();
+        let client = RateLimitClient::Address("203.0.113.7".to_string());
+        let start = Utc.with_ymd_and_hms(2024, 5, 1, 23, 0, 0).unwrap();
+        let policy = RateLimitPolicy { burst: 100, ..policy() };
+
+        // Each call opens the database again, like separate handler processes
+        let check = |bytes: u64, at: DateTime<Utc>| {
+            let db = Database::new(file.path()).unwrap();
+            check_rate_limit(&db, "upload", &policy, &client, bytes, at).unwrap()
+        };
+
+        allowed(check(600, start));
+        let refused = limited(check(500, start));
+        assert_eq!(refused.retry_after, 3600);
+        assert!(refused.message.contains("bytes"));
+
+        allowed(check(400, start));
+        let status = allowed(check(0, start));
+        assert_eq!((status.limit, status.remaining, status.reset), (3, 0, 3600));
+        assert!(limited(check(0, start)).message.contains("3 requests"));
+
+        // Counters start over the next day
+        let status = allowed(check(1000, start + Duration::hours(1)));
+        assert_eq!((status.limit, status.remaining), (3, 2));
+    }
+}
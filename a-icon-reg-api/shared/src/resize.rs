@@ -0,0 +1,188 @@
+use crate::error::HandlerError;
+use crate::generator::{encode_png, render_icon};
+use crate::models::{Favicon, GenerationOptions};
+use crate::utils::sha256_hex;
+use image::{DynamicImage, ImageFormat, RgbaImage};
+use std::io::Cursor;
+
+/// Square sizes that can be rendered on demand. Covers the generated sizes plus
+/// the odd ones platforms ask for (Android launchers, legacy iOS, Chrome Web Store).
+pub const RESIZE_SQUARE_SIZES: [u32; 27] = [
+    16, 24, 32, 36, 48, 57, 60, 64, 70, 72, 76, 96, 114, 120, 128, 144, 150, 152, 167, 180, 192, 196,
+    228, 256, 310, 384, 512,
+];
+
+/// Non-square sizes that can be rendered on demand (Windows wide tile)
+pub const RESIZE_RECT_SIZES: [(u32, u32); 1] = [(310, 150)];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RenderFormat {
+    Png,
+    WebP,
+}
+
+impl RenderFormat {
+    pub fn from_extension(s: &str) -> Option<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "png" => Some(RenderFormat::Png),
+            "webp" => Some(RenderFormat::WebP),
+            _ => None,
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            RenderFormat::Png => "png",
+            RenderFormat::WebP => "webp",
+        }
+    }
+
+    pub fn mime_type(&self) -> &'static str {
+        match self {
+            RenderFormat::Png => "image/png",
+            RenderFormat::WebP => "image/webp",
+        }
+    }
+}
+
+/// A requested rendition: dimensions and output format
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RenderSpec {
+    pub width: u32,
+    pub height: u32,
+    pub format: RenderFormat,
+}
+
+impl RenderSpec {
+    /// Parse the `size` (`N` or `WxH`) and `format` query parameters, rejecting
+    /// anything outside the allowlist
+    pub fn parse(size: &str, format: Option<&str>) -> Result<Self, HandlerError> {
+        let format = match format {
+            Some(format) => RenderFormat::from_extension(format)
+                .ok_or_else(|| HandlerError::ValidationError("format must be 'png' or 'webp'".to_string()))?,
+            None => RenderFormat::Png,
+        };
+
+        let invalid = || HandlerError::ValidationError(format!("Unsupported size: {}", size));
+        let (width, height) = match size.split_once(['x', 'X']) {
+            Some((w, h)) => (w.parse::<u32>().map_err(|_| invalid())?, h.parse::<u32>().map_err(|_| invalid())?),
+            None => {
+                let n = size.parse::<u32>().map_err(|_| invalid())?;
+                (n, n)
+            }
+        };
+
+        let allowed = if width == height {
+            RESIZE_SQUARE_SIZES.contains(&width)
+        } else {
+            RESIZE_RECT_SIZES.contains(&(width, height))
+        };
+        if !allowed {
+            return Err(invalid());
+        }
+
+        Ok(RenderSpec { width, height, format })
+    }
+
+    /// Dimensions as `WxH`
+    pub fn size_label(&self) -> String {
+        format!("{}x{}", self.width, self.height)
+    }
+}
+
+/// Identifies the source a rendition was made from: the content hash, or the
+/// last update time for favicons without one, plus the generation options
+pub fn source_version(favicon: &Favicon) -> String {
+    let source = match favicon.source_hash.as_deref() {
+        Some(hash) if hash.len() >= 16 => hash[..16].to_string(),
+        _ => format!("t{}", favicon.updated_at.timestamp()),
+    };
+
+    match favicon.generation_options.as_ref().filter(|options| !options.is_default()) {
+        Some(options) => format!("{}-{}", source, &sha256_hex(options.to_json().as_bytes())[..8]),
+        None => source,
+    }
+}
+
+/// Storage key a rendition is cached under. It includes the source version, so
+/// a replaced source never serves stale renditions.
+pub fn render_cache_key(favicon: &Favicon, spec: &RenderSpec) -> String {
+    format!(
+        "renders/{}/{}/{}.{}",
+        favicon.id,
+        source_version(favicon),
+        spec.size_label(),
+        spec.format.extension()
+    )
+}
+
+/// Strong ETag for a rendition, tied to the source version
+pub fn render_etag(favicon: &Favicon, spec: &RenderSpec) -> String {
+    format!("\"{}-{}-{}\"", source_version(favicon), spec.size_label(), spec.format.extension())
+}
+
+/// Render a prepared source (see [`crate::generator::prepare_source`]) at the
+/// requested size and encode it. Square sizes match the generated PNGs; wide
+/// tiles center the icon on a transparent background.
+pub fn render_variant(image: &DynamicImage, spec: &RenderSpec, options: &GenerationOptions) -> Result<Vec<u8>, HandlerError> {
+    let side = spec.width.min(spec.height);
+    let icon = render_icon(image, side, options);
+    let rendered = if spec.width == spec.height {
+        icon
+    } else {
+        let mut canvas = RgbaImage::new(spec.width, spec.height);
+        let x = (spec.width - side) / 2;
+        let y = (spec.height - side) / 2;
+        image::imageops::overlay(&mut canvas, &icon.to_rgba8(), x as i64, y as i64);
+        DynamicImage::ImageRgba8(canvas)
+    };
+
+    match spec.format {
+        RenderFormat::Png => encode_png(&rendered),
+        RenderFormat::WebP => {
+            // The WebP encoder only accepts 8-bit RGB(A)
+            let mut buffer = Cursor::new(Vec::new());
+            DynamicImage::ImageRgba8(rendered.to_rgba8())
+                .write_to(&mut buffer, ImageFormat::WebP)
+                .map_err(|e| HandlerError::InternalError(format!("Failed to encode WebP: {}", e)))?;
+            Ok(buffer.into_inner())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{GenericImageView, Rgb, RgbImage};
+
+    #[test]
+    fn test_parse_render_spec() {
+        let spec = RenderSpec::parse("196", None).unwrap();
+        assert_eq!((spec.width, spec.height, spec.format), (196, 196, RenderFormat::Png));
+
+        let spec = RenderSpec::parse("310x150", Some("WEBP")).unwrap();
+        assert_eq!((spec.width, spec.height, spec.format), (310, 150, RenderFormat::WebP));
+        assert_eq!(RenderSpec::parse("144x144", None).unwrap().width, 144);
+
+        assert!(RenderSpec::parse("1000", None).is_err());
+        assert!(RenderSpec::parse("150x310", None).is_err());
+        assert!(RenderSpec::parse("abc", None).is_err());
+        assert!(RenderSpec::parse("32", Some("gif")).is_err());
+    }
+
+    #[test]
+    fn test_render_variant() {
+        let source = DynamicImage::ImageRgb8(RgbImage::from_pixel(64, 64, Rgb([200, 10, 10])));
+        let options = GenerationOptions::default();
+
+        let png = render_variant(&source, &RenderSpec::parse("96", None).unwrap(), &options).unwrap();
+        assert_eq!(image::load_from_memory(&png).unwrap().dimensions(), (96, 96));
+
+        let webp = render_variant(&source, &RenderSpec::parse("310x150", Some("webp")).unwrap(), &options).unwrap();
+        let tile = image::load_from_memory_with_format(&webp, ImageFormat::WebP).unwrap();
+        assert_eq!(tile.dimensions(), (310, 150));
+        // The square icon is centered; the sides stay transparent
+        assert_eq!(tile.get_pixel(0, 75).0[3], 0);
+        assert_eq!(tile.get_pixel(155, 75).0, [200, 10, 10, 255]);
+    }
+}
@@ -0,0 +1,199 @@
+use crate::generator::render_icon;
+use crate::models::GenerationOptions;
+use image::{DynamicImage, RgbaImage};
+use std::collections::BTreeMap;
+
+/// Grid the silhouette is traced on. Pinned tabs are drawn at 16px, so a
+/// coarse grid keeps the path small without visible stair steps.
+pub const SILHOUETTE_SIZE: u32 = 64;
+
+/// Pixels at least this opaque belong to the silhouette of a transparent icon
+const ALPHA_THRESHOLD: u8 = 128;
+
+/// Splits dark from light pixels in icons without transparency
+const LUMINANCE_THRESHOLD: u8 = 128;
+
+type Point = (u32, u32);
+
+/// Build the single-color SVG Safari uses for pinned tabs (`mask-icon`). The
+/// icon is thresholded to a mask and its outline traced to one black path;
+/// Safari tints it with the `color` attribute of the link tag.
+pub fn build_mask_icon_svg(image: &DynamicImage, options: &GenerationOptions) -> Vec<u8> {
+    let icon = render_icon(image, SILHOUETTE_SIZE, options).to_rgba8();
+    let mask = silhouette_mask(&icon);
+    format!(
+        r#"<svg xmlns="http://www.w3.org/2000/svg" viewBox="0 0 {size} {size}"><path fill-rule="evenodd" d="{path}"/></svg>"#,
+        size = SILHOUETTE_SIZE,
+        path = trace_mask(&mask, icon.width(), icon.height()),
+    )
+    .into_bytes()
+}
+
+/// Threshold the icon to a foreground mask (row-major). Icons with
+/// transparency use alpha; opaque ones use luminance, taking whichever side
+/// most border pixels are not on as the foreground. An empty mask falls back
+/// to the whole square.
+fn silhouette_mask(icon: &RgbaImage) -> Vec<bool> {
+    let has_transparency = icon.pixels().any(|p| p.0[3] < ALPHA_THRESHOLD);
+    let is_dark = |p: &image::Rgba<u8>| {
+        let [r, g, b, _] = p.0;
+        let luminance = (299 * r as u32 + 587 * g as u32 + 114 * b as u32) / 1000;
+        luminance < LUMINANCE_THRESHOLD as u32
+    };
+
+    let mask: Vec<bool> = if has_transparency {
+        icon.pixels().map(|p| p.0[3] >= ALPHA_THRESHOLD).collect()
+    } else {
+        let (width, height) = icon.dimensions();
+        let border: Vec<bool> = icon
+            .enumerate_pixels()
+            .filter(|(x, y, _)| *x == 0 || *y == 0 || *x == width - 1 || *y == height - 1)
+            .map(|(_, _, p)| is_dark(p))
+            .collect();
+        let dark_border = border.iter().filter(|dark| **dark).count() * 2 > border.len();
+        icon.pixels().map(|p| is_dark(p) != dark_border).collect()
+    };
+
+    if mask.iter().any(|on| *on) {
+        mask
+    } else {
+        vec![true; mask.len()]
+    }
+}
+
+/// Trace the outline of a mask into SVG path data. Every edge between a
+/// foreground and a background pixel becomes part of a closed loop; holes
+/// come out as separate loops, so the path is filled with `evenodd`.
+pub(crate) fn trace_mask(mask: &[bool], width: u32, height: u32) -> String {
+    let on = |x: i64, y: i64| {
+        x >= 0 && y >= 0 && x < width as i64 && y < height as i64 && mask[(y as u32 * width + x as u32) as usize]
+    };
+
+    // Boundary edges, clockwise around each foreground pixel
+    let mut edges: BTreeMap<Point, Vec<Point>> = BTreeMap::new();
+    for y in 0..height {
+        for x in 0..width {
+            if !on(x as i64, y as i64) {
+                continue;
+            }
+            let (xi, yi) = (x as i64, y as i64);
+            let mut add = |from: Point, to: Point| edges.entry(from).or_default().push(to);
+            if !on(xi, yi - 1) {
+                add((x, y), (x + 1, y));
+            }
+            if !on(xi + 1, yi) {
+                add((x + 1, y), (x + 1, y + 1));
+            }
+            if !on(xi, yi + 1) {
+                add((x + 1, y + 1), (x, y + 1));
+            }
+            if !on(xi - 1, yi) {
+                add((x, y + 1), (x, y));
+            }
+        }
+    }
+
+    let mut path = String::new();
+    while let Some(&start) = edges.keys().next() {
+        // Every vertex has as many edges in as out, so the walk ends at start
+        let mut points = vec![start];
+        let mut current = start;
+        loop {
+            let targets = edges.get_mut(&current).expect("boundary edges form closed loops");
+            let next = targets.pop().expect("boundary edges form closed loops");
+            if targets.is_empty() {
+                edges.remove(&current);
+            }
+            if next == start {
+                break;
+            }
+            points.push(next);
+            current = next;
+        }
+        path.push_str(&loop_to_path(&points));
+    }
+    path
+}
+
+/// Path commands for one closed loop, dropping points in the middle of
+/// straight runs
+fn loop_to_path(points: &[Point]) -> String {
+    let n = points.len();
+    let corners: Vec<Point> = (0..n)
+        .filter(|&i| {
+            let (prev, point, next) = (points[(i + n - 1) % n], points[i], points[(i + 1) % n]);
+            !((prev.0 == point.0 && point.0 == next.0) || (prev.1 == point.1 && point.1 == next.1))
+        })
+        .map(|i| points[i])
+        .collect();
+
+    let Some((&first, rest)) = corners.split_first() else {
+        return String::new();
+    };
+    let mut path = format!("M{} {}", first.0, first.1);
+    let mut previous = first;
+    for &point in rest {
+        if point.1 == previous.1 {
+            path.push_str(&format!("H{}", point.0));
+        } else {
+            path.push_str(&format!("V{}", point.1));
+        }
+        previous = point;
+    }
+    path.push('Z');
+    path
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Rgba;
+
+    fn image_with(background: Rgba<u8>, foreground: Rgba<u8>, inside: impl Fn(u32, u32) -> bool) -> RgbaImage {
+        RgbaImage::from_fn(8, 8, |x, y| if inside(x, y) { foreground } else { background })
+    }
+
+    #[test]
+    fn test_trace_square() {
+        let mask: Vec<bool> = (0..16).map(|i| matches!((i % 4, i / 4), (1..=2, 1..=2))).collect();
+        assert_eq!(trace_mask(&mask, 4, 4), "M1 1H3V3H1Z");
+    }
+
+    #[test]
+    fn test_trace_ring_has_hole() {
+        // 4x4 block with the middle 2x2 cleared
+        let mask: Vec<bool> = (0..16).map(|i| !matches!((i % 4, i / 4), (1..=2, 1..=2))).collect();
+        let path = trace_mask(&mask, 4, 4);
+        assert_eq!(path.matches('M').count(), 2);
+        assert!(path.contains("M0 0H4V4H0Z"));
+    }
+
+    #[test]
+    fn test_silhouette_mask_thresholds() {
+        let clear = Rgba([0, 0, 0, 0]);
+        let red = Rgba([200, 10, 10, 255]);
+        let mask = silhouette_mask(&image_with(clear, red, |x, _| x < 4));
+        assert_eq!(mask.iter().filter(|on| **on).count(), 32);
+        assert!(mask[0] && !mask[7]);
+
+        // Opaque: the dark mark on a light background is the foreground
+        let white = Rgba([255, 255, 255, 255]);
+        let navy = Rgba([10, 20, 80, 255]);
+        let mask = silhouette_mask(&image_with(white, navy, |x, y| (2..6).contains(&x) && (2..6).contains(&y)));
+        assert_eq!(mask.iter().filter(|on| **on).count(), 16);
+        assert!(!mask[0] && mask[2 * 8 + 2]);
+
+        // A flat image becomes a full square
+        assert!(silhouette_mask(&image_with(white, white, |_, _| false)).iter().all(|on| *on));
+    }
+
+    #[test]
+    fn test_build_mask_icon_svg() {
+        let source = DynamicImage::ImageRgba8(RgbaImage::from_pixel(32, 32, Rgba([200, 10, 10, 255])));
+        let svg = String::from_utf8(build_mask_icon_svg(&source, &GenerationOptions::default())).unwrap();
+        assert_eq!(
+            svg,
+            r#"<svg xmlns="http://www.w3.org/2000/svg" viewBox="0 0 64 64"><path fill-rule="evenodd" d="M0 0H64V64H0Z"/></svg>"#
+        );
+    }
+}
@@ -0,0 +1,183 @@
+use crate::database::Database;
+use crate::error::HandlerError;
+use crate::generator::load_source;
+use crate::models::{Favicon, SimilarFavicon};
+use image::imageops::FilterType;
+use image::{DynamicImage, Rgba, RgbaImage};
+use std::collections::HashMap;
+
+/// Default Hamming distance under which two favicons count as near-duplicates
+pub const DEFAULT_MAX_DISTANCE: u32 = 8;
+
+/// Upper bound accepted for caller-supplied distances. Beyond this, unrelated
+/// images start to match.
+pub const MAX_DISTANCE_LIMIT: u32 = 16;
+
+/// Most similar favicons returned alongside a new upload
+pub const MAX_SIMILAR_RESULTS: usize = 10;
+
+/// Slices a perceptual hash is indexed by. Hashes that differ in fewer bits
+/// than there are bands agree on at least one band, so a lookup by band finds
+/// every favicon within `DEFAULT_MAX_DISTANCE`.
+pub const PHASH_BANDS: usize = 9;
+
+const PHASH_BAND_BITS: usize = 7;
+
+/// Compute a 64-bit difference hash (dHash) of an image.
+///
+/// The image is flattened onto white, reduced to 9x8 grayscale, and each bit
+/// records whether a pixel is brighter than its right-hand neighbour. Re-encoding,
+/// rescaling and small color shifts leave most bits unchanged.
+pub fn dhash(image: &DynamicImage) -> u64 {
+    let flattened = flatten_on_white(image);
+    let small = flattened.resize_exact(9, 8, FilterType::Triangle).to_luma8();
+
+    let mut hash = 0u64;
+    for y in 0..8 {
+        for x in 0..8 {
+            let left = small.get_pixel(x, y).0[0];
+            let right = small.get_pixel(x + 1, y).0[0];
+            hash = (hash << 1) | u64::from(left > right);
+        }
+    }
+    hash
+}
+
+/// Decode a source and compute its perceptual hash
+pub fn source_perceptual_hash(data: &[u8], mime_type: &str) -> Result<u64, HandlerError> {
+    Ok(dhash(&load_source(data, mime_type)?))
+}
+
+/// Existing favicons that look like `favicon`, closest first
+pub fn find_similar_favicons(db: &Database, favicon: &Favicon) -> Result<Vec<SimilarFavicon>, HandlerError> {
+    let hash = match favicon.perceptual_hash {
+        Some(hash) => hash as u64,
+        None => return Ok(Vec::new()),
+    };
+
+    Ok(db
+        .find_similar(hash, DEFAULT_MAX_DISTANCE, &favicon.id, MAX_SIMILAR_RESULTS)?
+        .into_iter()
+        .map(|(similar, distance)| SimilarFavicon::from_favicon(similar, distance))
+        .collect())
+}
+
+/// The bands of a perceptual hash, 7 bits each from the lowest; the top bit
+/// is in none of them
+pub fn phash_bands(hash: u64) -> [i64; PHASH_BANDS] {
+    std::array::from_fn(|band| ((hash >> (band * PHASH_BAND_BITS)) & 0x7f) as i64)
+}
+
+pub fn hamming_distance(a: u64, b: u64) -> u32 {
+    (a ^ b).count_ones()
+}
+
+/// Group ids whose hashes are within `max_distance` of each other, directly or
+/// through a chain of near-duplicates. Only groups with two or more members are
+/// returned; members keep the order of `hashes`.
+pub fn cluster(hashes: &[(String, u64)], max_distance: u32) -> Vec<Vec<String>> {
+    let mut parent: Vec<usize> = (0..hashes.len()).collect();
+
+    fn find(parent: &mut [usize], mut i: usize) -> usize {
+        while parent[i] != i {
+            parent[i] = parent[parent[i]];
+            i = parent[i];
+        }
+        i
+    }
+
+    for i in 0..hashes.len() {
+        for j in (i + 1)..hashes.len() {
+            if hamming_distance(hashes[i].1, hashes[j].1) <= max_distance {
+                let (a, b) = (find(&mut parent, i), find(&mut parent, j));
+                if a != b {
+                    parent[b.max(a)] = a.min(b);
+                }
+            }
+        }
+    }
+
+    let mut groups: HashMap<usize, Vec<String>> = HashMap::new();
+    let mut order = Vec::new();
+    for (i, (id, _)) in hashes.iter().enumerate() {
+        let root = find(&mut parent, i);
+        if !groups.contains_key(&root) {
+            order.push(root);
+        }
+        groups.entry(root).or_default().push(id.clone());
+    }
+
+    order
+        .into_iter()
+        .filter_map(|root| groups.remove(&root))
+        .filter(|group| group.len() > 1)
+        .collect()
+}
+
+fn flatten_on_white(image: &DynamicImage) -> DynamicImage {
+    let rgba = image.to_rgba8();
+    let mut flat = RgbaImage::from_pixel(rgba.width(), rgba.height(), Rgba([255, 255, 255, 255]));
+    image::imageops::overlay(&mut flat, &rgba, 0, 0);
+    DynamicImage::ImageRgba8(flat)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{ImageFormat, Rgb, RgbImage};
+    use std::io::Cursor;
+
+    fn logo(size: u32) -> DynamicImage {
+        DynamicImage::ImageRgb8(RgbImage::from_fn(size, size, |x, y| {
+            if x < size / 2 && y > size / 3 { Rgb([20, 40, 200]) } else { Rgb([250, 200, 30]) }
+        }))
+    }
+
+    #[test]
+    fn test_dhash_tolerates_rescaling_and_reencoding() {
+        let original = dhash(&logo(256));
+
+        let mut jpeg = Cursor::new(Vec::new());
+        logo(100).write_to(&mut jpeg, ImageFormat::Jpeg).unwrap();
+        let recompressed = source_perceptual_hash(jpeg.get_ref(), "image/jpeg").unwrap();
+
+        assert!(hamming_distance(original, recompressed) <= DEFAULT_MAX_DISTANCE);
+    }
+
+    #[test]
+    fn test_dhash_distinguishes_different_images() {
+        let flipped = DynamicImage::ImageRgb8(image::imageops::flip_horizontal(&logo(256).to_rgb8()));
+        assert!(hamming_distance(dhash(&logo(256)), dhash(&flipped)) > DEFAULT_MAX_DISTANCE);
+    }
+
+    #[test]
+    fn test_cluster() {
+        let hashes = vec![
+            ("a".to_string(), 0b0000),
+            ("b".to_string(), 0xFFFF_0000),
+            ("c".to_string(), 0b0011),
+            ("d".to_string(), 0b1111),
+            ("e".to_string(), 0xFFFF_0001),
+            ("f".to_string(), u64::MAX),
+        ];
+
+        let clusters = cluster(&hashes, 2);
+        assert_eq!(clusters, vec![
+            vec!["a".to_string(), "c".to_string(), "d".to_string()],
+            vec!["b".to_string(), "e".to_string()],
+        ]);
+    }
+
+    #[test]
+    fn test_phash_bands() {
+        assert!(DEFAULT_MAX_DISTANCE < PHASH_BANDS as u32);
+        assert_eq!(phash_bands(0), [0; PHASH_BANDS]);
+        assert_eq!(phash_bands(u64::MAX), [0x7f; PHASH_BANDS]);
+        assert_eq!(phash_bands(0b1000_0001), [1, 1, 0, 0, 0, 0, 0, 0, 0]);
+
+        // One bit flipped in each of 8 bands still leaves one band equal
+        let spread = (0..8).fold(0u64, |hash, band| hash | 1 << (band * PHASH_BAND_BITS));
+        let matching = phash_bands(0).iter().zip(phash_bands(spread)).filter(|(a, b)| **a == *b).count();
+        assert_eq!(matching, 1);
+    }
+}
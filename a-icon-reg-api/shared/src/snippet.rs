@@ -0,0 +1,287 @@
+use crate::generator::ICO_SIZES;
+use crate::manifest::{xml_escape, DEFAULT_THEME_COLOR};
+use crate::models::{asset_url, AssetType, AssetVariant, Favicon, FaviconAsset};
+use serde::Serialize;
+use std::env;
+
+/// Used when `PUBLIC_BASE_URL` is not set
+pub const DEFAULT_PUBLIC_BASE_URL: &str = "https://a-icon.com";
+
+/// PNG sizes linked directly with `rel="icon"`; larger sizes come from the manifest
+const LINKED_PNG_SIZES: [&str; 2] = ["32x32", "16x16"];
+
+/// Size Apple devices pick for the home screen
+const APPLE_TOUCH_SIZE: &str = "180x180";
+
+const LIGHT_SCHEME: &str = "(prefers-color-scheme: light)";
+const DARK_SCHEME: &str = "(prefers-color-scheme: dark)";
+
+/// Public origin that asset URLs are resolved against, without a trailing slash
+pub fn public_base_url() -> String {
+    env::var("PUBLIC_BASE_URL")
+        .ok()
+        .filter(|url| !url.trim().is_empty())
+        .unwrap_or_else(|| DEFAULT_PUBLIC_BASE_URL.to_string())
+        .trim_end_matches('/')
+        .to_string()
+}
+
+/// A single `<link>` or `<meta>` element for the document head
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "tag", rename_all = "lowercase")]
+pub enum HeadTag {
+    Link {
+        rel: String,
+        href: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        sizes: Option<String>,
+        #[serde(rename = "type", skip_serializing_if = "Option::is_none")]
+        mime_type: Option<String>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        media: Option<String>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        color: Option<String>,
+    },
+    Meta {
+        name: String,
+        content: String,
+    },
+}
+
+impl HeadTag {
+    fn link(rel: &str, href: String, mime_type: Option<&str>, sizes: Option<&str>, color: Option<&str>) -> Self {
+        HeadTag::Link {
+            rel: rel.to_string(),
+            href,
+            sizes: sizes.map(str::to_string),
+            mime_type: mime_type.map(str::to_string),
+            media: None,
+            color: color.map(str::to_string),
+        }
+    }
+
+    /// Restrict a link to a media query, e.g. a color scheme
+    fn with_media(mut self, query: &str) -> Self {
+        if let HeadTag::Link { media, .. } = &mut self {
+            *media = Some(query.to_string());
+        }
+        self
+    }
+
+    fn meta(name: &str, content: &str) -> Self {
+        HeadTag::Meta { name: name.to_string(), content: content.to_string() }
+    }
+
+    pub fn to_html(&self) -> String {
+        match self {
+            HeadTag::Link { rel, href, sizes, mime_type, media, color } => {
+                let mut html = format!("<link rel=\"{}\"", xml_escape(rel));
+                if let Some(mime_type) = mime_type {
+                    html.push_str(&format!(" type=\"{}\"", xml_escape(mime_type)));
+                }
+                if let Some(sizes) = sizes {
+                    html.push_str(&format!(" sizes=\"{}\"", xml_escape(sizes)));
+                }
+                if let Some(media) = media {
+                    html.push_str(&format!(" media=\"{}\"", xml_escape(media)));
+                }
+                html.push_str(&format!(" href=\"{}\"", xml_escape(href)));
+                if let Some(color) = color {
+                    html.push_str(&format!(" color=\"{}\"", xml_escape(color)));
+                }
+                html.push('>');
+                html
+            }
+            HeadTag::Meta { name, content } => {
+                format!("<meta name=\"{}\" content=\"{}\">", xml_escape(name), xml_escape(content))
+            }
+        }
+    }
+}
+
+/// Build the head tags for a favicon's generated assets. Tags whose asset is
+/// missing (e.g. generation failed) are left out.
+pub fn build_head_tags(favicon: &Favicon, assets: &[FaviconAsset], base_url: &str) -> Vec<HeadTag> {
+    build_head_tags_with(favicon, &|r#type, size, variant| {
+        find_asset(assets, r#type, size, variant).map(|asset| format!("{}{}", base_url, asset_url(&asset.storage_key)))
+    })
+}
+
+/// Resolves an asset's URL from its type, size and variant, or `None` if the
+/// asset does not exist
+pub type AssetLookup<'a> = dyn Fn(AssetType, Option<&str>, AssetVariant) -> Option<String> + 'a;
+
+/// Build head tags, taking each asset's URL from `find`
+pub fn build_head_tags_with(favicon: &Favicon, find: &AssetLookup<'_>) -> Vec<HeadTag> {
+    let theme_color = favicon.theme_color.as_deref().unwrap_or(DEFAULT_THEME_COLOR);
+    let light = |r#type, size| find(r#type, size, AssetVariant::Light);
+    let mut tags = Vec::new();
+
+    if let Some(href) = light(AssetType::Ico, Some("MULTI")) {
+        let sizes = ICO_SIZES.iter().map(|s| format!("{}x{}", s, s)).collect::<Vec<_>>().join(" ");
+        tags.push(HeadTag::link("icon", href, Some("image/x-icon"), Some(&sizes), None));
+    }
+
+    // Browsers apply the media query inside an adaptive SVG themselves
+    let svg = light(AssetType::Svg, None);
+    if let Some(href) = find(AssetType::Svg, None, AssetVariant::Adaptive).or_else(|| svg.clone()) {
+        tags.push(HeadTag::link("icon", href, Some("image/svg+xml"), None, None));
+    }
+
+    for size in LINKED_PNG_SIZES {
+        let dark = find(AssetType::Png, Some(size), AssetVariant::Dark);
+        if let Some(href) = light(AssetType::Png, Some(size)) {
+            let tag = HeadTag::link("icon", href, Some("image/png"), Some(size), None);
+            tags.push(if dark.is_some() { tag.with_media(LIGHT_SCHEME) } else { tag });
+        }
+        if let Some(href) = dark {
+            tags.push(HeadTag::link("icon", href, Some("image/png"), Some(size), None).with_media(DARK_SCHEME));
+        }
+    }
+
+    if let Some(href) = light(AssetType::Png, Some(APPLE_TOUCH_SIZE)) {
+        tags.push(HeadTag::link("apple-touch-icon", href, None, Some(APPLE_TOUCH_SIZE), None));
+    }
+
+    // Favicons generated before mask icons existed fall back to the canonical SVG
+    let mask_color = favicon.generation_options.as_ref()
+        .and_then(|options| options.mask_icon_color.as_deref())
+        .unwrap_or(theme_color);
+    if let Some(href) = light(AssetType::MaskIcon, None).or(svg) {
+        tags.push(HeadTag::link("mask-icon", href, None, None, Some(mask_color)));
+    }
+
+    if let Some(href) = light(AssetType::Manifest, None) {
+        tags.push(HeadTag::link("manifest", href, None, None, None));
+    }
+
+    if let Some(href) = light(AssetType::Browserconfig, None) {
+        tags.push(HeadTag::meta("msapplication-TileColor", theme_color));
+        tags.push(HeadTag::meta("msapplication-config", &href));
+    }
+
+    tags.push(HeadTag::meta("theme-color", theme_color));
+    tags
+}
+
+/// The asset of a given type, size and variant, if it was generated
+pub fn find_asset<'a>(
+    assets: &'a [FaviconAsset],
+    r#type: AssetType,
+    size: Option<&str>,
+    variant: AssetVariant,
+) -> Option<&'a FaviconAsset> {
+    assets
+        .iter()
+        .find(|asset| asset.r#type == r#type && asset.size.as_deref() == size && asset.variant == variant)
+}
+
+/// Render head tags as HTML, one per line
+pub fn render_html(tags: &[HeadTag]) -> String {
+    tags.iter().map(|tag| tag.to_html() + "\n").collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::GenerationOptions;
+    use chrono::Utc;
+
+    fn favicon() -> Favicon {
+        Favicon {
+            canonical_svg_key: Some("abc/SVG-canonical.svg".to_string()),
+            theme_color: Some("#1a2b3c".to_string()),
+            ..Favicon::test_default()
+        }
+    }
+
+    fn asset(r#type: AssetType, size: Option<&str>, format: &str) -> FaviconAsset {
+        variant_asset(r#type, size, AssetVariant::Light, format)
+    }
+
+    fn variant_asset(r#type: AssetType, size: Option<&str>, variant: AssetVariant, format: &str) -> FaviconAsset {
+        FaviconAsset {
+            id: "asset".to_string(),
+            favicon_id: "abc".to_string(),
+            r#type,
+            size: size.map(str::to_string),
+            variant,
+            format: format.to_string(),
+            storage_key: format!("abc/{}-{}{}{}", r#type.as_str(), size.unwrap_or("canonical"), variant.suffix(), format),
+            mime_type: String::new(),
+            created_at: Utc::now(),
+        }
+    }
+
+    #[test]
+    fn test_build_head_tags() {
+        let assets = vec![
+            asset(AssetType::Ico, Some("MULTI"), ".ico"),
+            asset(AssetType::Svg, None, ".svg"),
+            asset(AssetType::Png, Some("16x16"), ".png"),
+            asset(AssetType::Png, Some("32x32"), ".png"),
+            asset(AssetType::Png, Some("180x180"), ".png"),
+            asset(AssetType::MaskIcon, None, ".svg"),
+            asset(AssetType::Manifest, None, ".webmanifest"),
+            asset(AssetType::Browserconfig, None, ".xml"),
+        ];
+        let mut favicon = favicon();
+        favicon.generation_options = Some(GenerationOptions {
+            mask_icon_color: Some("#5bbad5".to_string()),
+            ..Default::default()
+        });
+        let html = render_html(&build_head_tags(&favicon, &assets, "https://cdn.example"));
+
+        assert_eq!(html, concat!(
+            "<link rel=\"icon\" type=\"image/x-icon\" sizes=\"16x16 32x32 48x48\" href=\"https://cdn.example/api/storage/abc/ICO-MULTI.ico\">\n",
+            "<link rel=\"icon\" type=\"image/svg+xml\" href=\"https://cdn.example/api/storage/abc/SVG-canonical.svg\">\n",
+            "<link rel=\"icon\" type=\"image/png\" sizes=\"32x32\" href=\"https://cdn.example/api/storage/abc/PNG-32x32.png\">\n",
+            "<link rel=\"icon\" type=\"image/png\" sizes=\"16x16\" href=\"https://cdn.example/api/storage/abc/PNG-16x16.png\">\n",
+            "<link rel=\"apple-touch-icon\" sizes=\"180x180\" href=\"https://cdn.example/api/storage/abc/PNG-180x180.png\">\n",
+            "<link rel=\"mask-icon\" href=\"https://cdn.example/api/storage/abc/MASK_ICON-canonical.svg\" color=\"#5bbad5\">\n",
+            "<link rel=\"manifest\" href=\"https://cdn.example/api/storage/abc/MANIFEST-canonical.webmanifest\">\n",
+            "<meta name=\"msapplication-TileColor\" content=\"#1a2b3c\">\n",
+            "<meta name=\"msapplication-config\" content=\"https://cdn.example/api/storage/abc/BROWSERCONFIG-canonical.xml\">\n",
+            "<meta name=\"theme-color\" content=\"#1a2b3c\">\n",
+        ));
+    }
+
+    #[test]
+    fn test_build_head_tags_with_dark_variants() {
+        let assets = vec![
+            asset(AssetType::Svg, None, ".svg"),
+            variant_asset(AssetType::Svg, None, AssetVariant::Adaptive, ".svg"),
+            asset(AssetType::Png, Some("32x32"), ".png"),
+            variant_asset(AssetType::Png, Some("32x32"), AssetVariant::Dark, ".png"),
+        ];
+        let html = render_html(&build_head_tags(&favicon(), &assets, "https://cdn.example"));
+
+        assert_eq!(html, concat!(
+            "<link rel=\"icon\" type=\"image/svg+xml\" href=\"https://cdn.example/api/storage/abc/SVG-canonical-adaptive.svg\">\n",
+            "<link rel=\"icon\" type=\"image/png\" sizes=\"32x32\" media=\"(prefers-color-scheme: light)\" href=\"https://cdn.example/api/storage/abc/PNG-32x32.png\">\n",
+            "<link rel=\"icon\" type=\"image/png\" sizes=\"32x32\" media=\"(prefers-color-scheme: dark)\" href=\"https://cdn.example/api/storage/abc/PNG-32x32-dark.png\">\n",
+            "<link rel=\"mask-icon\" href=\"https://cdn.example/api/storage/abc/SVG-canonical.svg\" color=\"#1a2b3c\">\n",
+            "<meta name=\"theme-color\" content=\"#1a2b3c\">\n",
+        ));
+    }
+
+    #[test]
+    fn test_build_head_tags_skips_missing_assets() {
+        let tags = build_head_tags(&favicon(), &[], DEFAULT_PUBLIC_BASE_URL);
+        assert_eq!(render_html(&tags), "<meta name=\"theme-color\" content=\"#1a2b3c\">\n");
+    }
+
+    #[test]
+    fn test_head_tag_json() {
+        let tag = HeadTag::link("apple-touch-icon", "https://a-icon.com/x.png".to_string(), None, Some("180x180"), None);
+        assert_eq!(
+            serde_json::to_value(&tag).unwrap(),
+            serde_json::json!({
+                "tag": "link",
+                "rel": "apple-touch-icon",
+                "href": "https://a-icon.com/x.png",
+                "sizes": "180x180",
+            })
+        );
+    }
+}
@@ -1,7 +1,16 @@
 use crate::error::HandlerError;
-use aws_sdk_s3::{Client, Config, config::Region, primitives::ByteStream};
+use crate::models::FaviconAsset;
+use crate::validation::sniff_image_type;
+use aws_sdk_s3::{Client, Config, config::Region, primitives::ByteStream, presigning::PresigningConfig};
 use aws_config::meta::region::RegionProviderChain;
 use std::env;
+use std::time::Duration;
+
+/// Size and content type of a stored object, as reported by `HEAD`
+pub struct ObjectHead {
+    pub content_length: i64,
+    pub content_type: Option<String>,
+}
 
 pub struct StorageService {
     client: Client,
@@ -59,6 +68,72 @@ impl StorageService {
         Ok(data.into_bytes().to_vec())
     }
 
+    /// Check whether an object exists without downloading it.
+    /// Returns `None` when the key is missing.
+    pub async fn head_object(&self, key: &str) -> Result<Option<ObjectHead>, HandlerError> {
+        let response = self.client
+            .head_object()
+            .bucket(&self.bucket)
+            .key(key)
+            .send()
+            .await;
+
+        match response {
+            Ok(head) => Ok(Some(ObjectHead {
+                content_length: head.content_length().unwrap_or(0),
+                content_type: head.content_type().map(|s| s.to_string()),
+            })),
+            Err(e) => {
+                let service_error = e.into_service_error();
+                if service_error.is_not_found() {
+                    Ok(None)
+                } else {
+                    Err(HandlerError::StorageError(format!("Failed to head object: {}", service_error)))
+                }
+            }
+        }
+    }
+
+    /// Copy an object to a new key within the bucket (server-side, no download)
+    pub async fn copy_object(&self, from_key: &str, to_key: &str) -> Result<(), HandlerError> {
+        self.client
+            .copy_object()
+            .bucket(&self.bucket)
+            .copy_source(format!("{}/{}", self.bucket, from_key))
+            .key(to_key)
+            .send()
+            .await
+            .map_err(|e| HandlerError::StorageError(format!("Failed to copy object: {}", e)))?;
+
+        Ok(())
+    }
+
+    /// Create a presigned URL that lets a client `PUT` a single object directly
+    /// to the bucket. The client must send the same `Content-Type` and
+    /// `Content-Length` it was signed with, so storage refuses any other size.
+    pub async fn presign_put_object(
+        &self,
+        key: &str,
+        content_type: &str,
+        content_length: i64,
+        expires_in: Duration,
+    ) -> Result<String, HandlerError> {
+        let presigning_config = PresigningConfig::expires_in(expires_in)
+            .map_err(|e| HandlerError::InternalError(format!("Invalid presign expiry: {}", e)))?;
+
+        let request = self.client
+            .put_object()
+            .bucket(&self.bucket)
+            .key(key)
+            .content_type(content_type)
+            .content_length(content_length)
+            .presigned(presigning_config)
+            .await
+            .map_err(|e| HandlerError::StorageError(format!("Failed to presign upload: {}", e)))?;
+
+        Ok(request.uri().to_string())
+    }
+
     pub async fn delete_object(&self, key: &str) -> Result<(), HandlerError> {
         self.client
             .delete_object()
@@ -71,41 +146,50 @@ impl StorageService {
         Ok(())
     }
 
-    pub fn detect_mime_type(buffer: &[u8]) -> String {
-        if buffer.len() < 4 {
-            return "application/octet-stream".to_string();
-        }
+    /// Keys of all objects whose key starts with `prefix`
+    pub async fn list_objects(&self, prefix: &str) -> Result<Vec<String>, HandlerError> {
+        let mut keys = Vec::new();
+        let mut continuation_token = None;
+        loop {
+            let response = self.client
+                .list_objects_v2()
+                .bucket(&self.bucket)
+                .prefix(prefix)
+                .set_continuation_token(continuation_token)
+                .send()
+                .await
+                .map_err(|e| HandlerError::StorageError(format!("Failed to list objects: {}", e)))?;
 
-        // PNG: 89 50 4E 47
-        if buffer[0] == 0x89 && buffer[1] == 0x50 && buffer[2] == 0x4E && buffer[3] == 0x47 {
-            return "image/png".to_string();
+            keys.extend(response.contents().iter().filter_map(|object| object.key()).map(str::to_string));
+            match response.next_continuation_token() {
+                Some(token) if response.is_truncated() == Some(true) => continuation_token = Some(token.to_string()),
+                _ => return Ok(keys),
+            }
         }
+    }
 
-        // JPEG: FF D8 FF
-        if buffer[0] == 0xFF && buffer[1] == 0xD8 && buffer[2] == 0xFF {
-            return "image/jpeg".to_string();
+    /// Delete a favicon's sources, assets, cached bundles and renditions.
+    /// Failures are ignored so a missing object never blocks deleting the
+    /// favicon itself.
+    pub async fn delete_favicon_objects(&self, favicon_id: &str, assets: &[FaviconAsset]) {
+        for source in ["original", "dark", "document"] {
+            let _ = self.delete_object(&format!("sources/{}/{}", favicon_id, source)).await;
         }
-
-        // GIF: 47 49 46
-        if buffer[0] == 0x47 && buffer[1] == 0x49 && buffer[2] == 0x46 {
-            return "image/gif".to_string();
+        for asset in assets {
+            let _ = self.delete_object(&asset.storage_key).await;
         }
-
-        // SVG: starts with < or whitespace then <
-        if let Ok(str) = std::str::from_utf8(&buffer[..buffer.len().min(100)]) {
-            let trimmed = str.trim();
-            if trimmed.starts_with("<svg") || trimmed.starts_with("<?xml") {
-                return "image/svg+xml".to_string();
+        for cache in ["bundles", "renders"] {
+            let prefix = format!("{}/{}/", cache, favicon_id);
+            for key in self.list_objects(&prefix).await.unwrap_or_default() {
+                let _ = self.delete_object(&key).await;
             }
         }
+    }
 
-        // ICO: 00 00 01 00
-        if buffer.len() >= 4 && buffer[0] == 0x00 && buffer[1] == 0x00 
-            && buffer[2] == 0x01 && buffer[3] == 0x00 {
-            return "image/x-icon".to_string();
-        }
-
-        "application/octet-stream".to_string()
+    pub fn detect_mime_type(buffer: &[u8]) -> String {
+        sniff_image_type(buffer)
+            .unwrap_or("application/octet-stream")
+            .to_string()
     }
 
     pub fn mime_type_from_extension(ext: &str) -> String {
@@ -114,7 +198,13 @@ impl StorageService {
             "jpg" | "jpeg" => "image/jpeg",
             "gif" => "image/gif",
             "svg" => "image/svg+xml",
-            "ico" => "image/x-icon",
+            "ico" | "cur" => "image/x-icon",
+            "webp" => "image/webp",
+            "avif" => "image/avif",
+            "bmp" => "image/bmp",
+            "tif" | "tiff" => "image/tiff",
+            "webmanifest" => "application/manifest+json",
+            "xml" => "application/xml",
             _ => "application/octet-stream",
         }.to_string()
     }
@@ -141,6 +231,14 @@ mod tests {
         // ICO
         let ico = vec![0x00, 0x00, 0x01, 0x00];
         assert_eq!(StorageService::detect_mime_type(&ico), "image/x-icon");
+
+        // WebP
+        let webp = b"RIFF\x24\0\0\0WEBPVP8L";
+        assert_eq!(StorageService::detect_mime_type(webp), "image/webp");
+
+        // TIFF
+        let tiff = b"II*\0\x08\0\0\0";
+        assert_eq!(StorageService::detect_mime_type(tiff), "image/tiff");
     }
 
     #[test]
@@ -150,6 +248,8 @@ mod tests {
         assert_eq!(StorageService::mime_type_from_extension("jpg"), "image/jpeg");
         assert_eq!(StorageService::mime_type_from_extension("svg"), "image/svg+xml");
         assert_eq!(StorageService::mime_type_from_extension("ico"), "image/x-icon");
+        assert_eq!(StorageService::mime_type_from_extension("webp"), "image/webp");
+        assert_eq!(StorageService::mime_type_from_extension("tif"), "image/tiff");
         assert_eq!(StorageService::mime_type_from_extension("unknown"), "application/octet-stream");
     }
 }
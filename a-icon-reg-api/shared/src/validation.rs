@@ -1,53 +1,162 @@
+use crate::animation::frame_delay_ms;
+use crate::canvas::{
+    is_supported_char, CanvasColor, CanvasDocument, CanvasElement, CANVAS_DOCUMENT_VERSION, MAX_CANVAS_ELEMENTS,
+    MAX_CANVAS_LAYERS, MAX_CANVAS_POINTS, MAX_CANVAS_SIZE, MAX_PALETTE_COLORS, MAX_TEXT_LENGTH,
+};
 use crate::error::HandlerError;
+use crate::ico;
+use crate::models::{DarkVariant, FitMode, GenerationOptions};
+use image::codecs::gif::GifDecoder;
+use idna::uts46::{AsciiDenyList, DnsLength, Hyphens, Uts46};
+use image::{AnimationDecoder, ImageDecoder, ImageFormat, ImageReader, Limits};
+use publicsuffix::{List, Psl};
 use regex::Regex;
+use resvg::usvg;
+use std::env;
+use std::fs;
+use std::io::Cursor;
+use std::sync::OnceLock;
 
-/// Validate domain name format
-/// - Max 256 characters
+/// Maximum width or height of a decoded source image
+pub const MAX_IMAGE_DIMENSION: u32 = 4096;
+
+/// Maximum number of pixels in a decoded source image (one full frame)
+pub const MAX_IMAGE_PIXELS: u64 = 4096 * 4096;
+
+/// Maximum number of frames in an animated GIF source
+pub const MAX_GIF_FRAMES: usize = 256;
+
+/// Maximum number of pixels decoded across all frames of an animated GIF
+pub const MAX_GIF_TOTAL_PIXELS: u64 = 64 * 1024 * 1024;
+
+/// Maximum playing time of one loop of an animated GIF source
+pub const MAX_GIF_DURATION_MS: u32 = 30_000;
+
+/// Maximum memory the decoder may allocate for a single image
+const MAX_DECODE_ALLOC: u64 = 256 * 1024 * 1024;
+
+/// Maximum size of an SVG source
+pub const MAX_SVG_BYTES: usize = 1024 * 1024;
+
+/// Maximum number of elements in an SVG source, counted both in the document
+/// and after `<use>` references are expanded
+pub const MAX_SVG_ELEMENTS: usize = 10_000;
+
+/// Longest domain name in DNS presentation form, without the trailing dot
+pub const MAX_DOMAIN_LENGTH: usize = 253;
+
+/// Longest label (the parts between dots) of a domain name
+pub const MAX_DOMAIN_LABEL_LENGTH: usize = 63;
+
+/// Validate a domain name and return its ASCII form, which is what gets stored
+/// and matched. Internationalized names go through UTS-46 processing (case
+/// folding, normalization) and are returned as punycode A-labels.
+/// - At most 253 octets in ASCII form, and at most 63 per label
 /// - Must contain a "." with content before and after it (TLD syntax)
-pub fn validate_domain(domain: &str) -> Result<(), HandlerError> {
+/// - Must not be a public suffix such as `co.uk`, when a Public Suffix List
+///   is configured with `PUBLIC_SUFFIX_LIST_PATH`
+pub fn validate_domain(domain: &str) -> Result<String, HandlerError> {
+    // Hyphens in the third and fourth position are allowed, as in real-world names
+    let ascii = Uts46::new()
+        .to_ascii(domain.as_bytes(), AsciiDenyList::STD3, Hyphens::CheckFirstLast, DnsLength::Ignore)
+        .map_err(|_| HandlerError::ValidationError(
+            "Invalid domain name. It contains characters that are not allowed in domain names".to_string(),
+        ))?
+        .into_owned();
+
     // Check length
-    if domain.len() > 256 {
-        return Err(HandlerError::ValidationError(
-            "Domain name must not exceed 256 characters".to_string(),
-        ));
+    if ascii.len() > MAX_DOMAIN_LENGTH {
+        return Err(HandlerError::ValidationError(format!(
+            "Domain name must not exceed {} characters in ASCII form", MAX_DOMAIN_LENGTH
+        )));
     }
 
     // Check for dot presence
-    if !domain.contains('.') {
+    if !ascii.contains('.') {
         return Err(HandlerError::ValidationError(
             "Domain name must contain at least one dot (.)".to_string(),
         ));
     }
 
-    // Check that there's content before and after the dot
-    let parts: Vec<&str> = domain.split('.').collect();
-    if parts.len() < 2 {
-        return Err(HandlerError::ValidationError(
-            "Domain name must have content before and after the dot".to_string(),
-        ));
-    }
-
     // Check that no part is empty
+    let parts: Vec<&str> = ascii.split('.').collect();
     if parts.iter().any(|part| part.is_empty()) {
         return Err(HandlerError::ValidationError(
             "Domain name cannot have empty parts (e.g., \"example..com\")".to_string(),
         ));
     }
 
-    // Validate domain format with regex
+    if parts.iter().any(|part| part.len() > MAX_DOMAIN_LABEL_LENGTH) {
+        return Err(HandlerError::ValidationError(format!(
+            "Each part of a domain name must not exceed {} characters in ASCII form", MAX_DOMAIN_LABEL_LENGTH
+        )));
+    }
+
+    // Validate the ASCII form with regex
     let domain_regex = Regex::new(
-        r"^[a-zA-Z0-9]([a-zA-Z0-9-]{0,61}[a-zA-Z0-9])?(\.[a-zA-Z0-9]([a-zA-Z0-9-]{0,61}[a-zA-Z0-9])?)+$"
+        r"^[a-z0-9]([a-z0-9-]*[a-z0-9])?(\.[a-z0-9]([a-z0-9-]*[a-z0-9])?)+$"
     ).unwrap();
 
-    if !domain_regex.is_match(domain) {
+    if !domain_regex.is_match(&ascii) {
         return Err(HandlerError::ValidationError(
             "Invalid domain name format. Domain must contain only letters, numbers, hyphens, and dots, and follow TLD syntax".to_string(),
         ));
     }
 
+    if let Some(list) = public_suffix_list()? {
+        check_not_public_suffix(&ascii, list)?;
+    }
+
+    Ok(ascii)
+}
+
+/// Reject a domain (in ASCII form) that is itself a public suffix, which
+/// anyone can register names under but nobody owns
+pub fn check_not_public_suffix(ascii: &str, list: &List) -> Result<(), HandlerError> {
+    if list.domain(ascii.as_bytes()).is_none() {
+        return Err(HandlerError::ValidationError(format!(
+            "{} is a public suffix; use a domain registered under it", ascii
+        )));
+    }
     Ok(())
 }
 
+/// Whether a domain (in ASCII form) is a public suffix. Always false when no
+/// Public Suffix List is configured.
+pub fn is_public_suffix(ascii: &str) -> Result<bool, HandlerError> {
+    Ok(public_suffix_list()?.is_some_and(|list| check_not_public_suffix(ascii, list).is_err()))
+}
+
+/// The Public Suffix List at `PUBLIC_SUFFIX_LIST_PATH`, read once per process.
+/// `None` if the variable is not set.
+fn public_suffix_list() -> Result<Option<&'static List>, HandlerError> {
+    static LIST: OnceLock<Option<Result<List, String>>> = OnceLock::new();
+
+    let list = LIST.get_or_init(|| {
+        let path = env::var("PUBLIC_SUFFIX_LIST_PATH").ok()?;
+        let list = fs::read(&path)
+            .map_err(|e| e.to_string())
+            .and_then(|data| List::from_bytes(&data).map_err(|e| e.to_string()))
+            .map_err(|e| format!("Failed to load public suffix list {}: {}", path, e));
+        Some(list)
+    });
+
+    match list {
+        None => Ok(None),
+        Some(Ok(list)) => Ok(Some(list)),
+        Some(Err(e)) => Err(HandlerError::InternalError(e.clone())),
+    }
+}
+
+/// Unicode form of a stored (ASCII) domain, for display. Names that do not
+/// decode are shown as stored.
+pub fn display_domain(ascii: &str) -> String {
+    match idna::domain_to_unicode(ascii) {
+        (unicode, Ok(())) => unicode,
+        (_, Err(_)) => ascii.to_string(),
+    }
+}
+
 /// Validate metadata length (max 256 characters for JPEG compatibility)
 pub fn validate_metadata(metadata: &str) -> Result<(), HandlerError> {
     if metadata.len() > 256 {
@@ -58,10 +167,301 @@ pub fn validate_metadata(metadata: &str) -> Result<(), HandlerError> {
     Ok(())
 }
 
+/// Parse a CSS hex color (`#rgb` or `#rrggbb`) into RGB components
+pub fn parse_hex_color(color: &str) -> Option<[u8; 3]> {
+    let hex = color.strip_prefix('#')?;
+    if !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return None;
+    }
+
+    let component = |digits: &str| u8::from_str_radix(digits, 16).ok();
+    match hex.len() {
+        3 => {
+            let expand = |i: usize| component(&hex[i..i + 1]).map(|v| v * 17);
+            Some([expand(0)?, expand(1)?, expand(2)?])
+        }
+        6 => Some([component(&hex[0..2])?, component(&hex[2..4])?, component(&hex[4..6])?]),
+        _ => None,
+    }
+}
+
+/// Validate a manifest color and normalize it to lowercase `#rrggbb`
+pub fn validate_color(color: &str) -> Result<String, HandlerError> {
+    let [r, g, b] = parse_hex_color(color.trim()).ok_or_else(|| {
+        HandlerError::ValidationError(format!(
+            "Invalid color '{}'. Colors must be hex values like #fff or #1a2b3c",
+            color
+        ))
+    })?;
+    Ok(format!("#{:02x}{:02x}{:02x}", r, g, b))
+}
+
+/// Largest maskable padding, in percent of each edge; the image keeps at
+/// least half the icon
+pub const MAX_MASKABLE_PADDING: u32 = 25;
+
+/// Largest corner radius, in percent of the icon size (a circle)
+pub const MAX_CORNER_RADIUS: u32 = 50;
+
+/// Parse and validate `generationOptions` sent as a JSON string (multipart uploads)
+pub fn parse_generation_options(json: &str) -> Result<GenerationOptions, HandlerError> {
+    let value: serde_json::Value = serde_json::from_str(json).map_err(|_| {
+        HandlerError::ValidationError("generationOptions must be a JSON object".to_string())
+    })?;
+    validate_generation_options(&value)
+}
+
+/// Check that the options' `frameIndex` names a frame of the source
+pub fn validate_frame_index(options: Option<&GenerationOptions>, frame_count: usize) -> Result<(), HandlerError> {
+    match options.and_then(|options| options.frame_index) {
+        Some(index) if index as usize >= frame_count => Err(HandlerError::ValidationError(format!(
+            "frameIndex {} is out of range; the source has {} frame(s)",
+            index, frame_count
+        ))),
+        _ => Ok(()),
+    }
+}
+
+/// Check that the options' `crop` lies within the source, whose size is
+/// `width`x`height` (for SVGs, their intrinsic size in SVG units)
+pub fn validate_crop(options: Option<&GenerationOptions>, width: u32, height: u32) -> Result<(), HandlerError> {
+    match options.and_then(|options| options.crop) {
+        Some(crop) if crop.x.saturating_add(crop.width) > width || crop.y.saturating_add(crop.height) > height => {
+            Err(HandlerError::ValidationError(format!("crop exceeds the {}x{} source", width, height)))
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Validate `generationOptions` and normalize colors
+pub fn validate_generation_options(value: &serde_json::Value) -> Result<GenerationOptions, HandlerError> {
+    if !value.is_object() {
+        return Err(HandlerError::ValidationError("generationOptions must be a JSON object".to_string()));
+    }
+    let mut options: GenerationOptions = serde_json::from_value(value.clone())
+        .map_err(|e| HandlerError::ValidationError(format!("Invalid generationOptions: {}", e)))?;
+
+    if let Some(background) = options.background.as_deref() {
+        options.background = Some(if background.trim().eq_ignore_ascii_case("transparent") {
+            "transparent".to_string()
+        } else {
+            validate_color(background).map_err(|_| {
+                HandlerError::ValidationError(format!(
+                    "Invalid background '{}'. Use 'transparent' or a hex color like #1a2b3c",
+                    background
+                ))
+            })?
+        });
+    }
+
+    if let Some(DarkVariant::Recolor { color }) = options.dark_variant.as_mut() {
+        *color = validate_color(color).map_err(|_| {
+            HandlerError::ValidationError(format!(
+                "Invalid darkVariant color '{}'. Use a hex color like #ffffff",
+                color
+            ))
+        })?;
+    }
+
+    if let Some(color) = options.mask_icon_color.as_deref() {
+        options.mask_icon_color = Some(validate_color(color).map_err(|_| {
+            HandlerError::ValidationError(format!(
+                "Invalid maskIconColor '{}'. Use a hex color like #1a2b3c",
+                color
+            ))
+        })?);
+    }
+
+    if options.maskable_padding.is_some_and(|padding| padding > MAX_MASKABLE_PADDING) {
+        return Err(HandlerError::ValidationError(format!(
+            "maskablePadding must be between 0 and {} (percent of each edge)",
+            MAX_MASKABLE_PADDING
+        )));
+    }
+
+    if options.corner_radius.is_some_and(|radius| radius > MAX_CORNER_RADIUS) {
+        return Err(HandlerError::ValidationError(format!(
+            "cornerRadius must be between 0 and {} (percent of the icon size)",
+            MAX_CORNER_RADIUS
+        )));
+    }
+
+    match (options.fit, options.crop) {
+        (FitMode::Crop, None) => {
+            return Err(HandlerError::ValidationError("crop is required when fit is 'crop'".to_string()));
+        }
+        (FitMode::Crop, Some(crop)) => {
+            if crop.width == 0 || crop.height == 0 {
+                return Err(HandlerError::ValidationError("crop width and height must be greater than 0".to_string()));
+            }
+            if crop.x.saturating_add(crop.width) > MAX_IMAGE_DIMENSION
+                || crop.y.saturating_add(crop.height) > MAX_IMAGE_DIMENSION
+            {
+                return Err(HandlerError::ValidationError(format!(
+                    "crop must lie within {}x{} pixels",
+                    MAX_IMAGE_DIMENSION, MAX_IMAGE_DIMENSION
+                )));
+            }
+        }
+        (_, Some(_)) => {
+            return Err(HandlerError::ValidationError("crop is only used when fit is 'crop'".to_string()));
+        }
+        (_, None) => {}
+    }
+
+    Ok(options)
+}
+
+/// Parse and validate a canvas document, normalizing its colors to `#rrggbb`.
+/// Coordinates may reach past the canvas, but not beyond four times its
+/// largest size.
+pub fn validate_canvas_document(value: &serde_json::Value) -> Result<CanvasDocument, HandlerError> {
+    let invalid = |message: String| HandlerError::ValidationError(format!("Invalid canvas document: {}", message));
+    if !value.is_object() {
+        return Err(HandlerError::ValidationError("document must be a JSON object".to_string()));
+    }
+
+    // Check the version first so documents from newer editors get a clear error
+    match value.get("version").and_then(|version| version.as_u64()) {
+        Some(version) if version == CANVAS_DOCUMENT_VERSION as u64 => {}
+        Some(version) => {
+            return Err(HandlerError::ValidationError(format!(
+                "Unsupported canvas document version {}; expected {}",
+                version, CANVAS_DOCUMENT_VERSION
+            )));
+        }
+        None => return Err(invalid("version is required".to_string())),
+    }
+
+    let mut document: CanvasDocument = serde_json::from_value(value.clone())
+        .map_err(|e| invalid(e.to_string()))?;
+
+    if !(1..=MAX_CANVAS_SIZE).contains(&document.width) || !(1..=MAX_CANVAS_SIZE).contains(&document.height) {
+        return Err(invalid(format!("width and height must be between 1 and {}", MAX_CANVAS_SIZE)));
+    }
+    if document.layers.len() > MAX_CANVAS_LAYERS {
+        return Err(invalid(format!("at most {} layers are allowed", MAX_CANVAS_LAYERS)));
+    }
+    if document.palette.len() > MAX_PALETTE_COLORS {
+        return Err(invalid(format!("the palette may have at most {} colors", MAX_PALETTE_COLORS)));
+    }
+
+    let elements = document.layers.iter().flat_map(|layer| layer.elements.iter());
+    if elements.clone().count() > MAX_CANVAS_ELEMENTS {
+        return Err(invalid(format!("at most {} elements are allowed", MAX_CANVAS_ELEMENTS)));
+    }
+    let points: usize = elements
+        .map(|element| match element {
+            CanvasElement::Stroke { points, .. } | CanvasElement::Polygon { points, .. } => points.len(),
+            _ => 0,
+        })
+        .sum();
+    if points > MAX_CANVAS_POINTS {
+        return Err(invalid(format!("at most {} points are allowed", MAX_CANVAS_POINTS)));
+    }
+
+    for color in document.palette.iter_mut() {
+        *color = validate_color(color).map_err(|_| invalid(format!("palette color '{}' is not a hex color", color)))?;
+    }
+
+    let palette_len = document.palette.len();
+    let check_color = |color: &mut CanvasColor| match color {
+        CanvasColor::Palette(index) if *index >= palette_len => {
+            Err(invalid(format!("palette index {} is out of range", index)))
+        }
+        CanvasColor::Palette(_) => Ok(()),
+        CanvasColor::Hex(hex) => {
+            *hex = validate_color(hex).map_err(|_| invalid(format!("'{}' is not a hex color", hex)))?;
+            Ok(())
+        }
+    };
+    let max_coordinate = 4.0 * MAX_CANVAS_SIZE as f64;
+    let check_coordinates = |values: &[f64]| {
+        if values.iter().all(|value| value.is_finite() && value.abs() <= max_coordinate) {
+            Ok(())
+        } else {
+            Err(invalid(format!("coordinates must be numbers between -{0} and {0}", max_coordinate)))
+        }
+    };
+    let check_size = |value: f64, name: &str| {
+        if value.is_finite() && value > 0.0 && value <= max_coordinate {
+            Ok(())
+        } else {
+            Err(invalid(format!("{} must be greater than 0 and at most {}", name, max_coordinate)))
+        }
+    };
+    let check_points = |points: &[[f64; 2]]| check_coordinates(&points.concat());
+    let check_paint = |fill: &mut Option<CanvasColor>, stroke: &mut Option<CanvasColor>, stroke_width: Option<f64>| {
+        if fill.is_none() && stroke.is_none() {
+            return Err(invalid("shapes need a fill or a stroke".to_string()));
+        }
+        fill.as_mut().map(check_color).transpose()?;
+        stroke.as_mut().map(check_color).transpose()?;
+        stroke_width.map(|width| check_size(width, "strokeWidth")).transpose()?;
+        Ok(())
+    };
+
+    if let Some(background) = document.background.as_mut() {
+        check_color(background)?;
+    }
+    for layer in document.layers.iter_mut() {
+        if !(0.0..=1.0).contains(&layer.opacity) {
+            return Err(invalid("layer opacity must be between 0 and 1".to_string()));
+        }
+        for element in layer.elements.iter_mut() {
+            match element {
+                CanvasElement::Fill { color } => check_color(color)?,
+                CanvasElement::Stroke { points, color, width } => {
+                    if points.is_empty() {
+                        return Err(invalid("strokes need at least one point".to_string()));
+                    }
+                    check_points(points)?;
+                    check_size(*width, "stroke width")?;
+                    check_color(color)?;
+                }
+                CanvasElement::Rect { x, y, width, height, radius, fill, stroke, stroke_width } => {
+                    check_coordinates(&[*x, *y])?;
+                    check_size(*width, "rect width")?;
+                    check_size(*height, "rect height")?;
+                    if !(radius.is_finite() && *radius >= 0.0) {
+                        return Err(invalid("rect radius must not be negative".to_string()));
+                    }
+                    check_paint(fill, stroke, *stroke_width)?;
+                }
+                CanvasElement::Ellipse { cx, cy, rx, ry, fill, stroke, stroke_width } => {
+                    check_coordinates(&[*cx, *cy])?;
+                    check_size(*rx, "ellipse rx")?;
+                    check_size(*ry, "ellipse ry")?;
+                    check_paint(fill, stroke, *stroke_width)?;
+                }
+                CanvasElement::Polygon { points, fill, stroke, stroke_width } => {
+                    if points.len() < 3 {
+                        return Err(invalid("polygons need at least three points".to_string()));
+                    }
+                    check_points(points)?;
+                    check_paint(fill, stroke, *stroke_width)?;
+                }
+                CanvasElement::Text { x, y, text, size, color } => {
+                    if text.chars().count() > MAX_TEXT_LENGTH {
+                        return Err(invalid(format!("text may be at most {} characters", MAX_TEXT_LENGTH)));
+                    }
+                    if let Some(c) = text.chars().find(|c| !is_supported_char(*c)) {
+                        return Err(invalid(format!("the font has no glyph for '{}'", c)));
+                    }
+                    check_coordinates(&[*x, *y])?;
+                    check_size(*size, "text size")?;
+                    check_color(color)?;
+                }
+            }
+        }
+    }
+
+    Ok(document)
+}
+
 /// Validate file size (max 0.5 MB)
 pub fn validate_file_size(size: usize) -> Result<(), HandlerError> {
-    const MAX_SIZE: usize = 512 * 1024; // 0.5 MB
-    if size > MAX_SIZE {
+    if size > MAX_FILE_SIZE {
         let size_mb = size as f64 / (1024.0 * 1024.0);
         return Err(HandlerError::ValidationError(format!(
             "File size ({:.2}MB) exceeds the maximum allowed size of 0.5 MB",
@@ -71,46 +471,382 @@ pub fn validate_file_size(size: usize) -> Result<(), HandlerError> {
     Ok(())
 }
 
-/// Validate that the buffer is an image
-pub fn validate_image_type(buffer: &[u8]) -> Result<String, HandlerError> {
-    if buffer.len() < 4 {
+/// Maximum size of a source sent through a handler (multipart, canvas, import)
+pub const MAX_FILE_SIZE: usize = 512 * 1024; // 0.5 MB
+
+/// Maximum size of a source uploaded directly to storage
+pub const MAX_DIRECT_UPLOAD_SIZE: usize = 5 * 1024 * 1024; // 5 MB
+
+/// Validate the size of a source uploaded directly to storage (max 5 MB).
+/// Direct uploads bypass the gateway, so they are not bound by the 0.5 MB
+/// limit that applies to multipart and canvas bodies.
+pub fn validate_direct_upload_size(size: usize) -> Result<(), HandlerError> {
+    if size == 0 {
         return Err(HandlerError::ValidationError(
-            "File is too small to be a valid image".to_string(),
+            "Uploaded file is empty".to_string(),
         ));
     }
+    if size > MAX_DIRECT_UPLOAD_SIZE {
+        let size_mb = size as f64 / (1024.0 * 1024.0);
+        return Err(HandlerError::ValidationError(format!(
+            "File size ({:.2}MB) exceeds the maximum allowed size of 5 MB",
+            size_mb
+        )));
+    }
+    Ok(())
+}
+
+/// Content types accepted as favicon sources. AVIF is only accepted when
+/// built with the `avif` feature, which can decode it.
+#[cfg(feature = "avif")]
+pub const SOURCE_MIME_TYPES: &[&str] = &[
+    "image/png",
+    "image/jpeg",
+    "image/gif",
+    "image/svg+xml",
+    "image/webp",
+    "image/avif",
+    "image/bmp",
+    "image/x-icon",
+];
+#[cfg(not(feature = "avif"))]
+pub const SOURCE_MIME_TYPES: &[&str] = &[
+    "image/png",
+    "image/jpeg",
+    "image/gif",
+    "image/svg+xml",
+    "image/webp",
+    "image/bmp",
+    "image/x-icon",
+];
+
+#[cfg(feature = "avif")]
+const UNSUPPORTED_TYPE_MESSAGE: &str =
+    "Only image files are allowed (PNG, JPEG, GIF, SVG, WebP, AVIF, BMP, ICO)";
+#[cfg(not(feature = "avif"))]
+const UNSUPPORTED_TYPE_MESSAGE: &str =
+    "Only image files are allowed (PNG, JPEG, GIF, SVG, WebP, BMP, ICO)";
+
+/// Validate the declared content type of a direct upload
+pub fn validate_upload_content_type(content_type: &str) -> Result<(), HandlerError> {
+    // Browsers disagree on the ICO content type
+    let content_type = match content_type {
+        "image/vnd.microsoft.icon" => "image/x-icon",
+        other => other,
+    };
+
+    if SOURCE_MIME_TYPES.contains(&content_type) {
+        Ok(())
+    } else {
+        Err(HandlerError::ValidationError(UNSUPPORTED_TYPE_MESSAGE.to_string()))
+    }
+}
+
+/// Identify an image format from its magic bytes.
+///
+/// Recognizes every source format plus TIFF, which is only served, never
+/// accepted as a source. Cursor files are reported as `image/x-icon` since
+/// they share the ICO container.
+pub fn sniff_image_type(buffer: &[u8]) -> Option<&'static str> {
+    if buffer.len() < 4 {
+        return None;
+    }
 
-    // Check magic bytes
     // PNG: 89 50 4E 47
-    if buffer[0] == 0x89 && buffer[1] == 0x50 && buffer[2] == 0x4E && buffer[3] == 0x47 {
-        return Ok("image/png".to_string());
+    if buffer.starts_with(&[0x89, 0x50, 0x4E, 0x47]) {
+        return Some("image/png");
     }
 
     // JPEG: FF D8 FF
-    if buffer[0] == 0xFF && buffer[1] == 0xD8 && buffer[2] == 0xFF {
-        return Ok("image/jpeg".to_string());
+    if buffer.starts_with(&[0xFF, 0xD8, 0xFF]) {
+        return Some("image/jpeg");
     }
 
     // GIF: 47 49 46
-    if buffer[0] == 0x47 && buffer[1] == 0x49 && buffer[2] == 0x46 {
-        return Ok("image/gif".to_string());
+    if buffer.starts_with(b"GIF") {
+        return Some("image/gif");
+    }
+
+    // WebP: RIFF <size> WEBP
+    if buffer.starts_with(b"RIFF") && buffer.get(8..12) == Some(b"WEBP") {
+        return Some("image/webp");
+    }
+
+    // AVIF: ISO BMFF ftyp box listing an AVIF brand
+    if buffer.get(4..8) == Some(b"ftyp") && has_avif_brand(buffer) {
+        return Some("image/avif");
+    }
+
+    // BMP: 42 4D
+    if buffer.starts_with(b"BM") {
+        return Some("image/bmp");
+    }
+
+    // ICO: 00 00 01 00, CUR: 00 00 02 00
+    if buffer.starts_with(&[0x00, 0x00, 0x01, 0x00]) || buffer.starts_with(&[0x00, 0x00, 0x02, 0x00]) {
+        return Some("image/x-icon");
+    }
+
+    // TIFF: little-endian (II*\0) or big-endian (MM\0*)
+    if buffer.starts_with(b"II*\0") || buffer.starts_with(b"MM\0*") {
+        return Some("image/tiff");
     }
 
     // SVG: starts with < or whitespace then <
     if let Ok(str) = std::str::from_utf8(&buffer[..buffer.len().min(100)]) {
         let trimmed = str.trim();
         if trimmed.starts_with("<svg") || trimmed.starts_with("<?xml") {
-            return Ok("image/svg+xml".to_string());
+            return Some("image/svg+xml");
+        }
+    }
+
+    None
+}
+
+/// Check the major and compatible brands of an `ftyp` box
+fn has_avif_brand(buffer: &[u8]) -> bool {
+    let box_size = u32::from_be_bytes([buffer[0], buffer[1], buffer[2], buffer[3]]) as usize;
+    let ftyp = &buffer[..box_size.min(buffer.len())];
+
+    // Major brand at 8..12, minor version at 12..16, compatible brands after
+    let major = ftyp.get(8..12).into_iter();
+    let compatible = ftyp.get(16..).unwrap_or_default().chunks_exact(4);
+    major.chain(compatible).any(|brand| brand == b"avif" || brand == b"avis")
+}
+
+/// Validate that the buffer is an image in one of the accepted source formats
+pub fn validate_image_type(buffer: &[u8]) -> Result<String, HandlerError> {
+    if buffer.len() < 4 {
+        return Err(HandlerError::ValidationError(
+            "File is too small to be a valid image".to_string(),
+        ));
+    }
+
+    match sniff_image_type(buffer) {
+        Some(mime_type) if SOURCE_MIME_TYPES.contains(&mime_type) => Ok(mime_type.to_string()),
+        _ => Err(HandlerError::ValidationError(UNSUPPORTED_TYPE_MESSAGE.to_string())),
+    }
+}
+
+/// Properties of a source image, obtained by fully decoding it
+#[derive(Debug, Clone)]
+pub struct ImageInfo {
+    pub mime_type: String,
+    pub width: u32,
+    pub height: u32,
+    pub color_type: String,
+    pub frame_count: usize,
+}
+
+/// Validate that the buffer is a well-formed image by decoding it completely.
+///
+/// Unlike `validate_image_type`, which only looks at the magic bytes, this
+/// rejects truncated or corrupt streams, trailing data after the end of the
+/// image (polyglot files), images over `MAX_IMAGE_DIMENSION`/`MAX_IMAGE_PIXELS`,
+/// and GIFs whose frames would decode to more than `MAX_GIF_TOTAL_PIXELS` or
+/// play for longer than `MAX_GIF_DURATION_MS`. For ICO and CUR files only the
+/// largest frame is decoded and described. SVGs must parse, be at most
+/// `MAX_SVG_BYTES` long and hold at most `MAX_SVG_ELEMENTS` elements, before
+/// and after `<use>` references are expanded; their intrinsic width and
+/// height are not limited since they are rasterized at a fixed resolution.
+pub fn validate_image_content(buffer: &[u8]) -> Result<ImageInfo, HandlerError> {
+    let mime_type = validate_image_type(buffer)?;
+
+    if mime_type == "image/svg+xml" {
+        return validate_svg_content(buffer);
+    }
+
+    check_trailing_data(&mime_type, buffer)?;
+
+    // Every frame of an ICO/CUR must decode; the largest becomes the master
+    // image and is described
+    let icon_frame;
+    let data = if mime_type == "image/x-icon" {
+        check_icon_frames(buffer)?;
+        icon_frame = ico::largest_frame(buffer)?;
+        &icon_frame[..]
+    } else {
+        buffer
+    };
+
+    let mut reader = ImageReader::new(Cursor::new(data))
+        .with_guessed_format()
+        .map_err(|e| HandlerError::ValidationError(format!("Failed to read image: {}", e)))?;
+    reader.limits(decode_limits());
+
+    let decoder = reader.into_decoder()
+        .map_err(|e| HandlerError::ValidationError(format!("Invalid image: {}", e)))?;
+    let (width, height) = decoder.dimensions();
+    let color_type = format!("{:?}", decoder.color_type());
+    check_dimensions(width, height)?;
+
+    let frame_count = if mime_type == "image/gif" {
+        drop(decoder);
+        count_gif_frames(buffer, width, height)?
+    } else {
+        image::DynamicImage::from_decoder(decoder)
+            .map_err(|e| HandlerError::ValidationError(format!("Image data is corrupt or truncated: {}", e)))?;
+        1
+    };
+
+    Ok(ImageInfo { mime_type, width, height, color_type, frame_count })
+}
+
+fn decode_limits() -> Limits {
+    let mut limits = Limits::default();
+    limits.max_image_width = Some(MAX_IMAGE_DIMENSION);
+    limits.max_image_height = Some(MAX_IMAGE_DIMENSION);
+    limits.max_alloc = Some(MAX_DECODE_ALLOC);
+    limits
+}
+
+fn check_dimensions(width: u32, height: u32) -> Result<(), HandlerError> {
+    if width == 0 || height == 0 {
+        return Err(HandlerError::ValidationError(
+            "Image has no pixels".to_string(),
+        ));
+    }
+    if width > MAX_IMAGE_DIMENSION || height > MAX_IMAGE_DIMENSION
+        || width as u64 * height as u64 > MAX_IMAGE_PIXELS
+    {
+        return Err(HandlerError::ValidationError(format!(
+            "Image dimensions ({}x{}) exceed the maximum of {}x{}",
+            width, height, MAX_IMAGE_DIMENSION, MAX_IMAGE_DIMENSION
+        )));
+    }
+    Ok(())
+}
+
+/// Reject data appended after the format's end marker
+fn check_trailing_data(mime_type: &str, buffer: &[u8]) -> Result<(), HandlerError> {
+    const PNG_IEND: [u8; 12] = [0, 0, 0, 0, 0x49, 0x45, 0x4E, 0x44, 0xAE, 0x42, 0x60, 0x82];
+
+    let terminated = match mime_type {
+        "image/png" => buffer.ends_with(&PNG_IEND),
+        "image/jpeg" => buffer.ends_with(&[0xFF, 0xD9]),
+        "image/gif" => buffer.ends_with(&[0x3B]),
+        // RIFF size excludes the 8-byte header; odd-sized chunks are padded
+        "image/webp" => buffer.len() >= 8 && {
+            let riff_size = u32::from_le_bytes([buffer[4], buffer[5], buffer[6], buffer[7]]) as usize;
+            riff_size.checked_add(8) == Some(buffer.len())
+        },
+        _ => true,
+    };
+
+    if terminated {
+        Ok(())
+    } else {
+        Err(HandlerError::ValidationError(
+            "Image is truncated or has unexpected data after the end of the image".to_string(),
+        ))
+    }
+}
+
+/// Decode every frame of an ICO or CUR file, so that no frame can hide
+/// corrupt or oversized data behind the one that is used
+fn check_icon_frames(buffer: &[u8]) -> Result<(), HandlerError> {
+    for frame in ico::frames(buffer)? {
+        let mut reader = ImageReader::with_format(Cursor::new(&frame[..]), ImageFormat::Ico);
+        reader.limits(decode_limits());
+        let image = reader.decode()
+            .map_err(|e| HandlerError::ValidationError(format!("ICO frame is corrupt or too large: {}", e)))?;
+        check_dimensions(image.width(), image.height())?;
+    }
+    Ok(())
+}
+
+/// Decode every GIF frame, stopping as soon as a frame or pixel budget is exceeded
+fn count_gif_frames(buffer: &[u8], width: u32, height: u32) -> Result<usize, HandlerError> {
+    let mut decoder = GifDecoder::new(Cursor::new(buffer))
+        .map_err(|e| HandlerError::ValidationError(format!("Invalid GIF: {}", e)))?;
+    decoder.set_limits(decode_limits())
+        .map_err(|e| HandlerError::ValidationError(format!("Invalid GIF: {}", e)))?;
+
+    let frame_pixels = width as u64 * height as u64;
+    let mut frame_count = 0;
+    let mut duration_ms = 0;
+    for frame in decoder.into_frames() {
+        let frame = frame.map_err(|e| HandlerError::ValidationError(format!("GIF data is corrupt or truncated: {}", e)))?;
+        frame_count += 1;
+        duration_ms += frame_delay_ms(frame.delay());
+
+        if frame_count > MAX_GIF_FRAMES {
+            return Err(HandlerError::ValidationError(format!(
+                "Animated GIF exceeds the maximum of {} frames",
+                MAX_GIF_FRAMES
+            )));
+        }
+        if frame_count as u64 * frame_pixels > MAX_GIF_TOTAL_PIXELS {
+            return Err(HandlerError::ValidationError(
+                "Animated GIF is too large to process".to_string(),
+            ));
+        }
+        if frame_count > 1 && duration_ms > MAX_GIF_DURATION_MS {
+            return Err(HandlerError::ValidationError(format!(
+                "Animated GIF exceeds the maximum duration of {} seconds",
+                MAX_GIF_DURATION_MS / 1000
+            )));
         }
     }
 
-    Err(HandlerError::ValidationError(
-        "Only image files are allowed (PNG, JPEG, GIF, SVG)".to_string(),
-    ))
+    if frame_count == 0 {
+        return Err(HandlerError::ValidationError("GIF contains no frames".to_string()));
+    }
+    Ok(frame_count)
+}
+
+fn validate_svg_content(buffer: &[u8]) -> Result<ImageInfo, HandlerError> {
+    if buffer.len() > MAX_SVG_BYTES {
+        return Err(HandlerError::ValidationError(format!(
+            "SVG exceeds the maximum size of {} KB",
+            MAX_SVG_BYTES / 1024
+        )));
+    }
+    // Count start tags before parsing, so huge documents are never built
+    let start_tags = memchr::memchr_iter(b'<', buffer)
+        .filter(|&i| !matches!(buffer.get(i + 1), Some(b'/' | b'!' | b'?')))
+        .count();
+    if start_tags > MAX_SVG_ELEMENTS {
+        return Err(too_many_svg_elements());
+    }
+
+    let tree = usvg::Tree::from_data(buffer, &usvg::Options::default())
+        .map_err(|e| HandlerError::ValidationError(format!("Invalid SVG: {}", e)))?;
+    if count_svg_nodes(tree.root()) > MAX_SVG_ELEMENTS {
+        return Err(too_many_svg_elements());
+    }
+
+    // SVGs are rasterized at a fixed size, so their intrinsic size is not limited
+    let size = tree.size();
+    let width = size.width().ceil() as u32;
+    let height = size.height().ceil() as u32;
+
+    Ok(ImageInfo {
+        mime_type: "image/svg+xml".to_string(),
+        width,
+        height,
+        color_type: "Vector".to_string(),
+        frame_count: 1,
+    })
+}
+
+/// Nodes in a parsed SVG, where each `<use>` has been replaced by a copy of
+/// what it references
+fn count_svg_nodes(group: &usvg::Group) -> usize {
+    group.children().iter().map(|node| match node {
+        usvg::Node::Group(group) => 1 + count_svg_nodes(group),
+        _ => 1,
+    }).sum()
+}
+
+fn too_many_svg_elements() -> HandlerError {
+    HandlerError::ValidationError(format!("SVG exceeds the maximum of {} elements", MAX_SVG_ELEMENTS))
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::models::CropRect;
 
     #[test]
     fn test_validate_domain() {
@@ -124,6 +860,54 @@ mod tests {
         assert!(validate_domain("example..com").is_err());
     }
 
+    #[test]
+    fn test_validate_internationalized_domain() {
+        assert_eq!(validate_domain("Example.COM").unwrap(), "example.com");
+        assert_eq!(validate_domain("münchen.de").unwrap(), "xn--mnchen-3ya.de");
+        assert_eq!(validate_domain("MÜNCHEN.DE").unwrap(), "xn--mnchen-3ya.de");
+        assert_eq!(validate_domain("xn--mnchen-3ya.de").unwrap(), "xn--mnchen-3ya.de");
+        assert_eq!(validate_domain("例え.jp").unwrap(), "xn--r8jz45g.jp");
+        // Ideographic full stop is a label separator
+        assert_eq!(validate_domain("例え。jp").unwrap(), "xn--r8jz45g.jp");
+
+        assert!(validate_domain("exa mple.com").is_err());
+        assert!(validate_domain("-example.com").is_err());
+        assert!(validate_domain("xn--zz.com").is_err());
+
+        assert_eq!(display_domain("xn--mnchen-3ya.de"), "münchen.de");
+        assert_eq!(display_domain("example.com"), "example.com");
+    }
+
+    #[test]
+    fn test_validate_domain_lengths() {
+        let label = "a".repeat(63);
+        assert!(validate_domain(&format!("{}.com", label)).is_ok());
+        assert!(validate_domain(&format!("a{}.com", label)).is_err());
+
+        // 63 + 1 + 63 + 1 + 63 + 1 + 61 = 253 octets
+        let longest = format!("{0}.{0}.{0}.{1}", label, "b".repeat(61));
+        assert_eq!(longest.len(), 253);
+        assert!(validate_domain(&longest).is_ok());
+        assert!(validate_domain(&format!("{}b", longest)).is_err());
+
+        // Each "ü" is 2 bytes of input, but the limit applies to the A-label
+        let unicode = format!("{}.de", "ü".repeat(40));
+        let ascii = validate_domain(&unicode).unwrap();
+        assert!(ascii.starts_with("xn--") && ascii.len() <= MAX_DOMAIN_LENGTH);
+        assert!(validate_domain(&format!("{}.de", "ü".repeat(70))).is_err());
+    }
+
+    #[test]
+    fn test_public_suffixes() {
+        let list: List = "// ===BEGIN ICANN DOMAINS===\ncom\nuk\nco.uk\n*.ck\n!www.ck\n// ===END ICANN DOMAINS===\n"
+            .parse()
+            .unwrap();
+        assert!(check_not_public_suffix("example.co.uk", &list).is_ok());
+        assert!(check_not_public_suffix("co.uk", &list).is_err());
+        assert!(check_not_public_suffix("foo.ck", &list).is_err());
+        assert!(check_not_public_suffix("www.ck", &list).is_ok());
+    }
+
     #[test]
     fn test_validate_metadata() {
         assert!(validate_metadata("short").is_ok());
@@ -131,11 +915,299 @@ mod tests {
         assert!(validate_metadata(&"a".repeat(257)).is_err());
     }
 
+    #[test]
+    fn test_validate_color() {
+        assert_eq!(validate_color("#FFF").unwrap(), "#ffffff");
+        assert_eq!(validate_color("#1a2B3c").unwrap(), "#1a2b3c");
+        assert!(validate_color("fff").is_err());
+        assert!(validate_color("#ffff").is_err());
+        assert!(validate_color("#ggg").is_err());
+        assert!(validate_color("red").is_err());
+    }
+
+    #[test]
+    fn test_validate_generation_options() {
+        let options = parse_generation_options(
+            r##"{"background": "#ABC", "maskablePadding": 15, "cornerRadius": 20, "fit": "crop",
+                "crop": {"x": 10, "y": 0, "width": 100, "height": 80}}"##,
+        )
+        .unwrap();
+        assert_eq!(options.background.as_deref(), Some("#aabbcc"));
+        assert_eq!(options.fit, FitMode::Crop);
+        assert_eq!(options.crop.unwrap().width, 100);
+
+        let options = parse_generation_options(r#"{"background": "Transparent", "fit": "contain"}"#).unwrap();
+        assert_eq!(options.background.as_deref(), Some("transparent"));
+        assert!(parse_generation_options("{}").unwrap().is_default());
+
+        let error = |json: &str| parse_generation_options(json).unwrap_err().to_string();
+        assert!(error("[]").contains("must be a JSON object"));
+        assert!(error(r#"{"background": "red"}"#).contains("Invalid background"));
+        assert!(error(r#"{"maskablePadding": 30}"#).contains("maskablePadding"));
+        assert!(error(r#"{"cornerRadius": 51}"#).contains("cornerRadius"));
+        assert!(error(r#"{"fit": "stretch"}"#).contains("unknown variant"));
+        assert!(error(r#"{"fit": "crop"}"#).contains("crop is required"));
+        assert!(error(r#"{"crop": {"x": 0, "y": 0, "width": 1, "height": 1}}"#).contains("only used"));
+        assert!(error(r#"{"fit": "crop", "crop": {"x": 0, "y": 0, "width": 0, "height": 1}}"#).contains("greater than 0"));
+        assert!(error(r#"{"padding": 10}"#).contains("unknown field"));
+
+        let options = parse_generation_options(r##"{"darkVariant": {"mode": "recolor", "color": "#FFF"}}"##).unwrap();
+        assert_eq!(options.dark_variant, Some(DarkVariant::Recolor { color: "#ffffff".to_string() }));
+        assert_eq!(parse_generation_options(r#"{"darkVariant": {"mode": "invert"}}"#).unwrap().dark_variant, Some(DarkVariant::Invert));
+        assert!(error(r#"{"darkVariant": {"mode": "recolor", "color": "white"}}"#).contains("darkVariant color"));
+        assert!(error(r#"{"darkVariant": {"mode": "sepia"}}"#).contains("unknown variant"));
+
+        let options = parse_generation_options(r##"{"maskIconColor": "#ABC"}"##).unwrap();
+        assert_eq!(options.mask_icon_color.as_deref(), Some("#aabbcc"));
+        assert!(error(r#"{"maskIconColor": "blue"}"#).contains("maskIconColor"));
+    }
+
     #[test]
     fn test_validate_file_size() {
         assert!(validate_file_size(1024).is_ok());
         assert!(validate_file_size(512 * 1024).is_ok());
         assert!(validate_file_size(512 * 1024 + 1).is_err());
     }
-}
 
+    #[test]
+    fn test_validate_direct_upload_size() {
+        assert!(validate_direct_upload_size(1024).is_ok());
+        assert!(validate_direct_upload_size(5 * 1024 * 1024).is_ok());
+        assert!(validate_direct_upload_size(5 * 1024 * 1024 + 1).is_err());
+        assert!(validate_direct_upload_size(0).is_err());
+    }
+
+    #[test]
+    fn test_validate_upload_content_type() {
+        assert!(validate_upload_content_type("image/png").is_ok());
+        assert!(validate_upload_content_type("image/svg+xml").is_ok());
+        assert!(validate_upload_content_type("application/pdf").is_err());
+        assert_eq!(validate_upload_content_type("image/avif").is_ok(), cfg!(feature = "avif"));
+    }
+
+    fn encode(image: image::DynamicImage, format: image::ImageFormat) -> Vec<u8> {
+        let mut buffer = Cursor::new(Vec::new());
+        image.write_to(&mut buffer, format).unwrap();
+        buffer.into_inner()
+    }
+
+    #[test]
+    fn test_validate_image_content_png() {
+        let png = encode(image::DynamicImage::new_rgba8(24, 16), image::ImageFormat::Png);
+        let info = validate_image_content(&png).unwrap();
+        assert_eq!(info.mime_type, "image/png");
+        assert_eq!((info.width, info.height), (24, 16));
+        assert_eq!(info.color_type, "Rgba8");
+        assert_eq!(info.frame_count, 1);
+    }
+
+    fn animated_gif(frames: usize, delay_ms: u32) -> Vec<u8> {
+        let mut gif = Vec::new();
+        {
+            let mut encoder = image::codecs::gif::GifEncoder::new(&mut gif);
+            for _ in 0..frames {
+                let frame = image::Frame::from_parts(
+                    image::RgbaImage::new(4, 4),
+                    0,
+                    0,
+                    image::Delay::from_numer_denom_ms(delay_ms, 1),
+                );
+                encoder.encode_frame(frame).unwrap();
+            }
+        }
+        gif
+    }
+
+    #[test]
+    fn test_validate_image_content_animated_gif_limits() {
+        assert_eq!(validate_image_content(&animated_gif(3, 100)).unwrap().frame_count, 3);
+
+        let too_long = validate_image_content(&animated_gif(2, 20_000)).unwrap_err().to_string();
+        assert!(too_long.contains("maximum duration"));
+        assert!(validate_image_content(&animated_gif(MAX_GIF_FRAMES + 1, 20)).is_err());
+    }
+
+    #[test]
+    fn test_validate_frame_index() {
+        let options = GenerationOptions { frame_index: Some(2), ..Default::default() };
+        assert!(validate_frame_index(Some(&options), 3).is_ok());
+        assert!(validate_frame_index(Some(&options), 2).is_err());
+        assert!(validate_frame_index(None, 1).is_ok());
+    }
+
+    #[test]
+    fn test_validate_crop() {
+        let options = GenerationOptions {
+            fit: FitMode::Crop,
+            crop: Some(CropRect { x: 10, y: 0, width: 30, height: 20 }),
+            ..Default::default()
+        };
+        assert!(validate_crop(Some(&options), 40, 20).is_ok());
+        assert!(validate_crop(Some(&options), 39, 20).is_err());
+        assert!(validate_crop(Some(&options), 40, 19).is_err());
+        assert!(validate_crop(None, 1, 1).is_ok());
+    }
+
+    #[test]
+    fn test_validate_canvas_document() {
+        let document = validate_canvas_document(&serde_json::json!({
+            "version": 1,
+            "width": 16,
+            "height": 16,
+            "palette": ["#F00"],
+            "layers": [{ "elements": [
+                { "type": "rect", "x": 0, "y": 0, "width": 8, "height": 8, "fill": 0, "stroke": "#ABC" },
+                { "type": "text", "x": 1, "y": 1, "text": "ok", "size": 7, "color": 0 },
+            ] }],
+        }))
+        .unwrap();
+        assert_eq!(document.palette, vec!["#ff0000"]);
+        assert!(matches!(
+            &document.layers[0].elements[0],
+            CanvasElement::Rect { stroke: Some(CanvasColor::Hex(hex)), .. } if hex == "#aabbcc"
+        ));
+
+        let rejected = |value: serde_json::Value| validate_canvas_document(&value).unwrap_err().to_string();
+        assert!(rejected(serde_json::json!({ "version": 2, "width": 16, "height": 16, "layers": [] }))
+            .contains("Unsupported canvas document version 2"));
+        assert!(rejected(serde_json::json!({ "version": 1, "width": 0, "height": 16, "layers": [] }))
+            .contains("width and height"));
+        assert!(rejected(serde_json::json!({ "version": 1, "width": 16, "height": 16, "layers": [
+            { "elements": [{ "type": "fill", "color": 3 }] },
+        ] }))
+        .contains("palette index 3"));
+        assert!(rejected(serde_json::json!({ "version": 1, "width": 16, "height": 16, "layers": [
+            { "elements": [{ "type": "ellipse", "cx": 8, "cy": 8, "rx": 4, "ry": 4 }] },
+        ] }))
+        .contains("fill or a stroke"));
+        assert!(rejected(serde_json::json!({ "version": 1, "width": 16, "height": 16, "layers": [
+            { "elements": [{ "type": "text", "x": 0, "y": 0, "text": "ü", "size": 7, "color": "#000" }] },
+        ] }))
+        .contains("no glyph"));
+        assert!(rejected(serde_json::json!({ "version": 1, "width": 16, "height": 16, "layers": [
+            { "elements": [{ "type": "blur" }] },
+        ] }))
+        .contains("Invalid canvas document"));
+    }
+
+    #[test]
+    fn test_validate_image_content_rejects_truncated() {
+        let png = encode(image::DynamicImage::new_rgb8(64, 64), image::ImageFormat::Png);
+        assert!(validate_image_content(&png[..png.len() / 2]).is_err());
+
+        // Magic bytes followed by garbage
+        let mut garbage = vec![0x89, 0x50, 0x4E, 0x47, 0x0D, 0x0A, 0x1A, 0x0A];
+        garbage.extend_from_slice(&[0xAB; 64]);
+        assert!(validate_image_content(&garbage).is_err());
+    }
+
+    #[test]
+    fn test_validate_image_content_rejects_polyglot() {
+        let mut png = encode(image::DynamicImage::new_rgb8(8, 8), image::ImageFormat::Png);
+        png.extend_from_slice(b"PK\x03\x04 appended archive");
+        assert!(validate_image_content(&png).is_err());
+    }
+
+    #[test]
+    fn test_validate_image_content_rejects_oversized() {
+        let png = encode(image::DynamicImage::new_luma8(MAX_IMAGE_DIMENSION + 1, 1), image::ImageFormat::Png);
+        assert!(validate_image_content(&png).is_err());
+    }
+
+    #[test]
+    fn test_validate_image_content_gif() {
+        let gif = encode(image::DynamicImage::new_rgba8(10, 10), image::ImageFormat::Gif);
+        let info = validate_image_content(&gif).unwrap();
+        assert_eq!(info.mime_type, "image/gif");
+        assert_eq!(info.frame_count, 1);
+    }
+
+    #[test]
+    fn test_validate_image_content_svg() {
+        let svg = br#"<svg xmlns="http://www.w3.org/2000/svg" width="48" height="32"></svg>"#;
+        let info = validate_image_content(svg).unwrap();
+        assert_eq!((info.width, info.height), (48, 32));
+
+        assert!(validate_image_content(b"<svg><unclosed").is_err());
+
+        let mut svg = String::from(r#"<svg xmlns="http://www.w3.org/2000/svg" width="8" height="8">"#);
+        svg.push_str(&"<rect width=\"1\" height=\"1\"/>".repeat(MAX_SVG_ELEMENTS));
+        svg.push_str("</svg>");
+        assert!(validate_image_content(svg.as_bytes()).is_err());
+
+        let mut svg = br#"<svg xmlns="http://www.w3.org/2000/svg" width="8" height="8"><!--"#.to_vec();
+        svg.resize(MAX_SVG_BYTES + 1, b' ');
+        svg.extend_from_slice(b"--></svg>");
+        assert!(validate_image_content(&svg).is_err());
+    }
+
+    #[test]
+    fn test_sniff_image_type() {
+        assert_eq!(sniff_image_type(include_bytes!("../tests/fixtures/icon.webp")), Some("image/webp"));
+        assert_eq!(sniff_image_type(include_bytes!("../tests/fixtures/icon.avif")), Some("image/avif"));
+        assert_eq!(sniff_image_type(include_bytes!("../tests/fixtures/icon.bmp")), Some("image/bmp"));
+        assert_eq!(sniff_image_type(include_bytes!("../tests/fixtures/icon.ico")), Some("image/x-icon"));
+        assert_eq!(sniff_image_type(include_bytes!("../tests/fixtures/icon.cur")), Some("image/x-icon"));
+        assert_eq!(sniff_image_type(include_bytes!("../tests/fixtures/icon.tiff")), Some("image/tiff"));
+
+        // ISO BMFF files that are not AVIF (e.g. MP4)
+        assert_eq!(sniff_image_type(b"\0\0\0\x14ftypisom\0\0\0\0mp41"), None);
+    }
+
+    #[test]
+    fn test_validate_image_type_rejects_tiff() {
+        assert!(validate_image_type(include_bytes!("../tests/fixtures/icon.tiff")).is_err());
+    }
+
+    #[test]
+    fn test_validate_image_content_webp() {
+        let webp = include_bytes!("../tests/fixtures/icon.webp");
+        let info = validate_image_content(webp).unwrap();
+        assert_eq!(info.mime_type, "image/webp");
+        assert_eq!((info.width, info.height), (24, 24));
+
+        let mut polyglot = webp.to_vec();
+        polyglot.extend_from_slice(b"trailing");
+        assert!(validate_image_content(&polyglot).is_err());
+    }
+
+    #[test]
+    fn test_validate_image_content_bmp() {
+        let info = validate_image_content(include_bytes!("../tests/fixtures/icon.bmp")).unwrap();
+        assert_eq!(info.mime_type, "image/bmp");
+        assert_eq!((info.width, info.height), (24, 24));
+    }
+
+    #[test]
+    fn test_validate_image_content_ico_uses_largest_frame() {
+        let info = validate_image_content(include_bytes!("../tests/fixtures/icon.ico")).unwrap();
+        assert_eq!(info.mime_type, "image/x-icon");
+        assert_eq!((info.width, info.height), (64, 64));
+
+        let info = validate_image_content(include_bytes!("../tests/fixtures/icon.cur")).unwrap();
+        assert_eq!((info.width, info.height), (32, 32));
+    }
+
+    #[test]
+    fn test_validate_image_content_ico_rejects_corrupt_frame() {
+        let mut ico = include_bytes!("../tests/fixtures/icon.ico").to_vec();
+        // Overwrite the data of a frame other than the 64x64 one
+        let entry = (0..3).map(|i| 6 + 16 * i).find(|&entry| ico[entry] != 64).unwrap();
+        let offset = u32::from_le_bytes(ico[entry + 12..entry + 16].try_into().unwrap()) as usize;
+        let size = u32::from_le_bytes(ico[entry + 8..entry + 12].try_into().unwrap()) as usize;
+        ico[offset..offset + size].fill(0xff);
+
+        assert!(validate_image_content(&ico).is_err());
+    }
+
+    #[test]
+    fn test_validate_image_content_avif() {
+        let result = validate_image_content(include_bytes!("../tests/fixtures/icon.avif"));
+        if cfg!(feature = "avif") {
+            let info = result.unwrap();
+            assert_eq!((info.width, info.height), (24, 24));
+        } else {
+            assert!(result.is_err());
+        }
+    }
+}
@@ -0,0 +1,175 @@
+use crate::generator::{render_icon, CANONICAL_SIZE};
+use crate::models::GenerationOptions;
+use crate::silhouette::trace_mask;
+use image::imageops::FilterType;
+use image::{DynamicImage, Rgba, RgbaImage};
+use std::collections::HashMap;
+
+/// Largest grid a source is traced on. Larger sources are sampled down to it,
+/// which is lossless for pixel art drawn on a coarser grid.
+pub const TRACE_MAX_SIZE: u32 = 128;
+
+/// Sources needing more colors than this are not traced
+pub const MAX_TRACE_COLORS: usize = 16;
+
+/// Traced SVGs larger than this are discarded in favor of the embedded PNG
+pub const MAX_TRACED_SVG_BYTES: usize = 32 * 1024;
+
+/// Largest mean per-pixel difference between the traced result and the
+/// source, as the distance between premultiplied RGBA values (0-510)
+const MAX_TRACE_ERROR: f64 = 1.5;
+
+/// Pixels at least this opaque are traced; the rest are left transparent
+const ALPHA_THRESHOLD: u8 = 128;
+
+/// Trace a prepared source (see [`crate::generator::prepare_source`]) into an
+/// SVG of flat-colored paths, one per palette color. Returns `None` when the
+/// source is not simple enough: too many colors, too far from the source once
+/// quantized and sampled onto the grid, or too large an SVG.
+pub fn trace_svg(image: &DynamicImage, options: &GenerationOptions) -> Option<Vec<u8>> {
+    let source_size = image.width();
+    let grid_size = source_size.min(TRACE_MAX_SIZE);
+    let grid = if source_size > grid_size {
+        image.resize_exact(grid_size, grid_size, FilterType::Nearest)
+    } else {
+        image.clone()
+    };
+
+    // Sizes match, so this only applies the background and corner options
+    let grid = render_icon(&grid, grid_size, options).to_rgba8();
+    let (palette, cells) = quantize(&grid);
+    if palette.len() > MAX_TRACE_COLORS {
+        return None;
+    }
+
+    let reference = render_icon(image, source_size, options).to_rgba8();
+    if trace_error(&reference, &palette, &cells, grid_size) > MAX_TRACE_ERROR {
+        return None;
+    }
+
+    let mut paths = String::new();
+    for (index, [r, g, b]) in palette.iter().enumerate() {
+        let mask: Vec<bool> = cells.iter().map(|cell| *cell == Some(index)).collect();
+        paths.push_str(&format!(
+            r##"<path fill="#{:02x}{:02x}{:02x}" fill-rule="evenodd" d="{}"/>"##,
+            r,
+            g,
+            b,
+            trace_mask(&mask, grid_size, grid_size)
+        ));
+    }
+
+    let svg = format!(
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{size}" height="{size}" viewBox="0 0 {grid} {grid}" shape-rendering="crispEdges">{paths}</svg>"#,
+        size = CANONICAL_SIZE,
+        grid = grid_size,
+    );
+    (svg.len() <= MAX_TRACED_SVG_BYTES).then(|| svg.into_bytes())
+}
+
+/// Reduce the grid to its most frequent opaque colors. Returns the palette,
+/// most frequent first, and each cell's palette index (`None` if transparent).
+/// The palette is only truncated to [`MAX_TRACE_COLORS`] plus one, which is
+/// enough to tell the caller the source has too many colors.
+fn quantize(grid: &RgbaImage) -> (Vec<[u8; 3]>, Vec<Option<usize>>) {
+    let mut counts: HashMap<[u8; 3], usize> = HashMap::new();
+    for pixel in grid.pixels().filter(|p| p.0[3] >= ALPHA_THRESHOLD) {
+        *counts.entry([pixel.0[0], pixel.0[1], pixel.0[2]]).or_default() += 1;
+    }
+
+    let mut palette: Vec<([u8; 3], usize)> = counts.into_iter().collect();
+    palette.sort_by(|(a_color, a_count), (b_color, b_count)| b_count.cmp(a_count).then(a_color.cmp(b_color)));
+    palette.truncate(MAX_TRACE_COLORS + 1);
+    let palette: Vec<[u8; 3]> = palette.into_iter().map(|(color, _)| color).collect();
+
+    let cells = grid
+        .pixels()
+        .map(|p| {
+            (p.0[3] >= ALPHA_THRESHOLD).then(|| {
+                let [r, g, b, _] = p.0;
+                (0..palette.len())
+                    .min_by_key(|&i| {
+                        let [pr, pg, pb] = palette[i];
+                        (r as i32 - pr as i32).pow(2) + (g as i32 - pg as i32).pow(2) + (b as i32 - pb as i32).pow(2)
+                    })
+                    .unwrap_or(0)
+            })
+        })
+        .collect();
+    (palette, cells)
+}
+
+/// Mean difference between the full-size reference and the traced grid
+/// scaled back up to it
+fn trace_error(reference: &RgbaImage, palette: &[[u8; 3]], cells: &[Option<usize>], grid_size: u32) -> f64 {
+    let size = reference.width();
+    let total: f64 = reference
+        .enumerate_pixels()
+        .map(|(x, y, pixel)| {
+            let cell = (y * grid_size / size * grid_size + x * grid_size / size) as usize;
+            let traced = match cells[cell] {
+                Some(index) => {
+                    let [r, g, b] = palette[index];
+                    Rgba([r, g, b, 255])
+                }
+                None => Rgba([0, 0, 0, 0]),
+            };
+            premultiplied_distance(pixel, &traced)
+        })
+        .sum();
+    total / (size as f64 * size as f64)
+}
+
+fn premultiplied_distance(a: &Rgba<u8>, b: &Rgba<u8>) -> f64 {
+    let premultiply = |p: &Rgba<u8>| {
+        let alpha = p.0[3] as f64 / 255.0;
+        [p.0[0] as f64 * alpha, p.0[1] as f64 * alpha, p.0[2] as f64 * alpha, p.0[3] as f64]
+    };
+    let (a, b) = (premultiply(a), premultiply(b));
+    a.iter().zip(b.iter()).map(|(x, y)| (x - y).powi(2)).sum::<f64>().sqrt()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 8x8 pixel art of a red square with a blue center on transparency,
+    /// scaled up by `scale` with nearest neighbour
+    fn pixel_art(scale: u32) -> DynamicImage {
+        let art = RgbaImage::from_fn(8, 8, |x, y| match (x, y) {
+            (3..=4, 3..=4) => Rgba([0, 0, 255, 255]),
+            (1..=6, 1..=6) => Rgba([255, 0, 0, 255]),
+            _ => Rgba([0, 0, 0, 0]),
+        });
+        DynamicImage::ImageRgba8(art).resize_exact(8 * scale, 8 * scale, FilterType::Nearest)
+    }
+
+    #[test]
+    fn test_trace_pixel_art() {
+        let svg = String::from_utf8(trace_svg(&pixel_art(1), &GenerationOptions::default()).unwrap()).unwrap();
+        assert!(svg.contains(r#"viewBox="0 0 8 8""#));
+        // The blue center is a hole in the red path
+        assert!(svg.contains(r##"<path fill="#ff0000" fill-rule="evenodd" d="M1 1H7V7H1ZM3 3V5H5V3Z"/>"##));
+        assert!(svg.contains(r##"<path fill="#0000ff" fill-rule="evenodd" d="M3 3H5V5H3Z"/>"##));
+        assert_eq!(svg.matches("<path").count(), 2);
+    }
+
+    #[test]
+    fn test_trace_upscaled_pixel_art_on_coarse_grid() {
+        // 512px export of the 8x8 drawing traces on the 128 grid without error
+        let svg = String::from_utf8(trace_svg(&pixel_art(64), &GenerationOptions::default()).unwrap()).unwrap();
+        assert!(svg.contains(r#"viewBox="0 0 128 128""#));
+        assert!(svg.contains(r##"<path fill="#0000ff" fill-rule="evenodd" d="M48 48H80V80H48Z"/>"##));
+    }
+
+    #[test]
+    fn test_trace_rejects_complex_sources() {
+        // A gradient needs far more than MAX_TRACE_COLORS colors
+        let gradient = RgbaImage::from_fn(64, 64, |x, y| Rgba([(x * 4) as u8, (y * 4) as u8, 128, 255]));
+        assert!(trace_svg(&DynamicImage::ImageRgba8(gradient), &GenerationOptions::default()).is_none());
+
+        // Few colors, but soft edges lose too much when thresholded
+        let soft = RgbaImage::from_fn(64, 64, |x, _| Rgba([255, 0, 0, ((x % 8) * 32) as u8]));
+        assert!(trace_svg(&DynamicImage::ImageRgba8(soft), &GenerationOptions::default()).is_none());
+    }
+}